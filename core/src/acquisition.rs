@@ -0,0 +1,287 @@
+//! The acquisition of the frontend readings, decoupled from their processing.
+//!
+//! The producer, fed by the data ready interrupt, pushes every reading with its sequence number and timestamp into
+//! a lock-free single-producer single-consumer [`ring_buffer`], while the consumer pops them at its own pace.
+//! Every sample that is not delivered, because the reading overlapped with the next one, the producer was late or
+//! the buffer was full, shows up as a gap in the sequence numbers and is counted by the [`SequenceTracker`].
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::protocol::RawData;
+
+/// A reading of the frontend, as it has been acquired.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AcquiredSample {
+    /// The number of the data ready interrupt that triggered the reading, wrapping around.
+    pub sequence: u32,
+    /// The time of the data ready interrupt, in milliseconds.
+    pub timestamp: u128,
+    pub raw_data: RawData,
+}
+
+struct RingBuffer<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // The total number of pushed and popped values, wrapping around. The slot of a value is its count modulo N.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    overflows: AtomicU32,
+}
+
+// The slots are only written by the producer before publishing them with `head`, and only read by the consumer
+// before releasing them with `tail`.
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+/// The producer side of a ring buffer.
+pub struct Producer<T, const N: usize> {
+    buffer: Arc<RingBuffer<T, N>>,
+}
+
+/// The consumer side of a ring buffer.
+pub struct Consumer<T, const N: usize> {
+    buffer: Arc<RingBuffer<T, N>>,
+}
+
+/// Creates a lock-free ring buffer with capacity `N`, split into its only producer and its only consumer.
+pub fn ring_buffer<T: Copy, const N: usize>() -> (Producer<T, N>, Consumer<T, N>) {
+    let buffer = Arc::new(RingBuffer {
+        slots: [(); N].map(|_| UnsafeCell::new(MaybeUninit::uninit())),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        overflows: AtomicU32::new(0),
+    });
+
+    (
+        Producer {
+            buffer: buffer.clone(),
+        },
+        Consumer { buffer },
+    )
+}
+
+impl<T: Copy, const N: usize> Producer<T, N> {
+    /// Pushes a value into the buffer. If the buffer is full, the value is dropped, counted as an overflow,
+    /// and `false` is returned.
+    pub fn push(&mut self, value: T) -> bool {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        let tail = self.buffer.tail.load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) >= N {
+            self.buffer.overflows.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        unsafe {
+            (*self.buffer.slots[head % N].get()).write(value);
+        }
+        self.buffer
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+
+        true
+    }
+
+    /// Gets the number of values dropped because the buffer was full.
+    pub fn overflows(&self) -> u32 {
+        self.buffer.overflows.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Copy, const N: usize> Consumer<T, N> {
+    /// Pops the oldest value from the buffer, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        let head = self.buffer.head.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { (*self.buffer.slots[tail % N].get()).assume_init() };
+        self.buffer
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+
+        Some(value)
+    }
+
+    /// Gets the number of values waiting in the buffer.
+    pub fn len(&self) -> usize {
+        let head = self.buffer.head.load(Ordering::Acquire);
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+
+        head.wrapping_sub(tail)
+    }
+
+    /// Checks if there are no values waiting in the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of values dropped because the buffer was full.
+    pub fn overflows(&self) -> u32 {
+        self.buffer.overflows.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the samples that have not been delivered to the consumer, from the gaps in their sequence numbers.
+#[derive(Debug, Default, Clone)]
+pub struct SequenceTracker {
+    last_sequence: Option<u32>,
+    received: u32,
+    dropped: u32,
+}
+
+impl SequenceTracker {
+    /// Creates a new `SequenceTracker`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks a received sequence number and returns the number of samples dropped since the previous one.
+    /// A sequence number that is not newer than the previous one is not counted as a gap.
+    pub fn track(&mut self, sequence: u32) -> u32 {
+        let dropped = match self.last_sequence {
+            Some(last_sequence) => {
+                let gap = sequence.wrapping_sub(last_sequence);
+                // A gap larger than half of the range means that the sequence went backwards.
+                if gap > 0 && gap < u32::MAX / 2 {
+                    gap - 1
+                } else {
+                    0
+                }
+            }
+            None => 0,
+        };
+
+        self.last_sequence = Some(sequence);
+        self.received = self.received.wrapping_add(1);
+        self.dropped = self.dropped.wrapping_add(dropped);
+
+        dropped
+    }
+
+    /// Gets the number of received samples.
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+
+    /// Gets the number of dropped samples.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn values_are_popped_in_order() {
+        let (mut producer, mut consumer) = ring_buffer::<u32, 4>();
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.pop(), None);
+
+        // The values wrap around the slots several times.
+        for round in 0..3 {
+            for value in 0..3 {
+                assert!(producer.push(round * 10 + value));
+            }
+            assert_eq!(consumer.len(), 3);
+            for value in 0..3 {
+                assert_eq!(consumer.pop(), Some(round * 10 + value));
+            }
+            assert!(consumer.is_empty());
+        }
+    }
+
+    #[test]
+    fn values_are_dropped_when_full() {
+        let (mut producer, mut consumer) = ring_buffer::<u32, 4>();
+        for value in 0..4 {
+            assert!(producer.push(value));
+        }
+        assert!(!producer.push(4));
+        assert!(!producer.push(5));
+        assert_eq!(producer.overflows(), 2);
+        assert_eq!(consumer.overflows(), 2);
+        assert_eq!(consumer.len(), 4);
+
+        // The oldest values are kept, and a slot is free again once one has been popped.
+        assert_eq!(consumer.pop(), Some(0));
+        assert!(producer.push(6));
+        assert_eq!(
+            std::iter::from_fn(|| consumer.pop()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 6]
+        );
+    }
+
+    #[test]
+    fn gaps_are_counted_across_the_sequence_wrap() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.track(u32::MAX - 2), 0);
+        assert_eq!(tracker.track(u32::MAX - 1), 0);
+        // The sequence numbers u32::MAX and 0 are missing.
+        assert_eq!(tracker.track(1), 2);
+        assert_eq!(tracker.track(2), 0);
+        // A sequence number that went backwards is not a gap.
+        assert_eq!(tracker.track(1), 0);
+        assert_eq!(tracker.received(), 5);
+        assert_eq!(tracker.dropped(), 2);
+    }
+
+    #[test]
+    fn concurrent_producer_and_consumer() {
+        const COUNT: u32 = 100_000;
+        let (mut producer, mut consumer) = ring_buffer::<AcquiredSample, 16>();
+
+        let producer_thread = thread::spawn(move || {
+            let mut delivered = 0;
+            for sequence in 0..COUNT {
+                if producer.push(AcquiredSample {
+                    sequence,
+                    timestamp: sequence as u128,
+                    ..Default::default()
+                }) {
+                    delivered += 1;
+                }
+            }
+            delivered
+        });
+
+        let mut tracker = SequenceTracker::new();
+        let mut last_sequence = None;
+        let mut receive = |sample: AcquiredSample| {
+            assert!(last_sequence.is_none_or(|last_sequence| sample.sequence > last_sequence));
+            assert_eq!(sample.timestamp, sample.sequence as u128);
+            tracker.track(sample.sequence);
+            last_sequence = Some(sample.sequence);
+        };
+        while !producer_thread.is_finished() {
+            match consumer.pop() {
+                Some(sample) => receive(sample),
+                None => thread::yield_now(),
+            }
+        }
+        let delivered = producer_thread.join().unwrap();
+        while let Some(sample) = consumer.pop() {
+            receive(sample);
+        }
+
+        assert_eq!(tracker.received(), delivered);
+        assert_eq!(delivered + consumer.overflows(), COUNT);
+        // The samples that were not delivered are either dropped in the middle or after the last one received.
+        assert_eq!(
+            tracker.dropped() + (COUNT - 1 - last_sequence.unwrap()),
+            consumer.overflows()
+        );
+    }
+}
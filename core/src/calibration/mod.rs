@@ -1,12 +1,19 @@
 pub mod offset_measuring;
 
+use std::sync::{Arc, Mutex};
+
 use uom::si::{
     electric_current::{microampere, milliampere},
     electric_potential::millivolt,
     f32::{ElectricCurrent, ElectricPotential, ElectricalResistance},
 };
 
-pub(crate) struct Calibrator {
+use crate::{
+    error::FrontendError,
+    frontend::{with_frontend, OpticalFrontend},
+};
+
+pub struct Calibrator {
     // Afe4404 values.
     led_current_min: ElectricCurrent,
     led_current_max: ElectricCurrent,
@@ -14,7 +21,7 @@ pub(crate) struct Calibrator {
     offset_current_max: ElectricCurrent,
 
     // Cached Afe4404 values.
-    pub(crate) offset_current: ElectricCurrent,
+    pub offset_current: ElectricCurrent,
 
     // Dc calibration.
     alpha: f32, // The skin reflectance parameter (alpha = i_led / i_photodiode).
@@ -23,32 +30,32 @@ pub(crate) struct Calibrator {
     adc_working_threshold: ElectricPotential, // Around the adc_set_point.
 
     // Frontend functions.
-    get_led_current: Box<dyn Fn() -> ElectricCurrent>,
-    set_led_current: Box<dyn Fn(ElectricCurrent) -> ElectricCurrent>,
-    get_offset_current: Box<dyn Fn() -> ElectricCurrent>,
-    set_offset_current: Box<dyn Fn(ElectricCurrent) -> ElectricCurrent>,
-    get_resistor: Box<dyn Fn() -> ElectricalResistance>,
+    get_led_current: Box<dyn Fn() -> Result<ElectricCurrent, FrontendError>>,
+    set_led_current: Box<dyn Fn(ElectricCurrent) -> Result<ElectricCurrent, FrontendError>>,
+    get_offset_current: Box<dyn Fn() -> Result<ElectricCurrent, FrontendError>>,
+    set_offset_current: Box<dyn Fn(ElectricCurrent) -> Result<ElectricCurrent, FrontendError>>,
+    get_resistor: Box<dyn Fn() -> Result<ElectricalResistance, FrontendError>>,
 }
 
 unsafe impl Send for Calibrator {}
 unsafe impl Sync for Calibrator {}
 
 impl Calibrator {
-    /// Creates a new `Calibrator`.
-    pub(crate) fn new<GLC, SLC, GOC, SOC, GR>(
+    /// Creates a new `Calibrator`, setting the minimum LED and offset currents on the frontend.
+    pub fn new<GLC, SLC, GOC, SOC, GR>(
         alpha: f32,
         get_led_current: GLC,
         set_led_current: SLC,
         get_offset_current: GOC,
         set_offset_current: SOC,
         get_resistor: GR,
-    ) -> Self
+    ) -> Result<Self, FrontendError>
     where
-        GLC: Fn() -> ElectricCurrent + 'static,
-        SLC: Fn(ElectricCurrent) -> ElectricCurrent + 'static,
-        GOC: Fn() -> ElectricCurrent + 'static,
-        SOC: Fn(ElectricCurrent) -> ElectricCurrent + 'static,
-        GR: Fn() -> ElectricalResistance + 'static,
+        GLC: Fn() -> Result<ElectricCurrent, FrontendError> + 'static,
+        SLC: Fn(ElectricCurrent) -> Result<ElectricCurrent, FrontendError> + 'static,
+        GOC: Fn() -> Result<ElectricCurrent, FrontendError> + 'static,
+        SOC: Fn(ElectricCurrent) -> Result<ElectricCurrent, FrontendError> + 'static,
+        GR: Fn() -> Result<ElectricalResistance, FrontendError> + 'static,
     {
         let calibrator = Calibrator {
             // TODO: Change to optimal initial value.
@@ -68,118 +75,119 @@ impl Calibrator {
             get_resistor: Box::new(get_resistor),
         };
 
-        (calibrator.set_led_current)(calibrator.led_current_min);
-        (calibrator.set_offset_current)(calibrator.offset_current_min);
+        (calibrator.set_led_current)(calibrator.led_current_min)?;
+        (calibrator.set_offset_current)(calibrator.offset_current_min)?;
 
-        calibrator
+        Ok(calibrator)
     }
 
     /// Gets an immutable reference of the minimum led current.
-    pub(crate) fn led_current_min(&self) -> &ElectricCurrent {
+    pub fn led_current_min(&self) -> &ElectricCurrent {
         &self.led_current_min
     }
 
     /// Gets an immutable reference of the maximum led current.
-    pub(crate) fn led_current_max(&self) -> &ElectricCurrent {
+    pub fn led_current_max(&self) -> &ElectricCurrent {
         &self.led_current_max
     }
 
     /// Gets an immutable reference of the minimum offset current.
-    pub(crate) fn offset_current_min(&self) -> &ElectricCurrent {
+    pub fn offset_current_min(&self) -> &ElectricCurrent {
         &self.offset_current_min
     }
 
     /// Gets an immutable reference of the maximum offset current.
-    pub(crate) fn offset_current_max(&self) -> &ElectricCurrent {
+    pub fn offset_current_max(&self) -> &ElectricCurrent {
         &self.offset_current_max
     }
 
     /// Gets an immutable reference of the skin reflectance parameter alpha.
-    pub(crate) fn alpha(&self) -> &f32 {
+    pub fn alpha(&self) -> &f32 {
         &self.alpha
     }
 
     /// Gets an immutable reference of the offset current set point.
-    pub(crate) fn offset_current_set_point(&self) -> &ElectricCurrent {
+    pub fn offset_current_set_point(&self) -> &ElectricCurrent {
         &self.offset_current_set_point
     }
 
     /// Gets an immutable reference of the adc set point.
-    pub(crate) fn adc_set_point(&self) -> &ElectricPotential {
+    pub fn adc_set_point(&self) -> &ElectricPotential {
         &self.adc_set_point
     }
 
     /// Gets an immutable reference of the adc working threshold.
-    pub(crate) fn adc_working_threshold(&self) -> &ElectricPotential {
+    pub fn adc_working_threshold(&self) -> &ElectricPotential {
         &self.adc_working_threshold
     }
 
     /// Gets a mutable reference of the minimum led current.
-    pub(crate) fn led_current_min_mut(&mut self) -> &mut ElectricCurrent {
+    pub fn led_current_min_mut(&mut self) -> &mut ElectricCurrent {
         &mut self.led_current_min
     }
 
     /// Gets a mutable reference of the maximum led current.
-    pub(crate) fn led_current_max_mut(&mut self) -> &mut ElectricCurrent {
+    pub fn led_current_max_mut(&mut self) -> &mut ElectricCurrent {
         &mut self.led_current_max
     }
 
     /// Gets a mutable reference of the minimum offset current.
-    pub(crate) fn offset_current_min_mut(&mut self) -> &mut ElectricCurrent {
+    pub fn offset_current_min_mut(&mut self) -> &mut ElectricCurrent {
         &mut self.offset_current_min
     }
 
     /// Gets a mutable reference of the maximum offset current.
-    pub(crate) fn offset_current_max_mut(&mut self) -> &mut ElectricCurrent {
+    pub fn offset_current_max_mut(&mut self) -> &mut ElectricCurrent {
         &mut self.offset_current_max
     }
 
     /// Gets a mutable reference of the skin reflectance parameter alpha.
-    pub(crate) fn alpha_mut(&mut self) -> &mut f32 {
+    pub fn alpha_mut(&mut self) -> &mut f32 {
         &mut self.alpha
     }
 
     /// Gets a mutable reference of the offset current set point.
-    pub(crate) fn offset_current_set_point_mut(&mut self) -> &mut ElectricCurrent {
+    pub fn offset_current_set_point_mut(&mut self) -> &mut ElectricCurrent {
         &mut self.offset_current_set_point
     }
 
     /// Gets a mutable reference of the adc set point.
-    pub(crate) fn adc_set_point_mut(&mut self) -> &mut ElectricPotential {
+    pub fn adc_set_point_mut(&mut self) -> &mut ElectricPotential {
         &mut self.adc_set_point
     }
 
     /// Gets a mutable reference of the adc working threshold.
-    pub(crate) fn adc_working_threshold_mut(&mut self) -> &mut ElectricPotential {
+    pub fn adc_working_threshold_mut(&mut self) -> &mut ElectricPotential {
         &mut self.adc_working_threshold
     }
 
     /// Calibrates the DC component of the signal by changing the LED current and the offset current.
     /// The calibration is firstly performed on the LED current for larger changes, then on the offset current for better accuracy.
     /// Returns true if the calibration was performed, false otherwise.
-    pub(crate) fn calibrate_dc(&mut self, sample: ElectricPotential) -> bool {
+    pub fn calibrate_dc(&mut self, sample: ElectricPotential) -> Result<bool, FrontendError> {
         // Calibrate only if the sample is out of the working threshold.
         if sample < self.adc_set_point - self.adc_working_threshold
             || sample > self.adc_set_point + self.adc_working_threshold
         {
             // Get the led current and the offset current from the frontend.
-            let mut led_current = (self.get_led_current)();
-            let offset_current = (self.get_offset_current)();
+            let mut led_current = (self.get_led_current)()?;
+            let offset_current = (self.get_offset_current)()?;
 
             // The error between the set point and the sample converted in the current seen by the photodiode.
-            let error = (self.adc_set_point - sample) / (2.0 * (self.get_resistor)());
+            let error = (self.adc_set_point - sample) / (2.0 * (self.get_resistor)()?);
 
             // Calculate the requested led current.
             let requested_led_current =
                 led_current + self.alpha * (error - self.offset_current_set_point + offset_current);
 
-            led_current = (self.set_led_current)(if requested_led_current < self.led_current_min {
-                self.led_current_min
-            } else if requested_led_current > self.led_current_max {
-                self.led_current_max
-            } else {
-                requested_led_current
-            });
+            led_current =
+                (self.set_led_current)(if requested_led_current < self.led_current_min {
+                    self.led_current_min
+                } else if requested_led_current > self.led_current_max {
+                    self.led_current_max
+                } else {
+                    requested_led_current
+                })?;
 
             // Calculate the requested offset current.
             let requested_offset_current =
@@ -194,21 +202,64 @@ impl Calibrator {
                     self.offset_current_max
                 } else {
                     requested_offset_current
-                });
+                })?;
 
             log::info!("Calibrated DC: {}", led_current.value);
 
-            true
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
 
-    /// Resets the calibrator by reading the LED current and setting it back.
-    /// It is useful when the calibrator has been changed from external functions.
-    pub fn reset(&mut self) {
-        let led_current = (self.get_led_current)();
-        (self.set_led_current)(led_current);
+    /// Resets the calibrator by reading the LED current and setting it back, and by reading the offset current.
+    /// It is useful when the calibrator or the frontend have been changed from external functions.
+    pub fn reset(&mut self) -> Result<(), FrontendError> {
+        let led_current = (self.get_led_current)()?;
+        (self.set_led_current)(led_current)?;
+        self.offset_current = (self.get_offset_current)()?;
+
+        Ok(())
     }
+}
 
+/// Creates the LED1 and the LED2-LED3 calibrators, acting on the given frontend.
+pub fn calibrators<F>(
+    frontend: &'static Arc<Mutex<Option<F>>>,
+) -> Result<(Calibrator, Calibrator), FrontendError>
+where
+    F: OpticalFrontend + 'static,
+{
+    let calibrator_led1 = Calibrator::new(
+        12000.0,
+        move || with_frontend(frontend, |frontend| frontend.get_led1_current()),
+        move |current| with_frontend(frontend, |frontend| frontend.set_led1_current(current)),
+        move || with_frontend(frontend, |frontend| frontend.get_offset_led1_current()),
+        move |current| {
+            with_frontend(frontend, |frontend| {
+                frontend.set_offset_led1_current(current)
+            })
+        },
+        move || with_frontend(frontend, |frontend| frontend.get_tia_resistor1()),
+    )?;
+    let calibrator_led2_led3 = Calibrator::new(
+        350.0,
+        move || with_frontend(frontend, |frontend| frontend.get_led3_current()),
+        move |current| {
+            with_frontend(frontend, |frontend| {
+                frontend.set_led2_current(current)?;
+                frontend.set_led3_current(current)
+            })
+        },
+        move || with_frontend(frontend, |frontend| frontend.get_offset_led3_current()),
+        move |current| {
+            with_frontend(frontend, |frontend| {
+                frontend.set_offset_led2_current(current)?;
+                frontend.set_offset_led3_current(current)
+            })
+        },
+        move || with_frontend(frontend, |frontend| frontend.get_tia_resistor2()),
+    )?;
+
+    Ok((calibrator_led1, calibrator_led2_led3))
 }
@@ -0,0 +1,74 @@
+// Due to the fact that the offset cancellation DAC can have device-to-device variations,
+// the offset currents can be measured from the current device.
+
+use afe4404::system::State;
+use uom::si::{
+    electric_current::microampere,
+    electrical_resistance::ohm,
+    f32::{ElectricCurrent, ElectricalResistance},
+};
+
+use crate::frontend::OpticalFrontend;
+
+pub struct OffsetCurrents {
+    currents: [ElectricCurrent; 31],
+}
+
+impl OffsetCurrents {
+    pub fn new() -> Self {
+        let mut currents: [ElectricCurrent; 31] = Default::default();
+        for (i, current) in currents.iter_mut().enumerate() {
+            *current = ElectricCurrent::new::<microampere>(7.0 / 15.0 * i as f32 - 7.0);
+        }
+
+        Self { currents }
+    }
+
+    /// Creates the offset currents from previously measured values, ordered from -7 µA to 7 µA.
+    pub fn from_currents(currents: [ElectricCurrent; 31]) -> Self {
+        Self { currents }
+    }
+
+    /// Returns the measured offset currents, ordered from -7 µA to 7 µA.
+    pub fn currents(&self) -> &[ElectricCurrent; 31] {
+        &self.currents
+    }
+
+    pub fn accurate(&mut self, offset: ElectricCurrent) -> ElectricCurrent {
+        let i = ((offset.get::<microampere>() + 7.0) / 7.0 * 15.0) as usize;
+        self.currents[i]
+    }
+
+    /// Measures the offset currents with the photodiode disconnected.
+    /// On error, the photodiode may be left disconnected and the frontend should be configured again.
+    pub fn measure<F: OpticalFrontend>(&mut self, frontend: &mut F) -> Result<(), F::Error> {
+        // Disconnect the photodiode.
+        frontend.set_photodiode(State::Disabled)?;
+        std::thread::sleep(std::time::Duration::from_millis(60));
+
+        // Measure offset currents.
+        for (i, accurate_current) in self.currents.iter_mut().enumerate() {
+            frontend.set_offset_led3_current(ElectricCurrent::new::<microampere>(
+                7.0 / 15.0 * i as f32 - 7.0,
+            ))?;
+            std::thread::sleep(std::time::Duration::from_millis(60));
+
+            let voltage = frontend.read()?.led3;
+            let current = voltage / (2.0 * ElectricalResistance::new::<ohm>(crate::RESISTOR2));
+
+            *accurate_current = current;
+            log::info!("Offset current: {}", current.get::<microampere>());
+        }
+
+        // Reconnect the photodiode.
+        frontend.set_photodiode(State::Enabled)?;
+
+        Ok(())
+    }
+}
+
+impl Default for OffsetCurrents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// A monotonic time source, used by the timers and the peak detection.
+pub trait Clock {
+    /// Returns the milliseconds elapsed since the origin of the clock.
+    fn now(&self) -> u128;
+}
+
+/// The system monotonic clock, counting from its creation.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    /// Creates a new `MonotonicClock` starting from now.
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> u128 {
+        self.origin.elapsed().as_millis()
+    }
+}
+
+/// A clock that only moves when it is set, e.g. to the timestamps of a recording.
+/// All the clones of a `ManualClock` share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    milliseconds: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Creates a new `ManualClock` starting from zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time, in milliseconds.
+    pub fn set(&self, milliseconds: u128) {
+        self.milliseconds
+            .store(milliseconds as u64, Ordering::Relaxed);
+    }
+
+    /// Moves the time forward by the given milliseconds.
+    pub fn advance(&self, milliseconds: u128) {
+        self.milliseconds
+            .fetch_add(milliseconds as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u128 {
+        self.milliseconds.load(Ordering::Relaxed) as u128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_when_set() {
+        let clock = ManualClock::new();
+        assert_eq!(clock.now(), 0);
+
+        clock.set(1500);
+        assert_eq!(clock.now(), 1500);
+        clock.advance(30);
+        assert_eq!(clock.now(), 1530);
+        // The time can also be set back, e.g. when a new recording starts.
+        clock.set(10);
+        assert_eq!(clock.now(), 10);
+    }
+
+    #[test]
+    fn manual_clock_clones_share_the_time() {
+        let clock = ManualClock::new();
+        let clone = clock.clone();
+
+        clone.advance(250);
+        assert_eq!(clock.now(), 250);
+        clock.set(1000);
+        assert_eq!(clone.now(), 1000);
+    }
+
+    #[test]
+    fn monotonic_clock_does_not_go_back() {
+        let clock = MonotonicClock::new();
+        let first = clock.now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        assert!(clock.now() >= first + 5);
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt::{self, Display, Formatter};
+
+/// An error that occurred while driving the optical frontend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrontendError {
+    /// The frontend has not been initialised.
+    NotInitialised,
+    /// The frontend mutex has been poisoned by a panicking thread.
+    Poisoned,
+    /// The frontend returned an error, usually caused by the communication bus.
+    Device(String),
+}
+
+impl Display for FrontendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendError::NotInitialised => write!(f, "frontend not initialised"),
+            FrontendError::Poisoned => write!(f, "frontend mutex poisoned"),
+            FrontendError::Device(error) => write!(f, "frontend device error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for FrontendError {}
@@ -0,0 +1,173 @@
+use afe4404::{
+    clock::ClockConfiguration, device::AFE4404, led_current::LedCurrentConfiguration,
+    measurement_window::MeasurementWindowConfiguration, modes::ThreeLedsMode, system::State,
+};
+use embedded_hal::i2c::{I2c, SevenBitAddress};
+use uom::si::f32::{Capacitance, ElectricCurrent, ElectricalResistance};
+
+use super::OpticalFrontend;
+use crate::protocol::RawData;
+
+/// An error returned by the AFE4404 driver.
+/// The driver does not export its error type, so only its description is kept.
+#[derive(Debug)]
+pub struct Afe4404Error(pub String);
+
+trait IntoFrontendResult<T> {
+    fn into_frontend_result(self) -> Result<T, Afe4404Error>;
+}
+
+impl<T, E: core::fmt::Debug> IntoFrontendResult<T> for Result<T, E> {
+    fn into_frontend_result(self) -> Result<T, Afe4404Error> {
+        self.map_err(|e| Afe4404Error(format!("{:?}", e)))
+    }
+}
+
+// The inherent methods of the driver take precedence over the trait ones, so every call below goes to the device.
+impl<I2C> OpticalFrontend for AFE4404<I2C, ThreeLedsMode>
+where
+    I2C: I2c<SevenBitAddress>,
+{
+    type Error = Afe4404Error;
+
+    fn sw_reset(&mut self) -> Result<(), Self::Error> {
+        self.sw_reset().into_frontend_result()
+    }
+
+    fn set_leds_current(
+        &mut self,
+        configuration: &LedCurrentConfiguration<ThreeLedsMode>,
+    ) -> Result<LedCurrentConfiguration<ThreeLedsMode>, Self::Error> {
+        self.set_leds_current(configuration).into_frontend_result()
+    }
+
+    fn set_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_led1_current(current).into_frontend_result()
+    }
+
+    fn set_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_led2_current(current).into_frontend_result()
+    }
+
+    fn set_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_led3_current(current).into_frontend_result()
+    }
+
+    fn get_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_led1_current().into_frontend_result()
+    }
+
+    fn get_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_led2_current().into_frontend_result()
+    }
+
+    fn get_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_led3_current().into_frontend_result()
+    }
+
+    fn set_offset_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_offset_led1_current(current).into_frontend_result()
+    }
+
+    fn set_offset_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_offset_led2_current(current).into_frontend_result()
+    }
+
+    fn set_offset_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.set_offset_led3_current(current).into_frontend_result()
+    }
+
+    fn get_offset_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_offset_led1_current().into_frontend_result()
+    }
+
+    fn get_offset_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_offset_led2_current().into_frontend_result()
+    }
+
+    fn get_offset_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        self.get_offset_led3_current().into_frontend_result()
+    }
+
+    fn set_tia_resistor1(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error> {
+        self.set_tia_resistor1(resistor).into_frontend_result()
+    }
+
+    fn set_tia_resistor2(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error> {
+        self.set_tia_resistor2(resistor).into_frontend_result()
+    }
+
+    fn get_tia_resistor1(&mut self) -> Result<ElectricalResistance, Self::Error> {
+        self.get_tia_resistor1().into_frontend_result()
+    }
+
+    fn get_tia_resistor2(&mut self) -> Result<ElectricalResistance, Self::Error> {
+        self.get_tia_resistor2().into_frontend_result()
+    }
+
+    fn set_tia_capacitor1(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error> {
+        self.set_tia_capacitor1(capacitor).into_frontend_result()
+    }
+
+    fn set_tia_capacitor2(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error> {
+        self.set_tia_capacitor2(capacitor).into_frontend_result()
+    }
+
+    fn set_clock_source(
+        &mut self,
+        configuration: ClockConfiguration,
+    ) -> Result<ClockConfiguration, Self::Error> {
+        self.set_clock_source(configuration).into_frontend_result()
+    }
+
+    fn set_measurement_window(
+        &mut self,
+        configuration: &MeasurementWindowConfiguration<ThreeLedsMode>,
+    ) -> Result<MeasurementWindowConfiguration<ThreeLedsMode>, Self::Error> {
+        self.set_measurement_window(configuration)
+            .into_frontend_result()
+    }
+
+    fn set_averaging(&mut self, averages: u8) -> Result<u8, Self::Error> {
+        self.set_averaging(averages).into_frontend_result()
+    }
+
+    fn set_photodiode(&mut self, state: State) -> Result<State, Self::Error> {
+        self.set_photodiode(state).into_frontend_result()
+    }
+
+    fn read(&mut self) -> Result<RawData, Self::Error> {
+        let readings = self.read().into_frontend_result()?;
+
+        Ok(RawData {
+            ambient: *readings.ambient(),
+            led1: *readings.led1(),
+            led2: *readings.led2(),
+            led3: *readings.led3(),
+        })
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Mutex;
+
+use afe4404::{
+    clock::ClockConfiguration,
+    led_current::LedCurrentConfiguration,
+    measurement_window::{
+        ActiveTiming, AmbientTiming, LedTiming, MeasurementWindowConfiguration, PowerDownTiming,
+    },
+    modes::ThreeLedsMode,
+    system::State,
+};
+use uom::si::{
+    capacitance::picofarad,
+    electric_current::milliampere,
+    electrical_resistance::ohm,
+    f32::{Capacitance, ElectricCurrent, ElectricalResistance, Time},
+    time::microsecond,
+};
+
+use crate::{error::FrontendError, protocol::RawData};
+
+pub mod afe4404_frontend;
+pub mod simulated;
+
+/// The operations that the optical pipeline needs from an analog frontend.
+///
+/// The setters return the value that has actually been applied, which can differ from the requested one
+/// because of the quantisation of the frontend registers.
+pub trait OpticalFrontend {
+    type Error: core::fmt::Debug;
+
+    /// Resets every register of the frontend to its default value.
+    fn sw_reset(&mut self) -> Result<(), Self::Error>;
+
+    /// Sets the current of the three LEDs at once.
+    fn set_leds_current(
+        &mut self,
+        configuration: &LedCurrentConfiguration<ThreeLedsMode>,
+    ) -> Result<LedCurrentConfiguration<ThreeLedsMode>, Self::Error>;
+
+    fn set_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn set_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn set_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn get_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+    fn get_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+    fn get_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+
+    fn set_offset_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn set_offset_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn set_offset_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error>;
+    fn get_offset_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+    fn get_offset_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+    fn get_offset_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error>;
+
+    /// Sets the TIA resistor used for the ambient and LED1 phases.
+    fn set_tia_resistor1(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error>;
+    /// Sets the TIA resistor used for the LED2 and LED3 phases.
+    fn set_tia_resistor2(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error>;
+    fn get_tia_resistor1(&mut self) -> Result<ElectricalResistance, Self::Error>;
+    fn get_tia_resistor2(&mut self) -> Result<ElectricalResistance, Self::Error>;
+    fn set_tia_capacitor1(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error>;
+    fn set_tia_capacitor2(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error>;
+
+    fn set_clock_source(
+        &mut self,
+        configuration: ClockConfiguration,
+    ) -> Result<ClockConfiguration, Self::Error>;
+
+    /// Sets the timing windows of every phase and the period of the measurement window.
+    fn set_measurement_window(
+        &mut self,
+        configuration: &MeasurementWindowConfiguration<ThreeLedsMode>,
+    ) -> Result<MeasurementWindowConfiguration<ThreeLedsMode>, Self::Error>;
+
+    /// Sets the number of ADC conversions averaged for each sample.
+    fn set_averaging(&mut self, averages: u8) -> Result<u8, Self::Error>;
+
+    /// Connects or disconnects the photodiode from the TIA.
+    fn set_photodiode(&mut self, state: State) -> Result<State, Self::Error>;
+
+    /// Reads the latest ambient and LEDs phase voltages.
+    fn read(&mut self) -> Result<RawData, Self::Error>;
+}
+
+/// Configures the frontend registers with the default values used by the firmware.
+pub fn configure<F: OpticalFrontend>(frontend: &mut F) -> Result<(), F::Error> {
+    frontend.sw_reset()?;
+
+    frontend.set_leds_current(&LedCurrentConfiguration::<ThreeLedsMode>::new(
+        ElectricCurrent::new::<milliampere>(0.0),
+        ElectricCurrent::new::<milliampere>(0.0),
+        ElectricCurrent::new::<milliampere>(0.0),
+    ))?;
+
+    frontend.set_tia_resistor1(ElectricalResistance::new::<ohm>(crate::RESISTOR1))?;
+    frontend.set_tia_resistor2(ElectricalResistance::new::<ohm>(crate::RESISTOR2))?;
+    frontend.set_tia_capacitor1(Capacitance::new::<picofarad>(2.5))?;
+    frontend.set_tia_capacitor2(Capacitance::new::<picofarad>(2.5))?;
+
+    frontend.set_clock_source(ClockConfiguration::Internal)?;
+
+    frontend.set_measurement_window(&MeasurementWindowConfiguration::<ThreeLedsMode>::new(
+        Time::new::<microsecond>(30_000.0),
+        ActiveTiming::<ThreeLedsMode>::new(
+            LedTiming {
+                lighting_st: Time::new::<microsecond>(600.0),
+                lighting_end: Time::new::<microsecond>(890.0),
+                sample_st: Time::new::<microsecond>(680.0),
+                sample_end: Time::new::<microsecond>(890.0),
+                reset_st: Time::new::<microsecond>(3200.0),
+                reset_end: Time::new::<microsecond>(3209.0),
+                conv_st: Time::new::<microsecond>(3210.0),
+                conv_end: Time::new::<microsecond>(3690.0),
+            },
+            LedTiming {
+                lighting_st: Time::new::<microsecond>(0.0),
+                lighting_end: Time::new::<microsecond>(290.0),
+                sample_st: Time::new::<microsecond>(80.0),
+                sample_end: Time::new::<microsecond>(290.0),
+                reset_st: Time::new::<microsecond>(2200.0),
+                reset_end: Time::new::<microsecond>(2209.0),
+                conv_st: Time::new::<microsecond>(2210.0),
+                conv_end: Time::new::<microsecond>(2690.0),
+            },
+            LedTiming {
+                lighting_st: Time::new::<microsecond>(300.0),
+                lighting_end: Time::new::<microsecond>(590.0),
+                sample_st: Time::new::<microsecond>(380.0),
+                sample_end: Time::new::<microsecond>(590.0),
+                reset_st: Time::new::<microsecond>(2700.0),
+                reset_end: Time::new::<microsecond>(2709.0),
+                conv_st: Time::new::<microsecond>(2710.0),
+                conv_end: Time::new::<microsecond>(3190.0),
+            },
+            AmbientTiming {
+                sample_st: Time::new::<microsecond>(980.0),
+                sample_end: Time::new::<microsecond>(1190.0),
+                reset_st: Time::new::<microsecond>(3700.0),
+                reset_end: Time::new::<microsecond>(3709.0),
+                conv_st: Time::new::<microsecond>(3710.0),
+                conv_end: Time::new::<microsecond>(4190.0),
+            },
+        ),
+        PowerDownTiming {
+            power_down_st: Time::new::<microsecond>(4400.0),
+            power_down_end: Time::new::<microsecond>(29_800.0),
+        },
+    ))?;
+
+    frontend.set_averaging(8)?;
+
+    Ok(())
+}
+
+/// Locks the frontend and calls the given operation on it, converting any failure into a [`FrontendError`].
+pub fn with_frontend<F, T, E, O>(
+    frontend: &Mutex<Option<F>>,
+    operation: O,
+) -> Result<T, FrontendError>
+where
+    E: core::fmt::Debug,
+    O: FnOnce(&mut F) -> Result<T, E>,
+{
+    let mut frontend = frontend.lock().map_err(|_| FrontendError::Poisoned)?;
+    let frontend = frontend.as_mut().ok_or(FrontendError::NotInitialised)?;
+
+    operation(frontend).map_err(|e| FrontendError::Device(format!("{:?}", e)))
+}
@@ -0,0 +1,386 @@
+// A frontend that produces photodiode voltages from the LED and offset currents it is configured with,
+// so that calibration and processing can run without the AFE4404.
+
+use afe4404::{
+    clock::ClockConfiguration,
+    led_current::LedCurrentConfiguration,
+    measurement_window::{ActiveTiming, MeasurementWindowConfiguration},
+    modes::ThreeLedsMode,
+    system::State,
+};
+use uom::si::{
+    electric_current::{microampere, milliampere},
+    electrical_resistance::ohm,
+    f32::{Capacitance, ElectricCurrent, ElectricalResistance, Time},
+};
+
+use super::OpticalFrontend;
+use crate::{
+    protocol::RawData,
+    synthetic::{FrontendSettings, SyntheticPpg},
+};
+
+/// The simulated frontend.
+pub struct SimulatedFrontend {
+    ppg: SyntheticPpg,
+
+    // Simulated registers.
+    settings: FrontendSettings,
+}
+
+impl SimulatedFrontend {
+    /// Creates a new `SimulatedFrontend` in its reset state, reading the given synthetic signal.
+    pub fn new(ppg: SyntheticPpg) -> Self {
+        let mut frontend = Self {
+            ppg,
+            settings: FrontendSettings::default(),
+        };
+        frontend.reset_registers();
+
+        frontend
+    }
+
+    /// Gets an immutable reference of the synthetic signal.
+    pub fn ppg(&self) -> &SyntheticPpg {
+        &self.ppg
+    }
+
+    /// Gets a mutable reference of the synthetic signal.
+    pub fn ppg_mut(&mut self) -> &mut SyntheticPpg {
+        &mut self.ppg
+    }
+
+    /// Gets the simulated time elapsed since the creation of the frontend.
+    pub fn time(&self) -> Time {
+        self.ppg.time()
+    }
+
+    fn reset_registers(&mut self) {
+        self.settings.led_currents = [ElectricCurrent::new::<milliampere>(0.0); 3];
+        self.settings.offset_currents = [ElectricCurrent::new::<microampere>(0.0); 3];
+        self.settings.resistor1 = ElectricalResistance::new::<ohm>(500e3);
+        self.settings.resistor2 = ElectricalResistance::new::<ohm>(500e3);
+        self.settings.averages = 1;
+        self.settings.photodiode_connected = true;
+    }
+
+    /// Quantises a LED current to the 0.8 mA steps of the LED driver.
+    fn quantise_led_current(current: ElectricCurrent) -> ElectricCurrent {
+        let steps = (current.get::<milliampere>() / 0.8)
+            .round()
+            .clamp(0.0, 63.0);
+        ElectricCurrent::new::<milliampere>(steps * 0.8)
+    }
+
+    /// Quantises an offset current to the 7/15 uA steps of the offset cancellation DAC.
+    fn quantise_offset_current(current: ElectricCurrent) -> ElectricCurrent {
+        let steps = (current.get::<microampere>() / 7.0 * 15.0)
+            .round()
+            .clamp(-15.0, 15.0);
+        ElectricCurrent::new::<microampere>(steps * 7.0 / 15.0)
+    }
+}
+
+impl Default for SimulatedFrontend {
+    fn default() -> Self {
+        Self::new(SyntheticPpg::default())
+    }
+}
+
+impl OpticalFrontend for SimulatedFrontend {
+    type Error = core::convert::Infallible;
+
+    fn sw_reset(&mut self) -> Result<(), Self::Error> {
+        self.reset_registers();
+        Ok(())
+    }
+
+    fn set_leds_current(
+        &mut self,
+        configuration: &LedCurrentConfiguration<ThreeLedsMode>,
+    ) -> Result<LedCurrentConfiguration<ThreeLedsMode>, Self::Error> {
+        Ok(LedCurrentConfiguration::<ThreeLedsMode>::new(
+            self.set_led1_current(*configuration.led1())?,
+            self.set_led2_current(*configuration.led2())?,
+            self.set_led3_current(*configuration.led3())?,
+        ))
+    }
+
+    fn set_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.led_currents[0] = Self::quantise_led_current(current);
+        Ok(self.settings.led_currents[0])
+    }
+
+    fn set_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.led_currents[1] = Self::quantise_led_current(current);
+        Ok(self.settings.led_currents[1])
+    }
+
+    fn set_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.led_currents[2] = Self::quantise_led_current(current);
+        Ok(self.settings.led_currents[2])
+    }
+
+    fn get_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.led_currents[0])
+    }
+
+    fn get_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.led_currents[1])
+    }
+
+    fn get_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.led_currents[2])
+    }
+
+    fn set_offset_led1_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.offset_currents[0] = Self::quantise_offset_current(current);
+        Ok(self.settings.offset_currents[0])
+    }
+
+    fn set_offset_led2_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.offset_currents[1] = Self::quantise_offset_current(current);
+        Ok(self.settings.offset_currents[1])
+    }
+
+    fn set_offset_led3_current(
+        &mut self,
+        current: ElectricCurrent,
+    ) -> Result<ElectricCurrent, Self::Error> {
+        self.settings.offset_currents[2] = Self::quantise_offset_current(current);
+        Ok(self.settings.offset_currents[2])
+    }
+
+    fn get_offset_led1_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.offset_currents[0])
+    }
+
+    fn get_offset_led2_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.offset_currents[1])
+    }
+
+    fn get_offset_led3_current(&mut self) -> Result<ElectricCurrent, Self::Error> {
+        Ok(self.settings.offset_currents[2])
+    }
+
+    fn set_tia_resistor1(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error> {
+        self.settings.resistor1 = resistor;
+        Ok(self.settings.resistor1)
+    }
+
+    fn set_tia_resistor2(
+        &mut self,
+        resistor: ElectricalResistance,
+    ) -> Result<ElectricalResistance, Self::Error> {
+        self.settings.resistor2 = resistor;
+        Ok(self.settings.resistor2)
+    }
+
+    fn get_tia_resistor1(&mut self) -> Result<ElectricalResistance, Self::Error> {
+        Ok(self.settings.resistor1)
+    }
+
+    fn get_tia_resistor2(&mut self) -> Result<ElectricalResistance, Self::Error> {
+        Ok(self.settings.resistor2)
+    }
+
+    // The TIA bandwidth is not simulated.
+    fn set_tia_capacitor1(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error> {
+        Ok(capacitor)
+    }
+
+    fn set_tia_capacitor2(&mut self, capacitor: Capacitance) -> Result<Capacitance, Self::Error> {
+        Ok(capacitor)
+    }
+
+    fn set_clock_source(
+        &mut self,
+        configuration: ClockConfiguration,
+    ) -> Result<ClockConfiguration, Self::Error> {
+        Ok(configuration)
+    }
+
+    fn set_measurement_window(
+        &mut self,
+        configuration: &MeasurementWindowConfiguration<ThreeLedsMode>,
+    ) -> Result<MeasurementWindowConfiguration<ThreeLedsMode>, Self::Error> {
+        self.settings.sample_period = *configuration.period();
+
+        let active = configuration.active_timing_configuration();
+        Ok(MeasurementWindowConfiguration::<ThreeLedsMode>::new(
+            *configuration.period(),
+            ActiveTiming::<ThreeLedsMode>::new(
+                *active.led1(),
+                *active.led2(),
+                *active.led3(),
+                *active.ambient(),
+            ),
+            *configuration.inactive_timing_configuration(),
+        ))
+    }
+
+    fn set_averaging(&mut self, averages: u8) -> Result<u8, Self::Error> {
+        self.settings.averages = averages.clamp(1, 16);
+        Ok(self.settings.averages)
+    }
+
+    fn set_photodiode(&mut self, state: State) -> Result<State, Self::Error> {
+        self.settings.photodiode_connected = state == State::Enabled;
+        Ok(state)
+    }
+
+    /// Produces the readings of the next measurement window.
+    fn read(&mut self) -> Result<RawData, Self::Error> {
+        Ok(self.ppg.sample(&self.settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        calibration::{calibrators, offset_measuring::OffsetCurrents},
+        frontend::{configure, with_frontend},
+    };
+
+    /// Creates a configured simulated frontend, shared like the one of the firmware.
+    fn frontend() -> &'static Arc<Mutex<Option<SimulatedFrontend>>> {
+        let frontend = Box::leak(Box::new(Arc::new(Mutex::new(Some(
+            SimulatedFrontend::default(),
+        )))));
+        with_frontend(frontend, configure).unwrap();
+
+        frontend
+    }
+
+    #[test]
+    fn dc_calibration_converges() {
+        let frontend = frontend();
+        let (mut calibrator_led1, mut calibrator_led2_led3) = calibrators(frontend).unwrap();
+
+        let mut calibrations = vec![];
+        for _ in 0..200 {
+            let raw_data = with_frontend(frontend, |frontend| frontend.read()).unwrap();
+            let calibrated_led1 = calibrator_led1.calibrate_dc(raw_data.led1).unwrap();
+            let calibrated_led2_led3 = calibrator_led2_led3.calibrate_dc(raw_data.led3).unwrap();
+            calibrations.push(calibrated_led1 || calibrated_led2_led3);
+        }
+        // The currents settle within a few samples and are not changed again.
+        assert!(calibrations.iter().skip(10).all(|calibrated| !calibrated));
+
+        let raw_data = with_frontend(frontend, |frontend| frontend.read()).unwrap();
+        for (reading, calibrator) in [
+            (raw_data.led1, &calibrator_led1),
+            (raw_data.led3, &calibrator_led2_led3),
+        ] {
+            assert!(
+                (reading - *calibrator.adc_set_point()).abs() < *calibrator.adc_working_threshold(),
+                "{:?}",
+                reading
+            );
+        }
+
+        let (led_currents, offset_currents) = with_frontend(frontend, |frontend| {
+            Ok::<_, core::convert::Infallible>((
+                [
+                    frontend.get_led1_current()?,
+                    frontend.get_led2_current()?,
+                    frontend.get_led3_current()?,
+                ],
+                [
+                    frontend.get_offset_led1_current()?,
+                    frontend.get_offset_led2_current()?,
+                    frontend.get_offset_led3_current()?,
+                ],
+            ))
+        })
+        .unwrap();
+        // The LED2 and LED3 currents are calibrated together.
+        assert_eq!(led_currents[1], led_currents[2]);
+        assert_eq!(offset_currents[1], offset_currents[2]);
+        // The LED current is quantised to the closest step, which can be just above the maximum.
+        let step = ElectricCurrent::new::<milliampere>(0.8);
+        for (i, calibrator) in [
+            (0, &calibrator_led1),
+            (1, &calibrator_led2_led3),
+            (2, &calibrator_led2_led3),
+        ] {
+            assert!(
+                led_currents[i] >= *calibrator.led_current_min()
+                    && led_currents[i] <= *calibrator.led_current_max() + step,
+                "{:?}",
+                led_currents
+            );
+            assert!(
+                offset_currents[i] >= *calibrator.offset_current_min()
+                    && offset_currents[i] <= *calibrator.offset_current_max(),
+                "{:?}",
+                offset_currents
+            );
+        }
+        assert_eq!(calibrator_led1.offset_current, offset_currents[0]);
+        assert_eq!(calibrator_led2_led3.offset_current, offset_currents[2]);
+    }
+
+    #[test]
+    fn calibrators_follow_a_frontend_reset() {
+        let frontend = frontend();
+        let (mut calibrator_led1, mut calibrator_led2_led3) = calibrators(frontend).unwrap();
+        for _ in 0..10 {
+            let raw_data = with_frontend(frontend, |frontend| frontend.read()).unwrap();
+            calibrator_led1.calibrate_dc(raw_data.led1).unwrap();
+            calibrator_led2_led3.calibrate_dc(raw_data.led3).unwrap();
+        }
+        assert_ne!(calibrator_led1.offset_current.value, 0.0);
+
+        with_frontend(frontend, configure).unwrap();
+        calibrator_led1.reset().unwrap();
+        calibrator_led2_led3.reset().unwrap();
+        // The reset has cleared the offset currents, which are read back.
+        assert_eq!(calibrator_led1.offset_current.value, 0.0);
+        assert_eq!(calibrator_led2_led3.offset_current.value, 0.0);
+    }
+
+    #[test]
+    fn offset_currents_are_measured() {
+        let frontend = frontend();
+        let mut offset_currents = OffsetCurrents::new();
+        with_frontend(frontend, |frontend| offset_currents.measure(frontend)).unwrap();
+
+        for (measured, nominal) in offset_currents
+            .currents()
+            .iter()
+            .zip(OffsetCurrents::new().currents())
+        {
+            assert!(
+                (*measured - *nominal).abs() < ElectricCurrent::new::<microampere>(0.05),
+                "{:?} for {:?}",
+                measured,
+                nominal
+            );
+        }
+        // The photodiode is connected again.
+        let raw_data = with_frontend(frontend, |frontend| frontend.read()).unwrap();
+        assert!(raw_data.ambient.value > 0.0);
+    }
+}
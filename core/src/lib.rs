@@ -0,0 +1,20 @@
+//! The hardware-independent part of the pulse.loop firmware: signal processing, frontend calibration,
+//! protocol encoding and the frontend abstraction, so that it can be built and tested on the host.
+
+pub mod acquisition;
+pub mod calibration;
+pub mod clock;
+pub mod error;
+pub mod frontend;
+pub mod measurement;
+pub mod pipeline;
+pub mod protocol;
+pub mod recording;
+pub mod signal_processing;
+pub mod synthetic;
+pub mod timer;
+
+// Afe4404 constants.
+pub static RESISTOR1: f32 = 500e3;
+pub static RESISTOR2: f32 = 10e3;
+pub static SAMPLE_PERIOD: f32 = 30e-3; // The measurement window, in seconds.
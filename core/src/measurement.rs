@@ -0,0 +1,422 @@
+use crate::clock::{Clock, MonotonicClock};
+
+/// The state of the measurement. The transitions are:
+///
+/// - `OffWrist` -> `WristCheck` after 1025 ms.
+/// - `WristCheck` -> `Calibrating` after 200 ms if the wrist is present, `OffWrist` otherwise.
+/// - `Calibrating` -> `FilterSettling` after 200 ms without frontend changes.
+/// - `FilterSettling` -> `Measuring` after the filters have settled.
+/// - `FilterSettling` or `Measuring` -> `Calibrating` when the frontend settings change.
+/// - `Calibrating`, `FilterSettling` or `Measuring` -> `SignalLost` when the wrist is not present.
+/// - `SignalLost` -> `Calibrating` if the wrist is present again, `OffWrist` after 1000 ms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MeasurementState {
+    /// The wrist is not on the sensor and the LEDs are off.
+    #[default]
+    OffWrist = 0,
+    /// The IR LED is on to check the wrist presence.
+    WristCheck = 1,
+    /// The wrist is present and the frontend is being calibrated.
+    Calibrating = 2,
+    /// The frontend is calibrated and the filters are settling.
+    FilterSettling = 3,
+    /// The vital signs are being measured.
+    Measuring = 4,
+    /// The wrist has not been detected anymore while it was present.
+    SignalLost = 5,
+}
+
+impl MeasurementState {
+    /// Returns the actions to perform on the frontend when entering this state.
+    pub fn entry_actions(&self) -> &'static [FrontendAction] {
+        match self {
+            MeasurementState::OffWrist => &[FrontendAction::TurnOffLeds],
+            MeasurementState::WristCheck => &[FrontendAction::TurnOnWristCheckLed],
+            MeasurementState::Calibrating => &[FrontendAction::ResetCalibration],
+            _ => &[],
+        }
+    }
+
+    /// Checks if the wrist is considered present in this state.
+    pub fn is_wrist_present(&self) -> bool {
+        matches!(
+            self,
+            MeasurementState::Calibrating
+                | MeasurementState::FilterSettling
+                | MeasurementState::Measuring
+        )
+    }
+
+    pub fn serialise(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// An action on the frontend, performed when entering a [`MeasurementState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontendAction {
+    /// Turns off all the LEDs.
+    TurnOffLeds,
+    /// Turns on the IR LED at its maximum current with the minimum offset current.
+    TurnOnWristCheckLed,
+    /// Resets the calibrators, so that they start again from the current frontend settings.
+    ResetCalibration,
+}
+
+/// A change of [`MeasurementState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: MeasurementState,
+    pub to: MeasurementState,
+}
+
+impl Transition {
+    /// Returns the actions to perform on the frontend for this transition.
+    pub fn actions(&self) -> &'static [FrontendAction] {
+        self.to.entry_actions()
+    }
+}
+
+/// The state machine that drives the measurement, from the wrist detection to the vital signs computation.
+pub struct MeasurementStateMachine<C: Clock = MonotonicClock> {
+    state: MeasurementState,
+    entered_at: u128,
+    clock: C,
+}
+
+impl MeasurementStateMachine {
+    /// Creates a new `MeasurementStateMachine` in the [`MeasurementState::OffWrist`] state, timed with the system
+    /// monotonic clock.
+    pub fn new() -> Self {
+        Self::with_clock(MonotonicClock::new())
+    }
+}
+
+impl Default for MeasurementStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> MeasurementStateMachine<C> {
+    /// The time with the LEDs off before checking the wrist presence, in milliseconds.
+    pub const OFF_WRIST_DURATION: u128 = 1025;
+    /// The time needed by the IR LED to turn on before checking the wrist presence, in milliseconds.
+    pub const WRIST_CHECK_DURATION: u128 = 200;
+    /// The time needed, after any change to the frontend settings, for high-accuracy data, in milliseconds.
+    pub const FRONTEND_SET_UP_DURATION: u128 = 200;
+    /// The time needed for the filters to settle, in milliseconds.
+    pub const FILTER_SETTLING_DURATION: u128 = 85 * 50 + 200;
+    /// The time waited for the wrist to come back before turning off the LEDs, in milliseconds.
+    pub const SIGNAL_LOST_DURATION: u128 = 1000;
+
+    /// Creates a new `MeasurementStateMachine` in the [`MeasurementState::OffWrist`] state, timed with the given
+    /// clock.
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            state: MeasurementState::OffWrist,
+            entered_at: clock.now(),
+            clock,
+        }
+    }
+
+    /// Gets the current state.
+    pub fn state(&self) -> MeasurementState {
+        self.state
+    }
+
+    /// Gets the milliseconds elapsed since the current state has been entered.
+    pub fn time_in_state(&self) -> u128 {
+        self.clock.now().saturating_sub(self.entered_at)
+    }
+
+    /// Updates the state with the wrist presence detected in the latest sample.
+    /// Returns the transition, if any.
+    pub fn update(&mut self, wrist_present: bool) -> Option<Transition> {
+        let elapsed = self.time_in_state();
+
+        let next = match self.state {
+            MeasurementState::OffWrist if elapsed >= Self::OFF_WRIST_DURATION => {
+                Some(MeasurementState::WristCheck)
+            }
+            MeasurementState::WristCheck if elapsed >= Self::WRIST_CHECK_DURATION => {
+                if wrist_present {
+                    Some(MeasurementState::Calibrating)
+                } else {
+                    Some(MeasurementState::OffWrist)
+                }
+            }
+            state if state.is_wrist_present() && !wrist_present => {
+                Some(MeasurementState::SignalLost)
+            }
+            MeasurementState::Calibrating if elapsed >= Self::FRONTEND_SET_UP_DURATION => {
+                Some(MeasurementState::FilterSettling)
+            }
+            MeasurementState::FilterSettling if elapsed >= Self::FILTER_SETTLING_DURATION => {
+                Some(MeasurementState::Measuring)
+            }
+            MeasurementState::SignalLost if wrist_present => Some(MeasurementState::Calibrating),
+            MeasurementState::SignalLost if elapsed >= Self::SIGNAL_LOST_DURATION => {
+                Some(MeasurementState::OffWrist)
+            }
+            _ => None,
+        };
+
+        next.map(|state| self.enter(state))
+    }
+
+    /// Notifies the state machine that the frontend settings have changed, so that the measurement starts again
+    /// from the calibration. Returns the transition, if any.
+    pub fn frontend_changed(&mut self) -> Option<Transition> {
+        match self.state {
+            MeasurementState::Calibrating => {
+                // Wait again for high-accuracy data.
+                self.entered_at = self.clock.now();
+                None
+            }
+            MeasurementState::FilterSettling | MeasurementState::Measuring => {
+                Some(self.enter(MeasurementState::Calibrating))
+            }
+            _ => None,
+        }
+    }
+
+    fn enter(&mut self, state: MeasurementState) -> Transition {
+        let transition = Transition {
+            from: self.state,
+            to: state,
+        };
+        self.state = state;
+        self.entered_at = self.clock.now();
+
+        transition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    type StateMachine = MeasurementStateMachine<ManualClock>;
+
+    /// Creates a state machine in the `Measuring` state, with the wrist present.
+    fn measuring(clock: &ManualClock) -> StateMachine {
+        let mut state_machine = StateMachine::with_clock(clock.clone());
+        for duration in [
+            StateMachine::OFF_WRIST_DURATION,
+            StateMachine::WRIST_CHECK_DURATION,
+            StateMachine::FRONTEND_SET_UP_DURATION,
+            StateMachine::FILTER_SETTLING_DURATION,
+        ] {
+            clock.advance(duration);
+            state_machine.update(true);
+        }
+        assert_eq!(state_machine.state(), MeasurementState::Measuring);
+
+        state_machine
+    }
+
+    fn transition(from: MeasurementState, to: MeasurementState) -> Option<Transition> {
+        Some(Transition { from, to })
+    }
+
+    #[test]
+    fn wrist_detection_to_measuring() {
+        let clock = ManualClock::new();
+        let mut state_machine = StateMachine::with_clock(clock.clone());
+        assert_eq!(state_machine.state(), MeasurementState::OffWrist);
+
+        clock.advance(StateMachine::OFF_WRIST_DURATION - 1);
+        assert_eq!(state_machine.update(true), None);
+        clock.advance(1);
+        let to_wrist_check = state_machine.update(true);
+        assert_eq!(
+            to_wrist_check,
+            transition(MeasurementState::OffWrist, MeasurementState::WristCheck)
+        );
+        assert_eq!(
+            to_wrist_check.unwrap().actions(),
+            &[FrontendAction::TurnOnWristCheckLed]
+        );
+
+        clock.advance(StateMachine::WRIST_CHECK_DURATION);
+        let to_calibrating = state_machine.update(true);
+        assert_eq!(
+            to_calibrating,
+            transition(MeasurementState::WristCheck, MeasurementState::Calibrating)
+        );
+        assert_eq!(
+            to_calibrating.unwrap().actions(),
+            &[FrontendAction::ResetCalibration]
+        );
+
+        clock.advance(StateMachine::FRONTEND_SET_UP_DURATION);
+        let to_filter_settling = state_machine.update(true);
+        assert_eq!(
+            to_filter_settling,
+            transition(
+                MeasurementState::Calibrating,
+                MeasurementState::FilterSettling
+            )
+        );
+        assert!(to_filter_settling.unwrap().actions().is_empty());
+
+        clock.advance(StateMachine::FILTER_SETTLING_DURATION - 1);
+        assert_eq!(state_machine.update(true), None);
+        clock.advance(1);
+        let to_measuring = state_machine.update(true);
+        assert_eq!(
+            to_measuring,
+            transition(
+                MeasurementState::FilterSettling,
+                MeasurementState::Measuring
+            )
+        );
+        assert!(to_measuring.unwrap().actions().is_empty());
+        assert!(state_machine.state().is_wrist_present());
+    }
+
+    #[test]
+    fn wrist_check_without_wrist_turns_off_the_leds() {
+        let clock = ManualClock::new();
+        let mut state_machine = StateMachine::with_clock(clock.clone());
+        clock.advance(StateMachine::OFF_WRIST_DURATION);
+        state_machine.update(false);
+
+        clock.advance(StateMachine::WRIST_CHECK_DURATION);
+        let to_off_wrist = state_machine.update(false);
+        assert_eq!(
+            to_off_wrist,
+            transition(MeasurementState::WristCheck, MeasurementState::OffWrist)
+        );
+        assert_eq!(
+            to_off_wrist.unwrap().actions(),
+            &[FrontendAction::TurnOffLeds]
+        );
+        assert!(!state_machine.state().is_wrist_present());
+    }
+
+    #[test]
+    fn signal_lost_and_found_again() {
+        let clock = ManualClock::new();
+        let mut state_machine = measuring(&clock);
+
+        let to_signal_lost = state_machine.update(false);
+        assert_eq!(
+            to_signal_lost,
+            transition(MeasurementState::Measuring, MeasurementState::SignalLost)
+        );
+        assert!(to_signal_lost.unwrap().actions().is_empty());
+        assert!(!state_machine.state().is_wrist_present());
+
+        clock.advance(StateMachine::SIGNAL_LOST_DURATION - 1);
+        let to_calibrating = state_machine.update(true);
+        assert_eq!(
+            to_calibrating,
+            transition(MeasurementState::SignalLost, MeasurementState::Calibrating)
+        );
+        assert_eq!(
+            to_calibrating.unwrap().actions(),
+            &[FrontendAction::ResetCalibration]
+        );
+    }
+
+    #[test]
+    fn signal_lost_for_too_long_turns_off_the_leds() {
+        let clock = ManualClock::new();
+        let mut state_machine = measuring(&clock);
+        state_machine.update(false);
+
+        clock.advance(StateMachine::SIGNAL_LOST_DURATION - 1);
+        assert_eq!(state_machine.update(false), None);
+        clock.advance(1);
+        let to_off_wrist = state_machine.update(false);
+        assert_eq!(
+            to_off_wrist,
+            transition(MeasurementState::SignalLost, MeasurementState::OffWrist)
+        );
+        assert_eq!(
+            to_off_wrist.unwrap().actions(),
+            &[FrontendAction::TurnOffLeds]
+        );
+    }
+
+    #[test]
+    fn wrist_removed_while_calibrating_or_settling() {
+        let clock = ManualClock::new();
+        let mut state_machine = measuring(&clock);
+        state_machine.frontend_changed();
+        assert_eq!(
+            state_machine.update(false),
+            transition(MeasurementState::Calibrating, MeasurementState::SignalLost)
+        );
+
+        state_machine.update(true);
+        clock.advance(StateMachine::FRONTEND_SET_UP_DURATION);
+        state_machine.update(true);
+        assert_eq!(
+            state_machine.update(false),
+            transition(
+                MeasurementState::FilterSettling,
+                MeasurementState::SignalLost
+            )
+        );
+    }
+
+    #[test]
+    fn frontend_changes_restart_the_calibration() {
+        let clock = ManualClock::new();
+        let mut state_machine = measuring(&clock);
+
+        let to_calibrating = state_machine.frontend_changed();
+        assert_eq!(
+            to_calibrating,
+            transition(MeasurementState::Measuring, MeasurementState::Calibrating)
+        );
+        assert_eq!(
+            to_calibrating.unwrap().actions(),
+            &[FrontendAction::ResetCalibration]
+        );
+
+        // Another change while calibrating restarts the wait for high-accuracy data.
+        clock.advance(StateMachine::FRONTEND_SET_UP_DURATION - 1);
+        assert_eq!(state_machine.frontend_changed(), None);
+        clock.advance(1);
+        assert_eq!(state_machine.update(true), None);
+        clock.advance(StateMachine::FRONTEND_SET_UP_DURATION - 1);
+        assert_eq!(
+            state_machine.update(true),
+            transition(
+                MeasurementState::Calibrating,
+                MeasurementState::FilterSettling
+            )
+        );
+
+        assert_eq!(
+            state_machine.frontend_changed(),
+            transition(
+                MeasurementState::FilterSettling,
+                MeasurementState::Calibrating
+            )
+        );
+    }
+
+    #[test]
+    fn frontend_changes_are_ignored_without_the_wrist() {
+        let clock = ManualClock::new();
+        let mut state_machine = StateMachine::with_clock(clock.clone());
+        assert_eq!(state_machine.frontend_changed(), None);
+
+        clock.advance(StateMachine::OFF_WRIST_DURATION);
+        state_machine.update(true);
+        assert_eq!(state_machine.frontend_changed(), None);
+        assert_eq!(state_machine.state(), MeasurementState::WristCheck);
+
+        let mut state_machine = measuring(&clock);
+        state_machine.update(false);
+        assert_eq!(state_machine.frontend_changed(), None);
+        assert_eq!(state_machine.state(), MeasurementState::SignalLost);
+    }
+}
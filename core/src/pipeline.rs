@@ -0,0 +1,787 @@
+use std::convert::TryFrom;
+
+use static_fir::FirFilter;
+use uom::si::{
+    electric_current::microampere,
+    electrical_resistance::ohm,
+    f32::{ElectricCurrent, ElectricalResistance, Time},
+    time::second,
+};
+
+use crate::{
+    calibration::offset_measuring::OffsetCurrents,
+    clock::{Clock, MonotonicClock},
+    measurement::{MeasurementState, MeasurementStateMachine, Transition},
+    protocol::{FilteredData, RawData, Results},
+    signal_processing::{
+        autocorrelation::AutocorrelationEstimator,
+        averaging::{Averager, Averaging},
+        beat_detection::{Beat, BeatDetector},
+        filters::{AcFir, DcFir},
+        fusion::{FusedHeartRate, HeartRateFusion},
+        hrv::{FrequencyDomainHrv, HrvAnalyser, SpectralHrvAnalyser, TimeDomainHrv},
+        motion::{MotionAssessment, MotionDetector},
+        perfusion::{PulseAmplitude, PulseAmplitudeMeter},
+        quality::{SignalQuality, SignalQualityAssessor},
+        respiration::{RespirationEstimate, RespirationEstimator},
+        rhythm::{IrregularRhythmDetector, RhythmAssessment},
+        rr_editing::{BeatClass, RrEditor},
+        spectral::SpectralEstimator,
+        spo2::{Spo2Estimate, Spo2Estimator},
+        spo2_calibration::Spo2Calibration,
+        standard_deviation::MovingStandardDeviation,
+        HeartRateEstimate,
+    },
+};
+
+/// The method used by the [`VitalSignsPipeline`] to compute the heart rate from the LED1 AC signal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HeartRateMethod {
+    /// The edited interval between the systolic peaks of consecutive beats, computed at every beat with a delay of
+    /// one beat.
+    #[default]
+    PeakDetection,
+    /// The period of the signal, from its autocorrelation over the last 8 s, computed every second.
+    Autocorrelation,
+    /// The dominant cardiac frequency of the spectrum over the last 8 s, tracked across windows and computed
+    /// every second.
+    Spectral,
+    /// The interval between the beats detected independently on LED1, LED2 and LED3, fused with weights given by
+    /// the signal quality index of each channel, computed at every beat.
+    Fusion,
+}
+
+impl HeartRateMethod {
+    pub fn serialise(&self) -> u8 {
+        match self {
+            HeartRateMethod::PeakDetection => 0,
+            HeartRateMethod::Autocorrelation => 1,
+            HeartRateMethod::Spectral => 2,
+            HeartRateMethod::Fusion => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for HeartRateMethod {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(HeartRateMethod::PeakDetection),
+            1 => Ok(HeartRateMethod::Autocorrelation),
+            2 => Ok(HeartRateMethod::Spectral),
+            3 => Ok(HeartRateMethod::Fusion),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The method used by the [`VitalSignsPipeline`] to compute the perfusion indices of LED2 and LED3.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PerfusionIndexMethod {
+    /// The RMS of the AC signal over the last 9 s over the DC level, computed at every sample.
+    #[default]
+    Rms,
+    /// The peak-to-peak AC amplitude of the latest beat over the DC level at its systolic peak, the clinical
+    /// definition, computed at every beat. It is about 2.8 times the RMS one for a sinusoidal pulse.
+    PeakToPeak,
+}
+
+/// The values computed by the [`VitalSignsPipeline`] for a single sample.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PipelineOutput {
+    /// The timestamp of the processed sample, in milliseconds.
+    pub timestamp: u128,
+    /// The measurement state after this sample.
+    pub state: MeasurementState,
+    /// The state transition caused by this sample, whose frontend actions have to be performed.
+    pub transition: Option<Transition>,
+    /// The filtered data, available only when the frontend has settled after the last change.
+    pub filtered_data: Option<FilteredData>,
+    /// The adaptive threshold of the beat detector, on the slope sum of the LED1 pulse.
+    pub beat_threshold: f32,
+    /// The heart beat whose systolic peak has just been passed, if any.
+    pub beat: Option<Beat>,
+    /// The latest normal (NN) interval edited at this beat, in milliseconds. The intervals are edited with a delay
+    /// of one beat.
+    pub rr_interval: Option<u128>,
+    /// The fraction of the recent RR intervals that have been edited, from 0 to 1, updated at every beat.
+    pub edited_fraction: Option<f32>,
+    /// The pulse amplitudes of LED1, LED2 and LED3 over the beat, if any.
+    pub pulse_amplitudes: Option<[PulseAmplitude; 3]>,
+    /// The quality of the pulse over the beat, if it could be assessed. Only the beats with a signal quality index
+    /// of at least [`SignalQualityAssessor::MIN_SQI`] are used to compute the SpO2, the heart rate from the peak
+    /// detection, the respiration rate and the rhythm.
+    pub signal_quality: Option<SignalQuality>,
+    /// The motion detected from the optical signals over the last 2 s. The beats are not used by the estimators while
+    /// moving, and the heart rate is held until the motion has left the window of the selected method.
+    pub motion: Option<MotionAssessment>,
+    /// The assessment of the heart rhythm over the last 2 minutes, updated at every beat.
+    pub rhythm: Option<RhythmAssessment>,
+    /// The time-domain heart rate variability over the HRV window, updated at every beat.
+    pub hrv: Option<TimeDomainHrv>,
+    /// The frequency-domain heart rate variability over the spectral HRV window, updated every spectral HRV period.
+    pub spectral_hrv: Option<FrequencyDomainHrv>,
+    /// The respiration rate over the last 32 s, updated every 5 s.
+    pub respiration: Option<RespirationEstimate>,
+    /// The heart rate in bpm averaged with the selected [`Averaging`], available only when a new heart beat has been
+    /// detected or, depending on the [`HeartRateMethod`], a new estimate has been computed.
+    pub heart_rate: Option<f32>,
+    /// The heart rate in bpm before the averaging, available together with the averaged one.
+    pub raw_heart_rate: Option<f32>,
+    /// The confidence of the heart rate, from 0 to 1, if the method provides one.
+    pub heart_rate_confidence: Option<f32>,
+    /// The heart rate fused from the channels, with their own heart rate and weight, if the [`HeartRateMethod`] is
+    /// [`HeartRateMethod::Fusion`].
+    pub fused_heart_rate: Option<FusedHeartRate>,
+    /// The blood oxygen saturation over the last 10 s, updated at every beat and when the beats get older than the
+    /// window.
+    pub spo2: Option<Spo2Estimate>,
+    /// The latest results. The SpO2 is averaged with the selected [`Averaging`], and it is invalid until enough
+    /// consistent beats have been measured.
+    pub results: Results,
+}
+
+/// The signal processing chain that converts the frontend readings into vital signs.
+/// All the time intervals, including the RR intervals, are measured with the given clock.
+pub struct VitalSignsPipeline<C: Clock + Clone = MonotonicClock> {
+    clock: C,
+
+    offset_currents: OffsetCurrents,
+    led1_offset_current: ElectricCurrent,
+    led2_led3_offset_current: ElectricCurrent,
+
+    dc_filters: [FirFilter<DcFir>; 3],
+    ac_filters: [FirFilter<AcFir>; 3],
+
+    heart_rate_method: HeartRateMethod,
+    perfusion_index_method: PerfusionIndexMethod,
+    autocorrelation_estimator: AutocorrelationEstimator,
+    spectral_estimator: SpectralEstimator,
+    heart_rate_fusion: HeartRateFusion,
+    heart_rate_averager: Averager,
+
+    beat_detector: BeatDetector,
+    rr_editor: RrEditor,
+    pulse_amplitude_meter: PulseAmplitudeMeter,
+    quality_assessor: SignalQualityAssessor,
+    motion_detector: MotionDetector,
+    rhythm_detector: IrregularRhythmDetector,
+    hrv_analyser: HrvAnalyser,
+    spectral_hrv_analyser: SpectralHrvAnalyser,
+    respiration_estimator: RespirationEstimator,
+    // The time the measurement has been interrupted, if it has not resumed yet.
+    interrupted_at: Option<u128>,
+
+    state_machine: MeasurementStateMachine<C>,
+
+    red_deviation: MovingStandardDeviation,
+    ir_deviation: MovingStandardDeviation,
+    spo2_estimator: Spo2Estimator,
+    spo2_averager: Averager,
+
+    results: Results,
+}
+
+impl<C: Clock + Clone> VitalSignsPipeline<C> {
+    /// The window of the autocorrelation and spectral heart rate estimators, in seconds.
+    const HEART_RATE_WINDOW: f32 = 8.0;
+    /// The longest interruption of the measurement with the wrist present, e.g. to recalibrate the frontend, across
+    /// which the heart rate variability and the rhythm keep their intervals, in milliseconds.
+    const MAX_INTERRUPTION: u128 = 30_000;
+
+    /// Creates a new `VitalSignsPipeline` that uses the given measured offset currents and clock.
+    pub fn new(offset_currents: OffsetCurrents, clock: C) -> Self {
+        Self {
+            clock: clock.clone(),
+            offset_currents,
+            led1_offset_current: ElectricCurrent::new::<microampere>(0.0),
+            led2_led3_offset_current: ElectricCurrent::new::<microampere>(0.0),
+            dc_filters: [FirFilter::new(), FirFilter::new(), FirFilter::new()],
+            ac_filters: [FirFilter::new(), FirFilter::new(), FirFilter::new()],
+            heart_rate_method: HeartRateMethod::default(),
+            perfusion_index_method: PerfusionIndexMethod::default(),
+            autocorrelation_estimator: AutocorrelationEstimator::new(
+                Time::new::<second>(Self::HEART_RATE_WINDOW),
+                Time::new::<second>(1.0),
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+            ),
+            spectral_estimator: SpectralEstimator::new(
+                Time::new::<second>(Self::HEART_RATE_WINDOW),
+                Time::new::<second>(1.0),
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+            ),
+            heart_rate_fusion: HeartRateFusion::new(Time::new::<second>(crate::SAMPLE_PERIOD)),
+            heart_rate_averager: Averager::new(Averaging::default()),
+            beat_detector: BeatDetector::new(Time::new::<second>(crate::SAMPLE_PERIOD)),
+            rr_editor: RrEditor::new(),
+            pulse_amplitude_meter: PulseAmplitudeMeter::new(Time::new::<second>(
+                crate::SAMPLE_PERIOD,
+            )),
+            quality_assessor: SignalQualityAssessor::new(
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+                Time::new::<second>(8.0),
+            ),
+            motion_detector: MotionDetector::new(
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+                Time::new::<second>(2.0),
+                Time::new::<second>(2.0),
+            ),
+            rhythm_detector: IrregularRhythmDetector::new(Time::new::<second>(120.0)),
+            hrv_analyser: HrvAnalyser::new(Time::new::<second>(60.0)),
+            spectral_hrv_analyser: SpectralHrvAnalyser::new(
+                Time::new::<second>(300.0),
+                Time::new::<second>(30.0),
+            ),
+            respiration_estimator: RespirationEstimator::new(
+                Time::new::<second>(32.0),
+                Time::new::<second>(5.0),
+            ),
+            interrupted_at: None,
+            state_machine: MeasurementStateMachine::with_clock(clock),
+            red_deviation: MovingStandardDeviation::new(300),
+            ir_deviation: MovingStandardDeviation::new(300),
+            spo2_estimator: Spo2Estimator::new(Time::new::<second>(10.0)),
+            spo2_averager: Averager::new(Averaging::default()),
+            results: Results::default(),
+        }
+    }
+
+    /// Sets the offset currents currently applied to LED1 and to LED2 and LED3.
+    pub fn set_offset_currents(
+        &mut self,
+        led1_offset_current: ElectricCurrent,
+        led2_led3_offset_current: ElectricCurrent,
+    ) {
+        self.led1_offset_current = led1_offset_current;
+        self.led2_led3_offset_current = led2_led3_offset_current;
+    }
+
+    /// Gets the method used to compute the heart rate.
+    pub fn heart_rate_method(&self) -> HeartRateMethod {
+        self.heart_rate_method
+    }
+
+    /// Sets the method used to compute the heart rate.
+    pub fn set_heart_rate_method(&mut self, heart_rate_method: HeartRateMethod) {
+        self.heart_rate_method = heart_rate_method;
+    }
+
+    /// Gets the method used to compute the perfusion indices.
+    pub fn perfusion_index_method(&self) -> PerfusionIndexMethod {
+        self.perfusion_index_method
+    }
+
+    /// Sets the method used to compute the perfusion indices.
+    pub fn set_perfusion_index_method(&mut self, perfusion_index_method: PerfusionIndexMethod) {
+        self.perfusion_index_method = perfusion_index_method;
+    }
+
+    /// Gets the curve that converts the ratio of ratios into the SpO2.
+    pub fn spo2_calibration(&self) -> Spo2Calibration {
+        self.spo2_estimator.calibration()
+    }
+
+    /// Sets the curve that converts the ratio of ratios into the SpO2, used from the next beat.
+    pub fn set_spo2_calibration(&mut self, calibration: Spo2Calibration) {
+        self.spo2_estimator.set_calibration(calibration);
+    }
+
+    /// Gets the averaging of the SpO2 and of the heart rate.
+    pub fn averaging(&self) -> Averaging {
+        self.spo2_averager.averaging()
+    }
+
+    /// Sets the averaging of the SpO2 and of the heart rate, used from their next value.
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        self.spo2_averager.set_averaging(averaging);
+        self.heart_rate_averager.set_averaging(averaging);
+    }
+
+    /// Gets the window of the heart rate variability metrics.
+    pub fn hrv_window(&self) -> Time {
+        self.hrv_analyser.window()
+    }
+
+    /// Sets the window of the heart rate variability metrics.
+    pub fn set_hrv_window(&mut self, window: Time) {
+        self.hrv_analyser.set_window(window);
+    }
+
+    /// Gets the window of the frequency-domain heart rate variability metrics.
+    pub fn spectral_hrv_window(&self) -> Time {
+        self.spectral_hrv_analyser.window()
+    }
+
+    /// Sets the window of the frequency-domain heart rate variability metrics, between 2 and 5 minutes.
+    pub fn set_spectral_hrv_window(&mut self, window: Time) {
+        self.spectral_hrv_analyser.set_window(window);
+    }
+
+    /// Gets the interval between the frequency-domain heart rate variability estimates.
+    pub fn spectral_hrv_period(&self) -> Time {
+        self.spectral_hrv_analyser.period()
+    }
+
+    /// Sets the interval between the frequency-domain heart rate variability estimates.
+    pub fn set_spectral_hrv_period(&mut self, period: Time) {
+        self.spectral_hrv_analyser.set_period(period);
+    }
+
+    /// Gets the current measurement state.
+    pub fn state(&self) -> MeasurementState {
+        self.state_machine.state()
+    }
+
+    /// Notifies the pipeline that the frontend settings have changed, so that the following samples are discarded
+    /// until the frontend and the filters settle. Returns the state transition, if any.
+    pub fn frontend_changed(&mut self) -> Option<Transition> {
+        let transition = self.state_machine.frontend_changed();
+        if let Some(transition) = transition {
+            self.transition(transition);
+        }
+
+        transition
+    }
+
+    /// Processes a new sample, timestamped with the current time of the clock.
+    pub fn process(&mut self, raw_data: RawData) -> PipelineOutput {
+        let mut output = PipelineOutput {
+            timestamp: self.clock.now(),
+            ..Default::default()
+        };
+
+        // Read the ambient light and convert it.
+        let ambient_current =
+            raw_data.ambient / (2.0 * ElectricalResistance::new::<ohm>(crate::RESISTOR1));
+
+        // Read the IR LED (LED 3) and convert it.
+        let ir_current = raw_data.led3 / (2.0 * ElectricalResistance::new::<ohm>(crate::RESISTOR2))
+            - self.offset_currents.accurate(self.led2_led3_offset_current)
+            - ambient_current;
+
+        // Check if the wrist is present with the IR LED (LED 3) and the ambient light.
+        let wrist_present = ambient_current < ElectricCurrent::new::<microampere>(1.0)
+            && ir_current > ElectricCurrent::new::<microampere>(10.0);
+
+        output.transition = self.state_machine.update(wrist_present);
+        if let Some(transition) = output.transition {
+            self.transition(transition);
+        }
+        output.state = self.state_machine.state();
+
+        // Process data.
+        if matches!(
+            output.state,
+            MeasurementState::FilterSettling | MeasurementState::Measuring
+        ) {
+            // Convert the data into current and remove the ambient light.
+            let green_current = raw_data.led1
+                / (2.0 * ElectricalResistance::new::<ohm>(crate::RESISTOR1))
+                - self.offset_currents.accurate(self.led1_offset_current)
+                - ambient_current;
+            let red_current = raw_data.led2
+                / (2.0 * ElectricalResistance::new::<ohm>(crate::RESISTOR2))
+                - self.offset_currents.accurate(self.led2_led3_offset_current)
+                - ambient_current;
+
+            let mut filtered_data = FilteredData::default();
+            for (i, refined_current) in [green_current, red_current, ir_current].iter().enumerate()
+            {
+                // Filter dc data (lowpass).
+                let dc_data = self.dc_filters[i].feed(refined_current.value);
+
+                // Filter ac data (bandpass).
+                let ac_data = self.ac_filters[i].feed(refined_current.value);
+
+                filtered_data[i] = (dc_data, ac_data);
+            }
+
+            output.filtered_data = Some(filtered_data);
+
+            // Calculate the vital signs.
+            if output.state == MeasurementState::Measuring {
+                let ac = filtered_data[0].1;
+                // The photodiode current decreases when the blood volume increases.
+                output.beat = self.beat_detector.push(-ac, output.timestamp);
+                self.pulse_amplitude_meter
+                    .push(filtered_data, output.timestamp);
+                let clipped = [raw_data.led1, raw_data.led2, raw_data.led3]
+                    .map(SignalQualityAssessor::clipped);
+                // The SpO2 is computed from all the channels, so the LED1 beats are rejected if any is clipped.
+                self.quality_assessor.push(
+                    -ac,
+                    clipped.iter().any(|&clipped| clipped),
+                    output.timestamp,
+                );
+                let moving =
+                    self.motion(filtered_data, clipped, ambient_current.value, &mut output);
+                let mut edited_heart_rate = None;
+                let mut clean_amplitudes = None;
+                if let Some(beat) = output.beat {
+                    output.pulse_amplitudes = self.pulse_amplitude_meter.measure(&beat);
+                    output.signal_quality = output.pulse_amplitudes.and_then(|amplitudes| {
+                        self.quality_assessor
+                            .assess(&beat, amplitudes[0].perfusion_index())
+                    });
+                    if let Some(quality) = output.signal_quality {
+                        self.results.signal_quality = quality.window_sqi;
+                    }
+                    // The RR intervals are all edited, since skipping a beat would merge two intervals, but only the
+                    // clean beats measured at rest feed the estimators.
+                    let clean = !moving
+                        && output
+                            .signal_quality
+                            .is_some_and(|quality| quality.sqi >= SignalQualityAssessor::MIN_SQI);
+                    if clean {
+                        clean_amplitudes = output.pulse_amplitudes;
+                    }
+
+                    if let Some(interval) = beat.interval {
+                        if clean {
+                            self.rhythm(beat.peak, interval, &mut output);
+                        }
+
+                        for edited in self.rr_editor.push(beat.peak, interval) {
+                            if edited.class == BeatClass::Normal {
+                                output.rr_interval = Some(edited.interval);
+                            }
+                            self.hrv_analyser.push(&edited);
+                            if let Some(spectral_hrv) = self.spectral_hrv_analyser.push(&edited) {
+                                output.spectral_hrv = Some(spectral_hrv);
+                            }
+                            if clean {
+                                edited_heart_rate = Some(60_000.0 / edited.interval as f32);
+                            }
+                        }
+                        output.edited_fraction = Some(self.rr_editor.edited_fraction());
+                    }
+                    output.hrv = self.hrv_analyser.time_domain();
+
+                    // The LED1 DC level carries the respiratory induced intensity variation.
+                    if clean {
+                        output.respiration =
+                            self.respiration_estimator.push(&beat, filtered_data[0].0);
+                    }
+                    if let Some(respiration) = output.respiration {
+                        self.results.respiration_rate = respiration.respiration_rate;
+                        self.results.respiration_rate_reliable = respiration.reliable;
+                    }
+                }
+
+                let estimate = match self.heart_rate_method {
+                    HeartRateMethod::PeakDetection => {
+                        output.heart_rate = edited_heart_rate;
+                        None
+                    }
+                    HeartRateMethod::Autocorrelation => self.autocorrelation_estimator.push(ac),
+                    HeartRateMethod::Spectral => self.spectral_estimator.push(ac),
+                    HeartRateMethod::Fusion => {
+                        output.fused_heart_rate =
+                            self.heart_rate_fusion
+                                .push(filtered_data, clipped, output.timestamp);
+                        output.fused_heart_rate.map(|fused| HeartRateEstimate {
+                            heart_rate: fused.heart_rate,
+                            confidence: fused.confidence,
+                        })
+                    }
+                };
+                if let Some(HeartRateEstimate {
+                    heart_rate,
+                    confidence,
+                }) = estimate
+                {
+                    // The estimators keep their windows, but the heart rate is held until the motion has left them.
+                    let window = match self.heart_rate_method {
+                        HeartRateMethod::Fusion => 0,
+                        _ => (Self::HEART_RATE_WINDOW * 1000.0) as u128,
+                    };
+                    if !moving
+                        && self
+                            .motion_detector
+                            .last_motion()
+                            .is_none_or(|last_motion| {
+                                output.timestamp.saturating_sub(last_motion) > window
+                            })
+                    {
+                        output.heart_rate = Some(heart_rate);
+                        output.heart_rate_confidence = Some(confidence);
+                    }
+                }
+                output.raw_heart_rate = output.heart_rate;
+                output.heart_rate = output
+                    .raw_heart_rate
+                    .map(|heart_rate| self.heart_rate_averager.push(output.timestamp, heart_rate));
+                self.perfusion_indices(&filtered_data, clean_amplitudes);
+                self.blood_oxygen_saturation(clean_amplitudes, &mut output);
+            }
+        }
+
+        output.beat_threshold = self.beat_detector.threshold();
+        output.results = self.results;
+
+        output
+    }
+
+    /// Updates the results after a state transition.
+    fn transition(&mut self, transition: Transition) {
+        log::info!(
+            "Measurement state: {:?} -> {:?}",
+            transition.from,
+            transition.to
+        );
+
+        self.results.measurement_state = transition.to;
+        self.results.wrist_presence = transition.to.is_wrist_present();
+
+        if transition.from == MeasurementState::Measuring {
+            // The windows and the thresholds would mix the signal before and after the interruption.
+            self.beat_detector.reset();
+            self.rr_editor.reset();
+            self.pulse_amplitude_meter.reset();
+            self.quality_assessor.reset();
+            self.results.signal_quality = 0.0;
+            self.motion_detector.reset();
+            self.results.motion = false;
+            self.spo2_estimator.reset();
+            self.spo2_averager.reset();
+            self.results.spo2 = None;
+            self.results.spo2_confidence = 0.0;
+            self.respiration_estimator.reset();
+            self.results.respiration_rate = 0.0;
+            self.results.respiration_rate_reliable = false;
+            self.autocorrelation_estimator.reset();
+            self.spectral_estimator.reset();
+            self.heart_rate_fusion.reset();
+            self.heart_rate_averager.reset();
+            self.interrupted_at = Some(self.clock.now());
+        }
+
+        // The intervals of the heart rate variability and of the rhythm span minutes, so they are kept across a
+        // recalibration of the frontend, but not once the wrist has been lost or after a long interruption.
+        let interrupted_for = self
+            .interrupted_at
+            .map(|interrupted_at| self.clock.now().saturating_sub(interrupted_at));
+        if !transition.to.is_wrist_present()
+            || interrupted_for
+                .is_some_and(|interrupted_for| interrupted_for > Self::MAX_INTERRUPTION)
+        {
+            self.rhythm_detector.reset();
+            self.results.irregular_rhythm = false;
+            self.results.irregular_rhythm_confidence = 0.0;
+            self.hrv_analyser.reset();
+            self.spectral_hrv_analyser.reset();
+        }
+        if transition.to == MeasurementState::Measuring {
+            self.interrupted_at = None;
+        }
+    }
+
+    /// Detects the motion from the filtered data, with whether the reading of each channel has been clipped, and the
+    /// ambient light current, in amperes, logs when it starts and stops, and returns whether the sensor is moving.
+    fn motion(
+        &mut self,
+        filtered_data: FilteredData,
+        clipped: [bool; 3],
+        ambient_current: f32,
+        output: &mut PipelineOutput,
+    ) -> bool {
+        let motion =
+            self.motion_detector
+                .push(filtered_data, clipped, ambient_current, output.timestamp);
+        if motion.moving != self.results.motion {
+            if motion.moving {
+                log::warn!(
+                    "Motion detected: channel disagreement {}, DC jump {}, AC burst {}, ambient variation {}",
+                    motion.channel_disagreement,
+                    motion.dc_jump,
+                    motion.ac_burst,
+                    motion.ambient_variation
+                );
+            } else {
+                log::info!("Motion stopped.");
+                // The reference interval would be the median of the intervals measured while moving.
+                self.rr_editor.reset();
+            }
+        }
+
+        self.results.motion = motion.moving;
+        output.motion = Some(motion);
+
+        motion.moving
+    }
+
+    /// Assesses the heart rhythm with the given interval and logs the changes of rhythm.
+    fn rhythm(&mut self, timestamp: u128, interval: u128, output: &mut PipelineOutput) {
+        // The intervals are not edited, since the irregular ones would be corrected, but the ones that cannot be
+        // physiological are discarded.
+        let (min_interval, max_interval) = BeatDetector::INTERVAL_RANGE;
+        if !(min_interval..=max_interval).contains(&interval) {
+            return;
+        }
+
+        output.rhythm = self.rhythm_detector.push(timestamp, interval);
+        if let Some(rhythm) = output.rhythm {
+            if rhythm.irregular != self.results.irregular_rhythm {
+                if rhythm.irregular {
+                    log::warn!(
+                        "Irregular rhythm detected: confidence {}, nRMSSD {}, entropy {}, TPR {}",
+                        rhythm.confidence,
+                        rhythm.normalised_rmssd,
+                        rhythm.entropy,
+                        rhythm.turning_point_ratio
+                    );
+                } else {
+                    log::info!("Regular rhythm: confidence {}", rhythm.confidence);
+                }
+            }
+
+            self.results.irregular_rhythm = rhythm.irregular;
+            self.results.irregular_rhythm_confidence = rhythm.confidence;
+        }
+    }
+
+    /// Updates the perfusion indices of LED2 and LED3 with the selected method.
+    fn perfusion_indices(
+        &mut self,
+        filtered_data: &FilteredData,
+        pulse_amplitudes: Option<[PulseAmplitude; 3]>,
+    ) {
+        let (red_ac_amplitude, red_dc_amplitude, ir_ac_amplitude, ir_dc_amplitude) = (
+            self.red_deviation.push(filtered_data[1].1),
+            filtered_data[1].0,
+            self.ir_deviation.push(filtered_data[2].1),
+            filtered_data[2].0,
+        );
+
+        match self.perfusion_index_method {
+            PerfusionIndexMethod::Rms => {
+                self.results.red_pi = red_ac_amplitude / red_dc_amplitude * 100.0;
+                self.results.ir_pi = ir_ac_amplitude / ir_dc_amplitude * 100.0;
+            }
+            // The perfusion indices keep their value between the beats.
+            PerfusionIndexMethod::PeakToPeak => {
+                if let Some(amplitudes) = pulse_amplitudes {
+                    self.results.red_pi = amplitudes[1].perfusion_index();
+                    self.results.ir_pi = amplitudes[2].perfusion_index();
+                }
+            }
+        }
+    }
+
+    /// Updates the R value and the averaged SpO2 at every clean beat, or when the beats get older than the window.
+    fn blood_oxygen_saturation(
+        &mut self,
+        pulse_amplitudes: Option<[PulseAmplitude; 3]>,
+        output: &mut PipelineOutput,
+    ) {
+        output.spo2 = match pulse_amplitudes {
+            Some(amplitudes) => Some(self.spo2_estimator.push(output.timestamp, &amplitudes)),
+            None => self.spo2_estimator.update(output.timestamp),
+        };
+
+        if let Some(estimate) = output.spo2 {
+            if estimate.spo2.is_none() && self.results.spo2.is_some() {
+                log::warn!(
+                    "SpO2 invalid: R {}, confidence {}",
+                    estimate.r,
+                    estimate.confidence
+                );
+            }
+            // The average restarts once the SpO2 is valid again, so that it does not mix values from before the
+            // interruption.
+            self.results.spo2 = match estimate.spo2 {
+                Some(spo2) => Some(self.spo2_averager.push(output.timestamp, spo2)),
+                None => {
+                    self.spo2_averager.reset();
+                    None
+                }
+            };
+            self.results.spo2_confidence = estimate.confidence;
+            self.results.r = estimate.r;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use uom::si::time::millisecond;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        synthetic::{PpgConfiguration, SyntheticPpg},
+    };
+
+    /// Runs a synthetic session of `duration` seconds through a pipeline set up by `configure`, and returns the
+    /// outputs of the samples taken while measuring.
+    pub(crate) fn measure(
+        configuration: PpgConfiguration,
+        duration: f32,
+        configure: impl FnOnce(&mut VitalSignsPipeline<ManualClock>),
+    ) -> Vec<PipelineOutput> {
+        let clock = ManualClock::new();
+        let mut pipeline = VitalSignsPipeline::new(OffsetCurrents::new(), clock.clone());
+        configure(&mut pipeline);
+
+        let mut ppg = SyntheticPpg::new(configuration);
+        let mut outputs = vec![];
+        while ppg.time().get::<second>() < duration {
+            clock.set(ppg.time().get::<millisecond>().round() as u128);
+            let raw_data = ppg.next().unwrap();
+            let output = pipeline.process(raw_data);
+            if output.state == MeasurementState::Measuring {
+                outputs.push(output);
+            }
+        }
+
+        outputs
+    }
+
+    #[test]
+    fn synthetic_session_reports_heart_rate_and_spo2() {
+        let configuration = PpgConfiguration {
+            heart_rate: 72.0,
+            spo2: 95.0,
+            ..Default::default()
+        };
+        let outputs = measure(configuration, 60.0, |_| {});
+
+        let heart_rate = outputs
+            .iter()
+            .rev()
+            .find_map(|output| output.heart_rate)
+            .unwrap();
+        assert!((heart_rate - 72.0).abs() < 3.0, "heart rate {}", heart_rate);
+        let spo2 = outputs.last().unwrap().results.spo2.unwrap();
+        assert!((spo2 - 95.0).abs() < 2.0, "SpO2 {}", spo2);
+    }
+
+    #[test]
+    fn heart_rate_variability_is_kept_across_a_recalibration() {
+        let clock = ManualClock::new();
+        let mut pipeline = VitalSignsPipeline::new(OffsetCurrents::new(), clock.clone());
+        pipeline.set_spectral_hrv_window(Time::new::<second>(120.0));
+        pipeline.set_spectral_hrv_period(Time::new::<second>(10.0));
+
+        let mut ppg = SyntheticPpg::new(PpgConfiguration::default());
+        let mut recalibrated = false;
+        let mut spectral_hrv_after_recalibration = None;
+        while ppg.time().get::<second>() < 170.0 {
+            clock.set(ppg.time().get::<millisecond>().round() as u128);
+            if !recalibrated && ppg.time().get::<second>() >= 140.0 {
+                assert_eq!(pipeline.state(), MeasurementState::Measuring);
+                pipeline.frontend_changed();
+                recalibrated = true;
+            }
+            let raw_data = ppg.next().unwrap();
+            let output = pipeline.process(raw_data);
+            if recalibrated && spectral_hrv_after_recalibration.is_none() {
+                spectral_hrv_after_recalibration = output.spectral_hrv;
+            }
+        }
+
+        // The 2 minutes of intervals would not have been collected again since the recalibration.
+        assert!(spectral_hrv_after_recalibration.is_some());
+    }
+}
@@ -1,19 +1,15 @@
-use std::{
-    sync::{Arc, Mutex, RwLock},
-    thread,
-    time::Duration,
-};
-
 use uom::si::f32::ElectricPotential;
 
+use crate::measurement::MeasurementState;
+
 /// This struct contains the raw readings from the frontend that will be sent to the application via notifications.
 /// All the voltages are expressed in microvolts.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct RawData {
-    pub(crate) ambient: ElectricPotential,
-    pub(crate) led1: ElectricPotential,
-    pub(crate) led2: ElectricPotential,
-    pub(crate) led3: ElectricPotential,
+    pub ambient: ElectricPotential,
+    pub led1: ElectricPotential,
+    pub led2: ElectricPotential,
+    pub led3: ElectricPotential,
 }
 
 impl RawData {
@@ -84,12 +80,12 @@ impl<'a> Iterator for RawDataIntoIterator<'a> {
 /// This data will be sent to the application via notifications.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct FilteredData {
-    pub(crate) led1: (f32, f32), // (dc, ac).
-    pub(crate) led2: (f32, f32), // (dc, ac).
-    pub(crate) led3: (f32, f32), // (dc, ac).
-    pub(crate) led1_threshold: f32,
-    pub(crate) led2_threshold: f32,
-    pub(crate) led3_threshold: f32,
+    pub led1: (f32, f32), // (dc, ac).
+    pub led2: (f32, f32), // (dc, ac).
+    pub led3: (f32, f32), // (dc, ac).
+    pub led1_threshold: f32,
+    pub led2_threshold: f32,
+    pub led3_threshold: f32,
 }
 
 impl FilteredData {
@@ -135,76 +131,17 @@ impl std::ops::IndexMut<usize> for FilteredData {
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Results {
-    pub(crate) wrist_presence: bool,
-    pub(crate) spo2: f32,
-    pub(crate) r: f32,
-    pub(crate) red_pi: f32,
-    pub(crate) ir_pi: f32,
-}
-
-/// This funtion should be called in a separate thread to send the readings from the AFE4404.
-pub fn notify_task(
-    ble_api: Arc<RwLock<crate::bluetooth::BluetoothAPI>>,
-    raw_data: Arc<Mutex<RawData>>,
-    filtered_data: Arc<Mutex<FilteredData>>,
-    results: Arc<Mutex<Results>>,
-) {
-    let mut notify_timer = super::timer::Timer::new(50);
-    loop {
-        thread::sleep(Duration::from_millis(10));
-
-        if notify_timer.is_expired() {
-            if let (Ok(ble_api), Ok(raw_data), Ok(filtered_data), Ok(results)) = (
-                ble_api.write(),
-                raw_data.lock(),
-                filtered_data.lock(),
-                results.lock(),
-            ) {
-                ble_api
-                    .sensor_data
-                    .raw_optical_data_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value(raw_data.serialise());
-                ble_api
-                    .sensor_data
-                    .filtered_optical_data_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value(filtered_data.serialise());
-                ble_api
-                    .results
-                    .wrist_presence_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value((results.wrist_presence as u8).to_le_bytes());
-                ble_api
-                    .results
-                    .blood_oxygen_saturation_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value((results.spo2).to_le_bytes());
-                ble_api
-                    .results
-                    .r
-                    .write()
-                    .unwrap()
-                    .set_value((results.r).to_le_bytes());
-                ble_api
-                    .results
-                    .led2_perfusion_index_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value((results.red_pi).to_le_bytes());
-                ble_api
-                    .results
-                    .led3_perfusion_index_characteristic
-                    .write()
-                    .unwrap()
-                    .set_value((results.ir_pi).to_le_bytes());
-
-                notify_timer.reset();
-            }
-        }
-    }
+    pub measurement_state: MeasurementState,
+    pub wrist_presence: bool,
+    pub spo2: Option<f32>,
+    pub spo2_confidence: f32,
+    pub r: f32,
+    pub red_pi: f32,
+    pub ir_pi: f32,
+    pub irregular_rhythm: bool,
+    pub irregular_rhythm_confidence: f32,
+    pub respiration_rate: f32,
+    pub respiration_rate_reliable: bool,
+    pub signal_quality: f32,
+    pub motion: bool,
 }
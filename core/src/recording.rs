@@ -0,0 +1,414 @@
+//! Recorded sessions: the frontend readings together with the frontend settings in effect when they were taken,
+//! so that they can be replayed through the [`VitalSignsPipeline`](crate::pipeline::VitalSignsPipeline).
+//!
+//! A recording can be stored in two formats:
+//!
+//! - **CSV**: one sample per line, see [`RecordedSample::to_csv`]. The measured offset currents are stored in a line
+//!   starting with `O: `, while sample lines can optionally start with `S: `. The markers are also accepted at the
+//!   start of the message of a firmware log line, `<level> (<ticks>) <tag>: <message>`, so that a firmware log can be
+//!   used directly as a recording. Lines without a marker are parsed as samples only if they start with a digit, every
+//!   other line (header, comments, other log lines) is ignored.
+//! - **Binary**: the [`BINARY_MAGIC`] bytes, the 31 measured offset currents in µA as little-endian `f32`, then one
+//!   [`RecordedSample::serialise`] record per sample.
+
+use std::io::{BufRead, Read};
+
+use uom::si::{
+    electric_current::{microampere, milliampere},
+    electric_potential::volt,
+    f32::{ElectricCurrent, ElectricPotential},
+};
+
+use crate::{calibration::offset_measuring::OffsetCurrents, protocol::RawData};
+
+/// The bytes at the beginning of a binary recording.
+pub const BINARY_MAGIC: [u8; 4] = *b"PLR1";
+
+/// The header of a CSV recording.
+pub const CSV_HEADER: &str = "timestamp_ms,ambient_v,led1_v,led2_v,led3_v,led1_current_ma,led2_current_ma,led3_current_ma,led1_offset_ua,led2_offset_ua,led3_offset_ua";
+
+/// The marker of a CSV line that contains a sample.
+pub const SAMPLE_MARKER: &str = "S: ";
+
+/// The marker of a CSV line that contains the measured offset currents.
+pub const OFFSET_CURRENTS_MARKER: &str = "O: ";
+
+/// An error that occurred while reading a recording.
+#[derive(Debug)]
+pub enum RecordingError {
+    /// The recording could not be read.
+    Io(std::io::Error),
+    /// A line of a CSV recording is malformed. The line number starts from 1.
+    Csv { line: usize, message: String },
+    /// A binary recording is malformed.
+    Binary(String),
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::Io(error) => write!(f, "I/O error: {}", error),
+            RecordingError::Csv { line, message } => write!(f, "line {}: {}", line, message),
+            RecordingError::Binary(message) => write!(f, "malformed binary recording: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(error: std::io::Error) -> Self {
+        RecordingError::Io(error)
+    }
+}
+
+/// A frontend reading with the LED and offset currents that were set when it was taken.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RecordedSample {
+    /// The timestamp of the reading, in milliseconds.
+    pub timestamp: u128,
+    pub raw_data: RawData,
+    /// The currents of LED1, LED2 and LED3.
+    pub led_currents: [ElectricCurrent; 3],
+    /// The offset currents of LED1, LED2 and LED3.
+    pub offset_currents: [ElectricCurrent; 3],
+}
+
+impl RecordedSample {
+    /// The length of a serialised sample.
+    pub const SERIALISED_LENGTH: usize = 48;
+
+    /// Returns the sample as a CSV line, without the marker and with the columns of [`CSV_HEADER`].
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.raw_data.ambient.get::<volt>(),
+            self.raw_data.led1.get::<volt>(),
+            self.raw_data.led2.get::<volt>(),
+            self.raw_data.led3.get::<volt>(),
+            self.led_currents[0].get::<milliampere>(),
+            self.led_currents[1].get::<milliampere>(),
+            self.led_currents[2].get::<milliampere>(),
+            self.offset_currents[0].get::<microampere>(),
+            self.offset_currents[1].get::<microampere>(),
+            self.offset_currents[2].get::<microampere>(),
+        )
+    }
+
+    /// Parses a CSV line with the columns of [`CSV_HEADER`], without the marker.
+    pub fn from_csv(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+        if fields.len() != 11 {
+            return Err(format!("expected 11 fields, found {}", fields.len()));
+        }
+
+        let timestamp = fields[0]
+            .parse::<u128>()
+            .map_err(|e| format!("invalid timestamp '{}': {}", fields[0], e))?;
+        let mut values = [0.0; 10];
+        for (value, field) in values.iter_mut().zip(&fields[1..]) {
+            *value = field
+                .parse::<f32>()
+                .map_err(|e| format!("invalid value '{}': {}", field, e))?;
+        }
+
+        Ok(Self::from_values(timestamp, &values))
+    }
+
+    /// Returns the sample as little-endian bytes: the timestamp as `u64`, then the values of [`CSV_HEADER`] as `f32`.
+    pub fn serialise(&self) -> [u8; Self::SERIALISED_LENGTH] {
+        let mut data = [0; Self::SERIALISED_LENGTH];
+
+        data[0..8].copy_from_slice(&(self.timestamp as u64).to_le_bytes());
+        for (i, value) in self.values().iter().enumerate() {
+            data[8 + i * 4..12 + i * 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        data
+    }
+
+    /// Parses a sample serialised with [`RecordedSample::serialise`].
+    pub fn deserialise(data: &[u8; Self::SERIALISED_LENGTH]) -> Self {
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&data[0..8]);
+
+        let mut values = [0.0; 10];
+        for (i, value) in values.iter_mut().enumerate() {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[8 + i * 4..12 + i * 4]);
+            *value = f32::from_le_bytes(bytes);
+        }
+
+        Self::from_values(u64::from_le_bytes(timestamp) as u128, &values)
+    }
+
+    fn values(&self) -> [f32; 10] {
+        [
+            self.raw_data.ambient.get::<volt>(),
+            self.raw_data.led1.get::<volt>(),
+            self.raw_data.led2.get::<volt>(),
+            self.raw_data.led3.get::<volt>(),
+            self.led_currents[0].get::<milliampere>(),
+            self.led_currents[1].get::<milliampere>(),
+            self.led_currents[2].get::<milliampere>(),
+            self.offset_currents[0].get::<microampere>(),
+            self.offset_currents[1].get::<microampere>(),
+            self.offset_currents[2].get::<microampere>(),
+        ]
+    }
+
+    fn from_values(timestamp: u128, values: &[f32; 10]) -> Self {
+        Self {
+            timestamp,
+            raw_data: RawData {
+                ambient: ElectricPotential::new::<volt>(values[0]),
+                led1: ElectricPotential::new::<volt>(values[1]),
+                led2: ElectricPotential::new::<volt>(values[2]),
+                led3: ElectricPotential::new::<volt>(values[3]),
+            },
+            led_currents: [
+                ElectricCurrent::new::<milliampere>(values[4]),
+                ElectricCurrent::new::<milliampere>(values[5]),
+                ElectricCurrent::new::<milliampere>(values[6]),
+            ],
+            offset_currents: [
+                ElectricCurrent::new::<microampere>(values[7]),
+                ElectricCurrent::new::<microampere>(values[8]),
+                ElectricCurrent::new::<microampere>(values[9]),
+            ],
+        }
+    }
+}
+
+/// Returns the measured offset currents as a CSV line of values in µA, without the marker.
+pub fn offset_currents_to_csv(offset_currents: &OffsetCurrents) -> String {
+    offset_currents
+        .currents()
+        .iter()
+        .map(|current| current.get::<microampere>().to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A recorded session.
+pub struct Recording {
+    /// The offset currents measured on the device. The nominal values are used if the recording does not contain them.
+    pub offset_currents: OffsetCurrents,
+    pub samples: Vec<RecordedSample>,
+}
+
+impl Recording {
+    /// Reads a CSV recording or a firmware log.
+    pub fn from_csv<R: BufRead>(reader: R) -> Result<Self, RecordingError> {
+        let mut recording = Recording {
+            offset_currents: OffsetCurrents::new(),
+            samples: Vec::new(),
+        };
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let error = |message| RecordingError::Csv {
+                line: i + 1,
+                message,
+            };
+
+            let message = log_message(&line).unwrap_or(&line);
+            if let Some(fields) = message.strip_prefix(OFFSET_CURRENTS_MARKER) {
+                let mut currents: [ElectricCurrent; 31] = Default::default();
+                let fields: Vec<&str> = fields.trim().split(',').collect();
+                if fields.len() != currents.len() {
+                    return Err(error(format!(
+                        "expected {} offset currents, found {}",
+                        currents.len(),
+                        fields.len()
+                    )));
+                }
+                for (current, field) in currents.iter_mut().zip(fields) {
+                    let value = field
+                        .trim()
+                        .parse::<f32>()
+                        .map_err(|e| error(format!("invalid offset current '{}': {}", field, e)))?;
+                    *current = ElectricCurrent::new::<microampere>(value);
+                }
+                recording.offset_currents = OffsetCurrents::from_currents(currents);
+            } else if let Some(fields) = message.strip_prefix(SAMPLE_MARKER) {
+                let sample = RecordedSample::from_csv(fields).map_err(error)?;
+                recording.samples.push(sample);
+            } else if line.starts_with(|c: char| c.is_ascii_digit()) {
+                recording
+                    .samples
+                    .push(RecordedSample::from_csv(&line).map_err(error)?);
+            }
+        }
+
+        Ok(recording)
+    }
+
+    /// Reads a binary recording.
+    pub fn from_binary<R: Read>(mut reader: R) -> Result<Self, RecordingError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let header_length = BINARY_MAGIC.len() + 31 * 4;
+        if data.len() < header_length || data[0..BINARY_MAGIC.len()] != BINARY_MAGIC {
+            return Err(RecordingError::Binary("missing header".to_string()));
+        }
+
+        let mut currents: [ElectricCurrent; 31] = Default::default();
+        for (current, bytes) in currents
+            .iter_mut()
+            .zip(data[BINARY_MAGIC.len()..header_length].as_chunks::<4>().0)
+        {
+            *current = ElectricCurrent::new::<microampere>(f32::from_le_bytes(*bytes));
+        }
+
+        let records = &data[header_length..];
+        if records.len() % RecordedSample::SERIALISED_LENGTH != 0 {
+            return Err(RecordingError::Binary(format!(
+                "{} trailing bytes",
+                records.len() % RecordedSample::SERIALISED_LENGTH
+            )));
+        }
+
+        let samples = records
+            .as_chunks::<{ RecordedSample::SERIALISED_LENGTH }>()
+            .0
+            .iter()
+            .map(RecordedSample::deserialise)
+            .collect();
+
+        Ok(Recording {
+            offset_currents: OffsetCurrents::from_currents(currents),
+            samples,
+        })
+    }
+
+    /// Returns the header of a binary recording with the given offset currents.
+    pub fn binary_header(offset_currents: &OffsetCurrents) -> Vec<u8> {
+        let mut data = BINARY_MAGIC.to_vec();
+        for current in offset_currents.currents() {
+            data.extend_from_slice(&current.get::<microampere>().to_le_bytes());
+        }
+
+        data
+    }
+}
+
+/// Gets the message of a firmware log line, `<level> (<ticks>) <tag>: <message>`, possibly wrapped in ANSI colour
+/// codes. Returns `None` if the line is not a log line.
+fn log_message(line: &str) -> Option<&str> {
+    let line = match line.strip_prefix("\x1b[") {
+        Some(colour) => colour.split_once('m')?.1,
+        None => line,
+    };
+    let line = line.strip_suffix("\x1b[0m").unwrap_or(line);
+
+    let rest = line
+        .strip_prefix(|c| matches!(c, 'E' | 'W' | 'I' | 'D' | 'V'))?
+        .strip_prefix(" (")?;
+    let (ticks, rest) = rest.split_once(") ")?;
+    if ticks.is_empty() || !ticks.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (tag, message) = rest.split_once(": ")?;
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<RecordedSample> {
+        (0..10)
+            .map(|i| RecordedSample {
+                timestamp: i as u128 * 30,
+                raw_data: RawData {
+                    ambient: ElectricPotential::new::<volt>(0.01),
+                    led1: ElectricPotential::new::<volt>(0.8 + 0.01 * (i as f32).sin()),
+                    led2: ElectricPotential::new::<volt>(0.7 - 0.002 * (i as f32).sin()),
+                    led3: ElectricPotential::new::<volt>(0.75 - 0.003 * (i as f32).sin()),
+                },
+                led_currents: [ElectricCurrent::new::<milliampere>(9.6); 3],
+                offset_currents: [ElectricCurrent::new::<microampere>(-2.8); 3],
+            })
+            .collect()
+    }
+
+    fn assert_same(a: &RecordedSample, b: &RecordedSample) {
+        assert_eq!(a.timestamp, b.timestamp);
+        // The currents are converted to and from their units, which rounds the last digit.
+        for (a, b) in a.values().iter().zip(b.values().iter()) {
+            assert!((a - b).abs() <= 1e-6 * b.abs(), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let samples = samples();
+        let mut csv = format!(
+            "{}{}\n{}\n",
+            OFFSET_CURRENTS_MARKER,
+            offset_currents_to_csv(&OffsetCurrents::new()),
+            CSV_HEADER
+        );
+        for sample in &samples {
+            csv += &format!("I (123) pulse_loop: {}{}\n", SAMPLE_MARKER, sample.to_csv());
+        }
+
+        let recording = Recording::from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(recording.samples.len(), samples.len());
+        for (read, written) in recording.samples.iter().zip(&samples) {
+            assert_same(read, written);
+        }
+    }
+
+    #[test]
+    fn markers_only_at_the_start_of_the_message() {
+        let sample = samples()[0];
+        let csv = format!(
+            "\x1b[0;32mI (10) pulse_loop::optical: {}{}\x1b[0m\n\
+             E (20) pulse_loop: Peripheral error: {}{}\n\
+             D (30) pulse_loop: {}{}\n\
+             W (40) pulse_loop: Unexpected S: 1,2,3\n\
+             Comment with {}{}\n",
+            OFFSET_CURRENTS_MARKER,
+            offset_currents_to_csv(&OffsetCurrents::from_currents(
+                [ElectricCurrent::new::<microampere>(1.0); 31]
+            )),
+            OFFSET_CURRENTS_MARKER,
+            "1,2,3",
+            SAMPLE_MARKER,
+            sample.to_csv(),
+            SAMPLE_MARKER,
+            sample.to_csv(),
+        );
+
+        let recording = Recording::from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(recording.samples.len(), 1);
+        assert_same(&recording.samples[0], &sample);
+        assert_eq!(
+            recording.offset_currents.currents()[0],
+            ElectricCurrent::new::<microampere>(1.0)
+        );
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let samples = samples();
+        let mut data = Recording::binary_header(&OffsetCurrents::new());
+        for sample in &samples {
+            data.extend_from_slice(&sample.serialise());
+        }
+
+        let recording = Recording::from_binary(data.as_slice()).unwrap();
+        for (read, written) in recording.samples.iter().zip(&samples) {
+            assert_same(read, written);
+        }
+        assert!(Recording::from_binary(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::second};
+
+use super::HeartRateEstimate;
+
+/// Estimates the heart rate from the periodicity of the AC signal, with the normalised autocorrelation of a sliding
+/// window. Unlike the peak detection, it is not affected by the dicrotic notch and it is robust to uncorrelated noise.
+pub struct AutocorrelationEstimator {
+    samples: VecDeque<f32>,
+    window_length: usize,
+    hop_length: usize,
+    samples_since_estimate: usize,
+    min_lag: usize,
+    max_lag: usize,
+    sample_period: Time,
+}
+
+impl AutocorrelationEstimator {
+    /// The minimum heart rate that can be estimated, in bpm.
+    pub const MIN_HEART_RATE: f32 = 40.0;
+    /// The maximum heart rate that can be estimated, in bpm.
+    pub const MAX_HEART_RATE: f32 = 200.0;
+    /// The peaks of the autocorrelation at least this fraction of the highest one are candidates for the period,
+    /// and the shortest lag is chosen, so that the multiples of the period are not mistaken for it.
+    pub const PEAK_RATIO: f32 = 0.8;
+
+    /// Creates a new `AutocorrelationEstimator` that estimates the heart rate every `hop` over the last `window`
+    /// of samples, taken every `sample_period`.
+    pub fn new(window: Time, hop: Time, sample_period: Time) -> Self {
+        let samples_in = |time: Time| (time / sample_period).value.round() as usize;
+        let window_length = samples_in(window).max(1);
+        let min_lag = samples_in(Time::new::<second>(60.0 / Self::MAX_HEART_RATE)).max(1);
+
+        Self {
+            samples: VecDeque::with_capacity(window_length),
+            window_length,
+            hop_length: samples_in(hop).max(1),
+            samples_since_estimate: 0,
+            min_lag,
+            // At least two periods are needed in the window for a meaningful autocorrelation.
+            max_lag: samples_in(Time::new::<second>(60.0 / Self::MIN_HEART_RATE))
+                .min(window_length / 2)
+                .max(min_lag + 2),
+            sample_period,
+        }
+    }
+
+    /// Pushes a new sample and returns the estimate when the window is full and a hop has elapsed.
+    pub fn push(&mut self, sample: f32) -> Option<HeartRateEstimate> {
+        if self.samples.len() == self.window_length {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.samples_since_estimate += 1;
+
+        if self.samples.len() == self.window_length
+            && self.samples_since_estimate >= self.hop_length
+        {
+            self.samples_since_estimate = 0;
+            self.estimate()
+        } else {
+            None
+        }
+    }
+
+    /// Discards the samples, e.g. after a change of the frontend settings.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.samples_since_estimate = 0;
+    }
+
+    /// Estimates the heart rate from the samples in the window.
+    fn estimate(&self) -> Option<HeartRateEstimate> {
+        let mean = self.samples.iter().sum::<f32>() / self.samples.len() as f32;
+        let window: Vec<f32> = self.samples.iter().map(|sample| sample - mean).collect();
+
+        // The autocorrelation is computed one lag beyond the range, so that the peaks at its edges can be found.
+        let autocorrelation: Vec<f32> = (0..=self.max_lag + 1)
+            .map(|lag| normalised_autocorrelation(&window, lag))
+            .collect();
+
+        // Find the local maxima within the heart rate range.
+        let peaks: Vec<usize> = (self.min_lag..=self.max_lag)
+            .filter(|&lag| {
+                autocorrelation[lag] > 0.0
+                    && autocorrelation[lag] >= autocorrelation[lag - 1]
+                    && autocorrelation[lag] > autocorrelation[lag + 1]
+            })
+            .collect();
+        let highest = peaks
+            .iter()
+            .map(|&lag| autocorrelation[lag])
+            .fold(0.0, f32::max);
+        let lag = *peaks
+            .iter()
+            .find(|&&lag| autocorrelation[lag] >= Self::PEAK_RATIO * highest)?;
+
+        // Refine the lag with a parabolic interpolation around the peak.
+        let (previous, peak, next) = (
+            autocorrelation[lag - 1],
+            autocorrelation[lag],
+            autocorrelation[lag + 1],
+        );
+        let curvature = previous - 2.0 * peak + next;
+        let offset = if curvature < 0.0 {
+            (0.5 * (previous - next) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        let period = (lag as f32 + offset) * self.sample_period;
+
+        Some(HeartRateEstimate {
+            heart_rate: 60.0 / period.get::<second>(),
+            confidence: peak.clamp(0.0, 1.0),
+        })
+    }
+}
+
+/// Computes the autocorrelation at the given lag, normalised with the energy of the overlapping parts, so that a
+/// periodic signal has an autocorrelation of 1 at its period, regardless of the lag.
+fn normalised_autocorrelation(window: &[f32], lag: usize) -> f32 {
+    if lag >= window.len() {
+        return 0.0;
+    }
+
+    let (head, tail) = (&window[..window.len() - lag], &window[lag..]);
+    let product: f32 = head.iter().zip(tail).map(|(a, b)| a * b).sum();
+    let energy = head.iter().map(|a| a * a).sum::<f32>() * tail.iter().map(|b| b * b).sum::<f32>();
+
+    if energy > 0.0 {
+        product / energy.sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::tests::measure,
+        signal_processing::tests::{sampled, sinusoid, white_noise},
+        synthetic::PpgConfiguration,
+    };
+
+    /// An estimator over 8 s windows, every second.
+    fn estimator() -> AutocorrelationEstimator {
+        AutocorrelationEstimator::new(
+            Time::new::<second>(8.0),
+            Time::new::<second>(1.0),
+            Time::new::<second>(crate::SAMPLE_PERIOD),
+        )
+    }
+
+    fn estimates(samples: &[f32]) -> Vec<HeartRateEstimate> {
+        let mut estimator = estimator();
+        samples
+            .iter()
+            .filter_map(|&sample| estimator.push(sample))
+            .collect()
+    }
+
+    #[test]
+    fn estimates_once_the_window_is_full_and_every_hop() {
+        let mut estimator = estimator();
+        let samples = sampled(20.0, |time| sinusoid(72.0, time));
+        // The window is 267 samples long and the hop 33 samples.
+        let pushed: Vec<usize> = samples
+            .iter()
+            .enumerate()
+            .filter_map(|(n, &sample)| estimator.push(sample).map(|_| n))
+            .collect();
+        assert_eq!(pushed, (266..samples.len()).step_by(33).collect::<Vec<_>>());
+
+        // The window is filled again after a reset.
+        estimator.reset();
+        assert!(samples[..266]
+            .iter()
+            .all(|&sample| estimator.push(sample).is_none()));
+        assert!(estimator.push(samples[266]).is_some());
+    }
+
+    #[test]
+    fn sinusoids() {
+        for heart_rate in [45.0, 72.0, 120.0, 190.0] {
+            let estimates = estimates(&sampled(20.0, |time| sinusoid(heart_rate, time)));
+
+            assert_eq!(estimates.len(), 13);
+            for estimate in estimates {
+                assert!(
+                    (estimate.heart_rate - heart_rate).abs() < 1.0,
+                    "{:?} for {} bpm",
+                    estimate,
+                    heart_rate
+                );
+                assert!(estimate.confidence > 0.95, "{:?}", estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn second_harmonic_is_not_mistaken_for_the_fundamental() {
+        // The second harmonic is stronger than the fundamental, as with a pronounced dicrotic notch.
+        let pulse = |time: f32| sinusoid(60.0, time) + 1.5 * sinusoid(120.0, time + 0.1);
+        for estimate in estimates(&sampled(20.0, pulse)) {
+            assert!((estimate.heart_rate - 60.0).abs() < 1.0, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn flat_input_has_no_estimate() {
+        assert!(estimates(&sampled(20.0, |_| 1.0)).is_empty());
+    }
+
+    #[test]
+    fn white_noise_has_a_low_confidence() {
+        let estimates = estimates(&white_noise(2000));
+        assert!(!estimates.is_empty());
+        for estimate in estimates {
+            assert!(estimate.confidence < 0.3, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn estimates_the_synthetic_heart_rate() {
+        for heart_rate in [50.0, 75.0, 120.0] {
+            let outputs = measure(
+                PpgConfiguration {
+                    heart_rate,
+                    ..Default::default()
+                },
+                45.0,
+                |_| {},
+            );
+            let mut estimator = AutocorrelationEstimator::new(
+                Time::new::<second>(8.0),
+                Time::new::<second>(1.0),
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+            );
+            let estimates: Vec<HeartRateEstimate> = outputs
+                .iter()
+                .filter_map(|output| estimator.push(output.filtered_data?[0].1))
+                .collect();
+
+            assert!(estimates.len() > 20);
+            for estimate in estimates {
+                assert!(
+                    (estimate.heart_rate - heart_rate).abs() < 3.0,
+                    "{:?} for {} bpm",
+                    estimate,
+                    heart_rate
+                );
+                assert!(estimate.confidence > 0.5, "{:?}", estimate);
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{collections::VecDeque, convert::TryFrom};
+
+use uom::si::{
+    f32::Time,
+    time::{millisecond, second},
+};
+
+/// How the values of the averaging window are combined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AveragingMethod {
+    /// The mean of the values in the sliding window.
+    #[default]
+    Mean,
+    /// The median of the values in the sliding window, which ignores the isolated outliers.
+    Median,
+    /// The exponential moving average with a time constant of half the window, so that the values have the same
+    /// mean age as in the sliding window.
+    Exponential,
+}
+
+impl AveragingMethod {
+    pub fn serialise(&self) -> u8 {
+        match self {
+            AveragingMethod::Mean => 0,
+            AveragingMethod::Median => 1,
+            AveragingMethod::Exponential => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for AveragingMethod {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AveragingMethod::Mean),
+            1 => Ok(AveragingMethod::Median),
+            2 => Ok(AveragingMethod::Exponential),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The averaging of the SpO2 and of the heart rate, like the fast, normal and slow modes of the clinical oximeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Averaging {
+    pub method: AveragingMethod,
+    pub window: Time,
+}
+
+impl Averaging {
+    /// The window of the fast mode, in seconds.
+    pub const FAST: f32 = 4.0;
+    /// The window of the normal mode, in seconds.
+    pub const NORMAL: f32 = 8.0;
+    /// The window of the slow mode, in seconds.
+    pub const SLOW: f32 = 16.0;
+    /// The longest window that can be set, in seconds.
+    pub const MAX_WINDOW: f32 = 32.0;
+
+    /// Creates a new `Averaging` with the given method over the given window.
+    pub fn new(method: AveragingMethod, window: Time) -> Self {
+        Self { method, window }
+    }
+
+    pub fn serialise(&self) -> [u8; 2] {
+        [
+            self.method.serialise(),
+            self.window.get::<second>().round() as u8,
+        ]
+    }
+
+    /// Reads an averaging serialised with [`Averaging::serialise`], the method and the window in seconds. Returns
+    /// `None` if the method is unknown or the window is not between 1 s and [`Averaging::MAX_WINDOW`].
+    pub fn deserialise(data: &[u8]) -> Option<Self> {
+        match data {
+            [method, window, ..] if *window >= 1 && *window as f32 <= Self::MAX_WINDOW => {
+                Some(Self::new(
+                    AveragingMethod::try_from(*method).ok()?,
+                    Time::new::<second>(*window as f32),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Averaging {
+    fn default() -> Self {
+        Self::new(
+            AveragingMethod::default(),
+            Time::new::<second>(Self::NORMAL),
+        )
+    }
+}
+
+/// Averages the values of a vital sign, which are not evenly spaced in time, with the selected [`Averaging`].
+/// The sliding window and the exponential average are both kept up to date, so that the method can be changed
+/// without restarting the average.
+pub struct Averager {
+    method: AveragingMethod,
+    window: u128,
+    values: VecDeque<(u128, f32)>,
+    exponential: Option<(u128, f32)>,
+}
+
+impl Averager {
+    /// Creates a new `Averager` with the given averaging.
+    pub fn new(averaging: Averaging) -> Self {
+        Self {
+            method: averaging.method,
+            window: averaging.window.get::<millisecond>().round() as u128,
+            values: VecDeque::new(),
+            exponential: None,
+        }
+    }
+
+    /// Gets the averaging of the values.
+    pub fn averaging(&self) -> Averaging {
+        Averaging::new(self.method, Time::new::<millisecond>(self.window as f32))
+    }
+
+    /// Sets the averaging of the values, used from the next value.
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        self.method = averaging.method;
+        self.window = averaging.window.get::<millisecond>().round() as u128;
+    }
+
+    /// Discards all the values, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.values.clear();
+        self.exponential = None;
+    }
+
+    /// Pushes a value at `timestamp` and returns the average.
+    pub fn push(&mut self, timestamp: u128, value: f32) -> f32 {
+        self.values.push_back((timestamp, value));
+        while let Some(&(oldest, _)) = self.values.front() {
+            if timestamp.saturating_sub(oldest) <= self.window {
+                break;
+            }
+            self.values.pop_front();
+        }
+
+        // The weight of the new value depends on the time elapsed since the previous one.
+        let time_constant = (self.window as f32 / 2.0).max(1.0);
+        let exponential = match self.exponential {
+            Some((previous, average)) => {
+                let elapsed = timestamp.saturating_sub(previous) as f32;
+                average + (1.0 - (-elapsed / time_constant).exp()) * (value - average)
+            }
+            None => value,
+        };
+        self.exponential = Some((timestamp, exponential));
+
+        match self.method {
+            AveragingMethod::Mean => {
+                self.values.iter().map(|&(_, value)| value).sum::<f32>() / self.values.len() as f32
+            }
+            AveragingMethod::Median => {
+                let mut values: Vec<f32> = self.values.iter().map(|&(_, value)| value).collect();
+                values.sort_unstable_by(f32::total_cmp);
+                let middle = values.len() / 2;
+                if values.len() % 2 == 0 {
+                    (values[middle - 1] + values[middle]) / 2.0
+                } else {
+                    values[middle]
+                }
+            }
+            AveragingMethod::Exponential => exponential,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn averager(method: AveragingMethod, window: f32) -> Averager {
+        Averager::new(Averaging::new(method, Time::new::<second>(window)))
+    }
+
+    #[test]
+    fn mean_over_the_window() {
+        for window in [Averaging::FAST, Averaging::NORMAL, Averaging::SLOW] {
+            let mut averager = averager(AveragingMethod::Mean, window);
+            for seconds in 0..40u128 {
+                let mean = averager.push(seconds * 1000, seconds as f32);
+                // The values of the last `window` seconds, both ends included.
+                let expected = (seconds as f32 - window / 2.0).max(seconds as f32 / 2.0);
+                assert_eq!(mean, expected, "at {} s over {} s", seconds, window);
+            }
+        }
+    }
+
+    #[test]
+    fn median_over_the_window() {
+        for window in [Averaging::FAST, Averaging::NORMAL, Averaging::SLOW] {
+            let mut averager = averager(AveragingMethod::Median, window);
+            // A value every 500 ms, with an isolated outlier every 2 s.
+            for timestamp in (0..40_000).step_by(500) {
+                let value = if timestamp % 2000 == 1500 { 50.0 } else { 97.0 };
+                assert_eq!(averager.push(timestamp, value), 97.0, "at {} ms", timestamp);
+            }
+        }
+
+        // The median of an even number of values is the mean of the two middle ones.
+        let mut averager = averager(AveragingMethod::Median, Averaging::FAST);
+        averager.push(0, 1.0);
+        assert_eq!(averager.push(1000, 3.0), 2.0);
+    }
+
+    #[test]
+    fn exponential_depends_on_the_elapsed_time() {
+        for window in [Averaging::FAST, Averaging::NORMAL, Averaging::SLOW] {
+            // The time constant is half the window.
+            let time_constant = window * 1000.0 / 2.0;
+            for step in [250, 1000] {
+                let mut averager = averager(AveragingMethod::Exponential, window);
+                averager.push(0, 0.0);
+                let mut average = 0.0;
+                for timestamp in (step..=time_constant as u128).step_by(step as usize) {
+                    average = averager.push(timestamp, 1.0);
+                }
+                // The step response does not depend on how often the values are pushed.
+                assert!(
+                    (average - (1.0 - (-1f32).exp())).abs() < 1e-4,
+                    "{} every {} ms over {} s",
+                    average,
+                    step,
+                    window
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn old_values_are_evicted() {
+        let mut averager = averager(AveragingMethod::Mean, Averaging::FAST);
+        averager.push(0, 100.0);
+        for seconds in 1..=3 {
+            averager.push(seconds * 1000, 0.0);
+        }
+        // The first value is still in the window at its end.
+        assert_eq!(averager.push(4000, 0.0), 20.0);
+        assert_eq!(averager.push(4001, 0.0), 0.0);
+
+        // After a gap longer than the window, only the new value is left.
+        assert_eq!(averager.push(20_000, 42.0), 42.0);
+    }
+
+    #[test]
+    fn reset_discards_the_values() {
+        for method in [
+            AveragingMethod::Mean,
+            AveragingMethod::Median,
+            AveragingMethod::Exponential,
+        ] {
+            let mut averager = averager(method, Averaging::NORMAL);
+            for seconds in 0..10 {
+                averager.push(seconds * 1000, 90.0);
+            }
+            averager.reset();
+
+            assert_eq!(averager.push(10_000, 60.0), 60.0, "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn averaging_is_changed_mid_stream() {
+        let values = |timestamp: u128| if timestamp < 10_000 { 90.0 } else { 60.0 };
+        let mut exponential = averager(AveragingMethod::Exponential, Averaging::NORMAL);
+        let mut averager = averager(AveragingMethod::Mean, Averaging::NORMAL);
+        for timestamp in (0..12_000).step_by(1000) {
+            averager.push(timestamp, values(timestamp));
+            exponential.push(timestamp, values(timestamp));
+        }
+
+        // The exponential average has been kept up to date.
+        let averaging = Averaging::new(
+            AveragingMethod::Exponential,
+            Time::new::<second>(Averaging::NORMAL),
+        );
+        averager.set_averaging(averaging);
+        assert_eq!(averager.averaging(), averaging);
+        assert_eq!(
+            averager.push(12_000, values(12_000)),
+            exponential.push(12_000, values(12_000))
+        );
+
+        // The sliding window has been kept up to date, and a shorter one evicts the older values at once.
+        averager.set_averaging(Averaging::new(
+            AveragingMethod::Median,
+            Time::new::<second>(Averaging::NORMAL),
+        ));
+        assert_eq!(averager.push(13_000, values(13_000)), 90.0);
+        averager.set_averaging(Averaging::new(
+            AveragingMethod::Median,
+            Time::new::<second>(Averaging::FAST),
+        ));
+        assert_eq!(averager.push(14_000, values(14_000)), 60.0);
+    }
+}
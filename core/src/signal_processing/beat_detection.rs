@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+/// A heart beat found by the [`BeatDetector`]. All the times are timestamps in milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Beat {
+    /// The start of the systolic upstroke, where the pulse has its minimum.
+    pub onset: u128,
+    /// The systolic peak, where the pulse has its maximum.
+    pub peak: u128,
+    /// The foot of the pulse, where the tangent at the steepest point of the upstroke crosses the minimum.
+    /// It is the most reliable fiducial point for the pulse arrival time.
+    pub foot: u128,
+    /// The difference between the pulse at its peak and at its onset.
+    pub amplitude: f32,
+    /// The interval from the peak of the previous beat, in milliseconds, if it has been detected.
+    pub interval: Option<u128>,
+    /// Whether the beat was missed by the threshold and has been found later by the search-back.
+    pub searched_back: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    timestamp: u128,
+    value: f32,
+    slope_sum: f32,
+}
+
+/// Detects the heart beats in a pulse signal, which rises with the blood volume, with its slope sum function:
+/// the sum of the rising slopes over a window as long as the systolic upstroke. A beat is detected when the slope
+/// sum crosses a threshold that adapts to the slope sum peaks of the previous beats, outside of a refractory period
+/// after the previous beat. If no beat is detected for much longer than the average interval, the missed beat is
+/// searched back with a lower threshold.
+pub struct BeatDetector {
+    history: VecDeque<Point>,
+    history_length: usize,
+    // The index of the first point of the history since the reset.
+    first_index: usize,
+    slope_window: usize,
+    learning_length: usize,
+
+    threshold: f32,
+    average_slope_sum_peak: f32,
+    average_interval: Option<f32>,
+
+    // The index of the threshold crossing whose peak has not been reached yet.
+    pending_crossing: Option<usize>,
+    last_beat: Option<(usize, Beat)>,
+    // The time of the last beat or of the last unsuccessful search-back.
+    last_search: u128,
+}
+
+impl BeatDetector {
+    /// The length of the slope sum window, in milliseconds, about the duration of the upstroke.
+    pub const SLOPE_WINDOW: f32 = 128.0;
+    /// The duration used to learn the initial threshold, in milliseconds.
+    pub const LEARNING_PERIOD: f32 = 2000.0;
+    /// The threshold, as a fraction of the average slope sum peak.
+    pub const THRESHOLD_RATIO: f32 = 0.5;
+    /// The weight of the latest beat in the average slope sum peak and in the average interval.
+    pub const AVERAGING_WEIGHT: f32 = 0.25;
+    /// The minimum interval between two beats, in milliseconds (240 bpm).
+    pub const REFRACTORY_PERIOD: u128 = 250;
+    /// The maximum duration of the upstroke after the threshold crossing, in milliseconds.
+    pub const MAX_RISE_TIME: u128 = 400;
+    /// The missed beats are searched back after this multiple of the average interval without beats.
+    pub const SEARCH_BACK_RATIO: f32 = 1.66;
+    /// The threshold of the search-back, as a fraction of the threshold.
+    pub const SEARCH_BACK_THRESHOLD_RATIO: f32 = 0.5;
+    /// The intervals outside this range, in milliseconds, do not update the average interval.
+    pub const INTERVAL_RANGE: (u128, u128) = (250, 2000);
+
+    /// Creates a new `BeatDetector` for a signal sampled every `sample_period`.
+    pub fn new(sample_period: Time) -> Self {
+        let samples_in = |milliseconds: f32| {
+            (milliseconds / sample_period.get::<millisecond>()).round() as usize
+        };
+
+        Self {
+            history: VecDeque::new(),
+            // Long enough to search back a beat after the longest interval.
+            history_length: samples_in(
+                Self::SEARCH_BACK_RATIO * Self::INTERVAL_RANGE.1 as f32
+                    + Self::MAX_RISE_TIME as f32,
+            )
+            .max(2),
+            first_index: 0,
+            slope_window: samples_in(Self::SLOPE_WINDOW).max(1),
+            learning_length: samples_in(Self::LEARNING_PERIOD).max(1),
+            threshold: 0.0,
+            average_slope_sum_peak: 0.0,
+            average_interval: None,
+            pending_crossing: None,
+            last_beat: None,
+            last_search: 0,
+        }
+    }
+
+    /// Gets the current threshold of the slope sum.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Gets the average interval between the beats, in milliseconds.
+    pub fn average_interval(&self) -> Option<f32> {
+        self.average_interval
+    }
+
+    /// Discards the history and the learnt threshold, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.first_index = 0;
+        self.threshold = 0.0;
+        self.average_slope_sum_peak = 0.0;
+        self.average_interval = None;
+        self.pending_crossing = None;
+        self.last_beat = None;
+        self.last_search = 0;
+    }
+
+    /// Pushes a new sample of the pulse, taken at `timestamp`, and returns the beat whose systolic peak has just
+    /// been passed or found by the search-back, if any.
+    pub fn push(&mut self, value: f32, timestamp: u128) -> Option<Beat> {
+        self.push_point(value, timestamp);
+        let index = self.end_index() - 1;
+        let samples = self.first_index + self.history.len();
+
+        if samples < self.learning_length {
+            return None;
+        }
+        if samples == self.learning_length {
+            // The initial threshold is learnt from the highest slope sum, which may not be a full upstroke.
+            self.average_slope_sum_peak = self
+                .history
+                .iter()
+                .map(|point| point.slope_sum)
+                .fold(0.0, f32::max);
+            self.threshold = Self::THRESHOLD_RATIO * self.average_slope_sum_peak;
+            return None;
+        }
+
+        if let Some(crossing) = self.pending_crossing {
+            // Wait for the systolic peak, where the pulse stops rising.
+            let rising = self.point(index).value >= self.point(index - 1).value;
+            if rising
+                && timestamp.saturating_sub(self.point(crossing).timestamp) < Self::MAX_RISE_TIME
+            {
+                return None;
+            }
+            self.pending_crossing = None;
+
+            return self.detect(crossing, false);
+        }
+
+        let crossed = self.point(index).slope_sum > self.threshold
+            && self.point(index - 1).slope_sum <= self.threshold;
+        if crossed && !self.is_refractory(timestamp) {
+            self.pending_crossing = Some(index);
+            return None;
+        }
+
+        self.search_back(timestamp)
+    }
+
+    /// Appends a point to the history, with the slope sum over the window ending at it.
+    fn push_point(&mut self, value: f32, timestamp: u128) {
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+            self.first_index += 1;
+        }
+
+        let slope = |a: &Point, b: f32| (b - a.value).max(0.0);
+        let mut slope_sum = self.history.back().map_or(0.0, |last| slope(last, value));
+        let len = self.history.len();
+        for i in len.saturating_sub(self.slope_window)..len.saturating_sub(1) {
+            slope_sum += slope(&self.history[i], self.history[i + 1].value);
+        }
+
+        self.history.push_back(Point {
+            timestamp,
+            value,
+            slope_sum,
+        });
+    }
+
+    /// Gets the point at the given index since the reset, which must be in the history.
+    fn point(&self, index: usize) -> &Point {
+        &self.history[index - self.first_index]
+    }
+
+    /// Gets the index after the last point.
+    fn end_index(&self) -> usize {
+        self.first_index + self.history.len()
+    }
+
+    /// Checks if the given time is too close to the peak of the last beat for a new beat.
+    fn is_refractory(&self, timestamp: u128) -> bool {
+        match self.last_beat {
+            Some((_, beat)) => timestamp.saturating_sub(beat.peak) < Self::REFRACTORY_PERIOD,
+            None => false,
+        }
+    }
+
+    /// Looks for a beat missed since the last one with a lower threshold, once the last beat is too old.
+    fn search_back(&mut self, timestamp: u128) -> Option<Beat> {
+        let (last_index, _) = self.last_beat?;
+        let average_interval = self.average_interval?;
+        if (timestamp.saturating_sub(self.last_search) as f32)
+            < Self::SEARCH_BACK_RATIO * average_interval
+        {
+            return None;
+        }
+        self.last_search = timestamp;
+
+        let threshold = Self::SEARCH_BACK_THRESHOLD_RATIO * self.threshold;
+        let start = (last_index + 1).max(self.first_index + 1);
+        let crossing = (start..self.end_index())
+            .filter(|&index| {
+                self.point(index).slope_sum > threshold
+                    && self.point(index - 1).slope_sum <= threshold
+                    && !self.is_refractory(self.point(index).timestamp)
+            })
+            .max_by(|&a, &b| self.slope_sum_peak(a).total_cmp(&self.slope_sum_peak(b)));
+
+        match crossing {
+            Some(crossing) => self.detect(crossing, true),
+            None => {
+                // The amplitude of the pulse has probably dropped.
+                self.average_slope_sum_peak *= Self::SEARCH_BACK_THRESHOLD_RATIO;
+                self.threshold = Self::THRESHOLD_RATIO * self.average_slope_sum_peak;
+                None
+            }
+        }
+    }
+
+    /// Gets the highest slope sum of the upstroke that crosses the threshold at the given index.
+    fn slope_sum_peak(&self, crossing: usize) -> f32 {
+        (crossing..self.end_index())
+            .take_while(|&index| {
+                self.point(index)
+                    .timestamp
+                    .saturating_sub(self.point(crossing).timestamp)
+                    <= Self::MAX_RISE_TIME
+            })
+            .map(|index| self.point(index).slope_sum)
+            .fold(0.0, f32::max)
+    }
+
+    /// Finds the fiducial points of the upstroke that crosses the threshold at the given index, and adapts the
+    /// threshold to it.
+    fn detect(&mut self, crossing: usize, searched_back: bool) -> Option<Beat> {
+        // The peak is the end of the upstroke, the onset is its start.
+        let mut peak = crossing;
+        while peak + 1 < self.end_index() && self.point(peak + 1).value >= self.point(peak).value {
+            peak += 1;
+        }
+        let mut onset = crossing;
+        while onset > self.first_index && self.point(onset - 1).value < self.point(onset).value {
+            onset -= 1;
+        }
+        if self.is_refractory(self.point(peak).timestamp) {
+            return None;
+        }
+
+        // The foot is where the tangent at the steepest point crosses the horizontal line through the onset.
+        let (onset_point, peak_point) = (*self.point(onset), *self.point(peak));
+        let steepest = (onset + 1..=peak).max_by(|&a, &b| {
+            let slope = |index: usize| self.point(index).value - self.point(index - 1).value;
+            slope(a).total_cmp(&slope(b))
+        });
+        let foot = match steepest {
+            Some(steepest) => {
+                let (previous, point) = (self.point(steepest - 1), self.point(steepest));
+                let slope = (point.value - previous.value)
+                    / point.timestamp.saturating_sub(previous.timestamp).max(1) as f32;
+                let offset = (point.value - onset_point.value) / slope;
+                (point.timestamp as f32 - offset)
+                    .clamp(onset_point.timestamp as f32, point.timestamp as f32)
+                    .round() as u128
+            }
+            None => onset_point.timestamp,
+        };
+
+        let interval = self
+            .last_beat
+            .map(|(_, last_beat)| peak_point.timestamp.saturating_sub(last_beat.peak));
+        let beat = Beat {
+            onset: onset_point.timestamp,
+            peak: peak_point.timestamp,
+            foot,
+            amplitude: peak_point.value - onset_point.value,
+            interval,
+            searched_back,
+        };
+
+        // Adapt the threshold and the average interval.
+        let weight = Self::AVERAGING_WEIGHT;
+        self.average_slope_sum_peak =
+            (1.0 - weight) * self.average_slope_sum_peak + weight * self.slope_sum_peak(crossing);
+        self.threshold = Self::THRESHOLD_RATIO * self.average_slope_sum_peak;
+        if let Some(interval) = interval
+            .filter(|interval| (Self::INTERVAL_RANGE.0..=Self::INTERVAL_RANGE.1).contains(interval))
+        {
+            let interval = interval as f32;
+            self.average_interval = Some(match self.average_interval {
+                Some(average_interval) => (1.0 - weight) * average_interval + weight * interval,
+                None => interval,
+            });
+        }
+        self.last_beat = Some((peak, beat));
+        self.last_search = beat.peak;
+
+        Some(beat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PERIOD: u128 = 30;
+    const INTERVAL: u128 = 900;
+    /// A pulse starting at its onset, sampled every 30 ms: a slow then a steep upstroke up to the peak after
+    /// 150 ms, and a linear decay back to the baseline.
+    const PULSE: [f32; 21] = [
+        0.0, 0.05, 0.1, 0.4, 0.7, 1.0, 0.93, 0.86, 0.79, 0.72, 0.65, 0.58, 0.51, 0.44, 0.37, 0.3,
+        0.23, 0.16, 0.09, 0.02, 0.0,
+    ];
+
+    /// Gets the pulse at the given time since its onset, scaled by `amplitude`.
+    fn pulse(time: u128, amplitude: f32) -> f32 {
+        PULSE
+            .get((time / SAMPLE_PERIOD) as usize)
+            .map_or(0.0, |value| value * amplitude)
+    }
+
+    /// Feeds a pulse for each of the given amplitudes, with `extra` added at each time since its onset, and returns
+    /// the detected beats.
+    fn detect(amplitudes: &[f32], extra: impl Fn(u128) -> f32) -> Vec<Beat> {
+        let mut detector = BeatDetector::new(Time::new::<millisecond>(SAMPLE_PERIOD as f32));
+        let duration = amplitudes.len() as u128 * INTERVAL;
+        (0..duration)
+            .step_by(SAMPLE_PERIOD as usize)
+            .filter_map(|timestamp| {
+                let time = timestamp % INTERVAL;
+                let amplitude = amplitudes[(timestamp / INTERVAL) as usize];
+                detector.push(pulse(time, amplitude) + extra(time), timestamp)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fiducial_points() {
+        let beats = detect(&[1.0; 20], |_| 0.0);
+        // The beats of the learning period are not detected.
+        assert!(beats.len() >= 17);
+
+        for beat in beats.iter().skip(1) {
+            let onset = beat.onset - beat.onset % INTERVAL;
+            assert_eq!(beat.onset, onset);
+            assert_eq!(beat.peak, onset + 150);
+            // The tangent of the steep upstroke, from 0.1 at 60 ms with a slope of 0.01 per ms, crosses 0 at 50 ms.
+            assert_eq!(beat.foot, onset + 50);
+            assert!((beat.amplitude - 1.0).abs() < 1e-6);
+            assert_eq!(beat.interval, Some(INTERVAL));
+            assert!(!beat.searched_back);
+        }
+    }
+
+    #[test]
+    fn refractory_period() {
+        // A notch that rises more steeply than the pulse, 120 ms after its peak.
+        let notch = |time: u128| match time {
+            300 => 0.5,
+            330..=480 => 1.0,
+            _ => 0.0,
+        };
+        let beats = detect(&[1.0; 20], notch);
+        // Only the first beat, without a previous one, can be a notch.
+        assert!(beats.len() >= 17);
+        assert!(beats.iter().skip(1).all(|beat| beat.peak % INTERVAL == 150));
+
+        // The same notch after the refractory period is detected as a beat.
+        let late_notch = |time: u128| notch(time.wrapping_sub(BeatDetector::REFRACTORY_PERIOD));
+        let beats = detect(&[1.0; 20], late_notch);
+        assert!(beats.iter().skip(1).any(|beat| beat.peak % INTERVAL != 150));
+    }
+
+    #[test]
+    fn search_back() {
+        let mut amplitudes = [1.0; 20];
+        amplitudes[10] = 0.35;
+        let beats = detect(&amplitudes, |_| 0.0);
+
+        let weak_beat = beats
+            .iter()
+            .find(|beat| beat.peak == 10 * INTERVAL + 150)
+            .expect("The weak beat has been missed.");
+        assert!(weak_beat.searched_back);
+        assert_eq!(weak_beat.interval, Some(INTERVAL));
+        assert!((weak_beat.amplitude - 0.35).abs() < 1e-6);
+        assert!(beats.iter().all(|beat| beat.peak % INTERVAL == 150));
+        assert!(beats
+            .iter()
+            .filter(|beat| beat.peak != 10 * INTERVAL + 150)
+            .all(|beat| !beat.searched_back));
+    }
+}
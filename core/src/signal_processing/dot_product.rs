@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+
+// Used to perform a dot product between a sine wave and a signal.
+// The sine wave is generated by rotating a phasor, so that no trigonometric function is computed for each sample,
+// and the dot product with the cosine wave is computed too, so that the magnitude does not depend on the phase.
+#[derive(Debug)]
+pub struct SineProduct {
+    pub sine_frequency: f32,
+    pub sampling_period: f32,
+    pub window_period: f32,
+    pub time: f32,
+    pub result: f32,
+    pub quadrature_result: f32,
+
+    pub s: f32,
+    pub c: f32,
+    step: (f32, f32), // The cosine and the sine of the phase increment.
+}
+
+impl SineProduct {
+    pub fn new(sine_frequency: f32, sampling_period: f32, window_period: f32) -> Self {
+        let mut sine_product = Self {
+            sine_frequency,
+            sampling_period,
+            window_period,
+            time: 0.0,
+            result: 0.0,
+            quadrature_result: 0.0,
+
+            s: 0.0,
+            c: 1.0,
+            step: (1.0, 0.0),
+        };
+        sine_product.reset(sine_frequency);
+
+        sine_product
+    }
+
+    /// Accumulates the product of the signal with the sine and the cosine waves.
+    /// Returns the magnitude of the dot product once the window period has elapsed.
+    pub fn process(&mut self, signal: f32) -> Option<f32> {
+        self.result += signal * self.s;
+        self.quadrature_result += signal * self.c;
+
+        // Rotate the phasor by one sample.
+        let (step_cos, step_sin) = self.step;
+        (self.c, self.s) = (
+            self.c * step_cos - self.s * step_sin,
+            self.s * step_cos + self.c * step_sin,
+        );
+        self.time += self.sampling_period;
+
+        // Half a sample of tolerance for the accumulated rounding of the time.
+        if self.time >= self.window_period - self.sampling_period / 2.0 {
+            Some(self.magnitude())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the magnitude of the dot product with the complex sine wave.
+    pub fn magnitude(&self) -> f32 {
+        (self.result.powi(2) + self.quadrature_result.powi(2)).sqrt()
+    }
+
+    pub fn reset(&mut self, sine_frequency: f32) {
+        let phase_step = 2.0 * PI * sine_frequency * self.sampling_period;
+
+        self.sine_frequency = sine_frequency;
+        self.time = 0.0;
+        self.result = 0.0;
+        self.quadrature_result = 0.0;
+        self.s = 0.0;
+        self.c = 1.0;
+        self.step = (phase_step.cos(), phase_step.sin());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLING_PERIOD: f32 = 0.03;
+    const WINDOW_PERIOD: f32 = 8.0;
+    /// The number of samples in the window.
+    const SAMPLES: usize = 267;
+
+    /// Processes a sinusoid at `frequency` with the given amplitude and phase, and returns the result of the
+    /// last sample.
+    fn process(
+        product: &mut SineProduct,
+        frequency: f32,
+        amplitude: f32,
+        phase: f32,
+    ) -> Option<f32> {
+        let mut result = None;
+        for n in 0..SAMPLES {
+            let time = n as f32 * SAMPLING_PERIOD;
+            result = product.process(amplitude * (2.0 * PI * frequency * time + phase).sin());
+            // The magnitude is returned only once the window period has elapsed.
+            assert_eq!(result.is_some(), n == SAMPLES - 1);
+        }
+        result
+    }
+
+    #[test]
+    fn magnitude_at_its_frequency() {
+        for phase in [0.0, 1.0, 2.0] {
+            let mut product = SineProduct::new(1.25, SAMPLING_PERIOD, WINDOW_PERIOD);
+            let magnitude = process(&mut product, 1.25, 2.0, phase).unwrap();
+
+            // The sum of the squared sine over the window.
+            let expected = 2.0 * SAMPLES as f32 / 2.0;
+            assert!(
+                (magnitude - expected).abs() < 0.02 * expected,
+                "{} at phase {}",
+                magnitude,
+                phase
+            );
+            assert_eq!(magnitude, product.magnitude());
+        }
+    }
+
+    #[test]
+    fn magnitude_off_its_frequency() {
+        // One period more over the window, where the dot product vanishes.
+        let mut product = SineProduct::new(1.25, SAMPLING_PERIOD, WINDOW_PERIOD);
+        let magnitude = process(&mut product, 1.25 + 1.0 / WINDOW_PERIOD, 2.0, 0.5).unwrap();
+        assert!(magnitude < 0.02 * SAMPLES as f32, "{}", magnitude);
+
+        // After a reset to the frequency of the signal, the full magnitude is found again.
+        product.reset(1.25 + 1.0 / WINDOW_PERIOD);
+        assert_eq!(product.magnitude(), 0.0);
+        let magnitude = process(&mut product, 1.25 + 1.0 / WINDOW_PERIOD, 2.0, 0.5).unwrap();
+        assert!((magnitude - SAMPLES as f32).abs() < 0.02 * SAMPLES as f32);
+    }
+}
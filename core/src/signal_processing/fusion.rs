@@ -0,0 +1,447 @@
+use uom::si::{f32::Time, time::second};
+
+use super::{
+    beat_detection::BeatDetector,
+    quality::{SignalQuality, SignalQualityAssessor},
+};
+use crate::protocol::FilteredData;
+
+/// The heart rate fused from the beats of LED1 (green), LED2 (red) and LED3 (IR).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FusedHeartRate {
+    /// The heart rate, in bpm.
+    pub heart_rate: f32,
+    /// The mean signal quality of the fused channels, weighted like their heart rates, from 0 to 1.
+    pub confidence: f32,
+    /// The heart rate of the latest beat of each channel, in bpm, if it is recent enough.
+    pub channel_heart_rates: [Option<f32>; 3],
+    /// The weight of each channel in the fused heart rate, its signal quality index from 0 to 1, or 0 if it has
+    /// been left out.
+    pub weights: [f32; 3],
+}
+
+/// The latest beat of a channel.
+#[derive(Debug, Clone, Copy)]
+struct ChannelBeat {
+    peak: u128,
+    heart_rate: f32,
+    quality: SignalQuality,
+    // Whether the interval is close to the average one of the channel.
+    consistent: bool,
+}
+
+/// Estimates the heart rate independently on the pulse of each channel, from the interval between its beats, and
+/// fuses the estimates weighted by the signal quality index of their beat. A channel whose ADC is saturated or
+/// whose pulse is too weak gets an index below [`SignalQualityAssessor::MIN_SQI`], or has no beat at all, so it is
+/// left out and the others take over.
+pub struct HeartRateFusion {
+    beat_detectors: [BeatDetector; 3],
+    quality_assessors: [SignalQualityAssessor; 3],
+    latest_beats: [Option<ChannelBeat>; 3],
+    pending: Option<u128>,
+}
+
+impl HeartRateFusion {
+    /// The time waited after a channel has detected a beat for the other channels to detect it too, in
+    /// milliseconds. It is shorter than the refractory period, so that it never covers the next beat.
+    pub const SYNC_DELAY: u128 = 200;
+    /// The time after its latest beat from which a channel is left out, in milliseconds.
+    pub const MAX_AGE: u128 = BeatDetector::INTERVAL_RANGE.1;
+    /// The relative difference from the weighted median, or of its interval from the average one of the channel,
+    /// above which a channel is left out, e.g. when its beat detector has locked on a harmonic or on a motion
+    /// artefact.
+    pub const MAX_DEVIATION: f32 = 0.2;
+
+    /// Creates a new `HeartRateFusion` for signals sampled every `sample_period`.
+    pub fn new(sample_period: Time) -> Self {
+        Self {
+            beat_detectors: [
+                BeatDetector::new(sample_period),
+                BeatDetector::new(sample_period),
+                BeatDetector::new(sample_period),
+            ],
+            quality_assessors: [
+                SignalQualityAssessor::new(sample_period, Time::new::<second>(8.0)),
+                SignalQualityAssessor::new(sample_period, Time::new::<second>(8.0)),
+                SignalQualityAssessor::new(sample_period, Time::new::<second>(8.0)),
+            ],
+            latest_beats: [None; 3],
+            pending: None,
+        }
+    }
+
+    /// Discards the beats of all the channels, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        for beat_detector in self.beat_detectors.iter_mut() {
+            beat_detector.reset();
+        }
+        for quality_assessor in self.quality_assessors.iter_mut() {
+            quality_assessor.reset();
+        }
+        self.latest_beats = [None; 3];
+        self.pending = None;
+    }
+
+    /// Pushes the filtered data taken at `timestamp`, with whether the reading of each channel has been clipped.
+    /// Returns the fused heart rate once a beat has been detected and the other channels have had the time to
+    /// detect it too, if a channel has a usable beat.
+    pub fn push(
+        &mut self,
+        filtered_data: FilteredData,
+        clipped: [bool; 3],
+        timestamp: u128,
+    ) -> Option<FusedHeartRate> {
+        for i in 0..3 {
+            let (dc, ac) = filtered_data[i];
+            // The photodiode current decreases when the blood volume increases.
+            self.quality_assessors[i].push(-ac, clipped[i], timestamp);
+            // The average interval is taken before it is updated with the new beat.
+            let average_interval = self.beat_detectors[i].average_interval();
+            let beat = match self.beat_detectors[i].push(-ac, timestamp) {
+                Some(beat) => beat,
+                None => continue,
+            };
+            let interval = match beat.interval {
+                Some(interval)
+                    if (BeatDetector::INTERVAL_RANGE.0..=BeatDetector::INTERVAL_RANGE.1)
+                        .contains(&interval) =>
+                {
+                    interval
+                }
+                _ => continue,
+            };
+            let perfusion_index = if dc.abs() > 0.0 {
+                beat.amplitude / dc.abs() * 100.0
+            } else {
+                0.0
+            };
+            if let Some(quality) = self.quality_assessors[i].assess(&beat, perfusion_index) {
+                self.latest_beats[i] = Some(ChannelBeat {
+                    peak: beat.peak,
+                    heart_rate: 60_000.0 / interval as f32,
+                    quality,
+                    consistent: average_interval.is_none_or(|average_interval| {
+                        (interval as f32 - average_interval).abs()
+                            <= Self::MAX_DEVIATION * average_interval
+                    }),
+                });
+                self.pending.get_or_insert(timestamp + Self::SYNC_DELAY);
+            }
+        }
+
+        match self.pending {
+            Some(pending) if timestamp >= pending => {
+                self.pending = None;
+                self.fuse(timestamp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Fuses the recent beats of the channels that agree with the weighted median.
+    fn fuse(&self, timestamp: u128) -> Option<FusedHeartRate> {
+        let mut channel_heart_rates = [None; 3];
+        let mut weights = [0.0; 3];
+        for (i, beat) in self.latest_beats.iter().enumerate() {
+            if let Some(beat) =
+                beat.filter(|beat| timestamp.saturating_sub(beat.peak) <= Self::MAX_AGE)
+            {
+                channel_heart_rates[i] = Some(beat.heart_rate);
+                if beat.consistent && beat.quality.sqi >= SignalQualityAssessor::MIN_SQI {
+                    weights[i] = beat.quality.sqi / 100.0;
+                }
+            }
+        }
+
+        let mut estimates: Vec<(f32, f32)> = channel_heart_rates
+            .iter()
+            .zip(weights.iter())
+            .filter_map(|(heart_rate, &weight)| heart_rate.map(|heart_rate| (heart_rate, weight)))
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+        estimates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let total_weight: f32 = estimates.iter().map(|&(_, weight)| weight).sum();
+        let mut cumulative_weight = 0.0;
+        let median = estimates
+            .iter()
+            .find(|&&(_, weight)| {
+                cumulative_weight += weight;
+                cumulative_weight >= total_weight / 2.0
+            })?
+            .0;
+
+        for (heart_rate, weight) in channel_heart_rates.iter().zip(weights.iter_mut()) {
+            if heart_rate.is_some_and(|heart_rate| {
+                (heart_rate - median).abs() > Self::MAX_DEVIATION * median
+            }) {
+                *weight = 0.0;
+            }
+        }
+        let total_weight: f32 = weights.iter().sum();
+        let heart_rate = channel_heart_rates
+            .iter()
+            .zip(weights.iter())
+            .filter_map(|(heart_rate, &weight)| heart_rate.map(|heart_rate| heart_rate * weight))
+            .sum::<f32>()
+            / total_weight;
+        let confidence = weights.iter().map(|weight| weight.powi(2)).sum::<f32>() / total_weight;
+
+        Some(FusedHeartRate {
+            heart_rate,
+            confidence,
+            channel_heart_rates,
+            weights,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::time::millisecond;
+
+    use super::*;
+    use crate::{
+        pipeline::{tests::measure, HeartRateMethod},
+        synthetic::PpgConfiguration,
+    };
+
+    const SAMPLE_PERIOD: u128 = 30;
+    /// A pulse starting at its onset, sampled every 30 ms: a slow then a steep upstroke up to the peak after
+    /// 150 ms, and a linear decay back to the baseline.
+    const PULSE: [f32; 21] = [
+        0.0, 0.05, 0.1, 0.4, 0.7, 1.0, 0.93, 0.86, 0.79, 0.72, 0.65, 0.58, 0.51, 0.44, 0.37, 0.3,
+        0.23, 0.16, 0.09, 0.02, 0.0,
+    ];
+
+    /// The pulse of a channel with a DC level of 1 V.
+    #[derive(Debug, Clone, Copy)]
+    struct Channel {
+        /// The interval between the beats, in milliseconds, or 0 for no pulse.
+        interval: u128,
+        /// The perfusion index, in percent.
+        perfusion_index: f32,
+        clipped: bool,
+    }
+
+    const CLEAN: Channel = Channel {
+        interval: 900,
+        perfusion_index: 1.0,
+        clipped: false,
+    };
+
+    /// Pushes the pulses of the channels every 30 ms from `start` to `end`, in milliseconds, and returns the
+    /// timestamped fused heart rates.
+    fn fuse(
+        fusion: &mut HeartRateFusion,
+        channels: [Channel; 3],
+        start: u128,
+        end: u128,
+    ) -> Vec<(u128, FusedHeartRate)> {
+        (start..end)
+            .step_by(SAMPLE_PERIOD as usize)
+            .filter_map(|timestamp| {
+                let mut filtered_data = FilteredData::default();
+                for (i, channel) in channels.iter().enumerate() {
+                    let pulse = match channel.interval {
+                        0 => 0.0,
+                        interval => PULSE
+                            .get(((timestamp % interval) / SAMPLE_PERIOD) as usize)
+                            .copied()
+                            .unwrap_or(0.0),
+                    };
+                    // The photodiode current decreases when the blood volume increases.
+                    filtered_data[i] = (1.0, -pulse * channel.perfusion_index / 100.0);
+                }
+                let clipped = [
+                    channels[0].clipped,
+                    channels[1].clipped,
+                    channels[2].clipped,
+                ];
+                Some((timestamp, fusion.push(filtered_data, clipped, timestamp)?))
+            })
+            .collect()
+    }
+
+    fn fusion() -> HeartRateFusion {
+        HeartRateFusion::new(Time::new::<millisecond>(SAMPLE_PERIOD as f32))
+    }
+
+    #[test]
+    fn agreeing_channels_are_fused() {
+        let fused = fuse(&mut fusion(), [CLEAN; 3], 0, 30_000);
+
+        // One estimate per beat, once the beat detectors and the templates have learned the pulse.
+        let learned: Vec<FusedHeartRate> = fused
+            .iter()
+            .filter(|&&(timestamp, _)| timestamp >= 5_400)
+            .map(|&(_, fused)| fused)
+            .collect();
+        assert_eq!(learned.len(), (30_000 - 5_400) / 900);
+        for fused in learned {
+            assert!(
+                (fused.heart_rate - 60_000.0 / 900.0).abs() < 1e-3,
+                "{:?}",
+                fused
+            );
+            assert_eq!(fused.channel_heart_rates, [Some(60_000.0 / 900.0); 3]);
+            assert!(
+                fused.weights.iter().all(|&weight| weight > 0.99),
+                "{:?}",
+                fused
+            );
+            assert!(fused.confidence > 0.99, "{:?}", fused);
+        }
+    }
+
+    #[test]
+    fn channels_are_weighted_by_their_signal_quality() {
+        // The IR channel beats a little slower, with a perfusion index that scores 0.6.
+        let (low, high) = SignalQualityAssessor::PERFUSION_INDEX_RANGE;
+        let ir = Channel {
+            interval: 960,
+            perfusion_index: low + 0.6 * (high - low),
+            clipped: false,
+        };
+        let fused = fuse(&mut fusion(), [CLEAN, CLEAN, ir], 0, 30_000);
+
+        for &(_, fused) in fused.iter().filter(|&&(timestamp, _)| timestamp >= 5_000) {
+            assert!((fused.weights[2] - 0.6).abs() < 0.01, "{:?}", fused);
+            let total_weight: f32 = fused.weights.iter().sum();
+            let heart_rate = fused
+                .channel_heart_rates
+                .iter()
+                .zip(fused.weights.iter())
+                .map(|(heart_rate, weight)| heart_rate.unwrap() * weight)
+                .sum::<f32>()
+                / total_weight;
+            assert!((fused.heart_rate - heart_rate).abs() < 1e-3, "{:?}", fused);
+            assert!(
+                fused.heart_rate > 62.5 && fused.heart_rate < 60_000.0 / 900.0,
+                "{:?}",
+                fused
+            );
+            let confidence = fused
+                .weights
+                .iter()
+                .map(|weight| weight.powi(2))
+                .sum::<f32>()
+                / total_weight;
+            assert!((fused.confidence - confidence).abs() < 1e-3, "{:?}", fused);
+        }
+    }
+
+    #[test]
+    fn disagreeing_channel_is_left_out() {
+        let ir = Channel {
+            interval: 600,
+            ..CLEAN
+        };
+        let fused = fuse(&mut fusion(), [CLEAN, CLEAN, ir], 0, 30_000);
+
+        let learned: Vec<FusedHeartRate> = fused
+            .iter()
+            .filter(|&&(timestamp, _)| timestamp >= 5_000)
+            .map(|&(_, fused)| fused)
+            .collect();
+        assert!(!learned.is_empty());
+        for fused in learned {
+            assert!(
+                (fused.heart_rate - 60_000.0 / 900.0).abs() < 1e-3,
+                "{:?}",
+                fused
+            );
+            assert_eq!(fused.channel_heart_rates[2], Some(100.0));
+            assert_eq!(fused.weights[2], 0.0);
+        }
+    }
+
+    #[test]
+    fn clipped_channel_is_left_out() {
+        let red = Channel {
+            clipped: true,
+            ..CLEAN
+        };
+        let fused = fuse(&mut fusion(), [CLEAN, red, CLEAN], 0, 30_000);
+
+        assert!(fused.len() > 20);
+        for (_, fused) in fused {
+            assert_eq!(fused.weights[1], 0.0);
+        }
+    }
+
+    #[test]
+    fn channel_without_a_recent_beat_is_left_out() {
+        let mut fusion = fusion();
+        fuse(&mut fusion, [CLEAN; 3], 0, 18_000);
+        // The green pulse stops.
+        let green = Channel {
+            interval: 0,
+            ..CLEAN
+        };
+        let fused = fuse(&mut fusion, [green, CLEAN, CLEAN], 18_000, 27_000);
+
+        for &(timestamp, fused) in &fused {
+            if timestamp >= 18_000 + HeartRateFusion::MAX_AGE {
+                assert_eq!(fused.channel_heart_rates[0], None);
+                assert_eq!(fused.weights[0], 0.0);
+            }
+            assert!(
+                (fused.heart_rate - 60_000.0 / 900.0).abs() < 1e-3,
+                "{:?}",
+                fused
+            );
+        }
+
+        // Without any pulse, nothing is fused.
+        let none = Channel {
+            interval: 0,
+            ..CLEAN
+        };
+        assert!(fuse(&mut fusion, [none; 3], 27_000, 36_000).is_empty());
+    }
+
+    #[test]
+    fn beats_are_learned_again_after_a_reset() {
+        let mut fusion = fusion();
+        fuse(&mut fusion, [CLEAN; 3], 0, 9_000);
+        fusion.reset();
+
+        // The first beats of the detectors are not used.
+        let fused = fuse(&mut fusion, [CLEAN; 3], 10_000, 18_000);
+        assert!(fused.iter().all(|&(timestamp, _)| timestamp > 10_000));
+    }
+
+    #[test]
+    fn fuses_the_synthetic_heart_rate() {
+        for heart_rate in [50.0, 75.0, 120.0] {
+            let fused: Vec<FusedHeartRate> = measure(
+                PpgConfiguration {
+                    heart_rate,
+                    ..Default::default()
+                },
+                40.0,
+                |pipeline| pipeline.set_heart_rate_method(HeartRateMethod::Fusion),
+            )
+            .iter()
+            .filter_map(|output| output.fused_heart_rate)
+            .collect();
+
+            assert!(fused.len() as f32 > heart_rate / 3.0);
+            let mean = fused.iter().map(|fused| fused.heart_rate).sum::<f32>() / fused.len() as f32;
+            assert!(
+                (mean - heart_rate).abs() < 1.5,
+                "{} for {} bpm",
+                mean,
+                heart_rate
+            );
+            for fused in fused {
+                assert!(
+                    fused.weights.iter().all(|&weight| weight > 0.5),
+                    "{:?}",
+                    fused
+                );
+                assert!(fused.confidence > 0.5, "{:?}", fused);
+            }
+        }
+    }
+}
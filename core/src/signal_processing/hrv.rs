@@ -0,0 +1,569 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+use super::{
+    lomb_scargle::lomb_scargle,
+    rr_editing::{BeatClass, EditedInterval},
+};
+
+/// The time-domain heart rate variability metrics over a window of NN intervals.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TimeDomainHrv {
+    /// The mean of the NN intervals, in milliseconds.
+    pub mean_nn: f32,
+    /// The standard deviation of the NN intervals, in milliseconds.
+    pub sdnn: f32,
+    /// The root mean square of the successive differences of the NN intervals, in milliseconds.
+    pub rmssd: f32,
+    /// The percentage of successive differences larger than 50 ms.
+    pub pnn50: f32,
+}
+
+impl TimeDomainHrv {
+    pub fn serialise(&self) -> [u8; 16] {
+        let mut data = [0; 16];
+
+        data[0..4].copy_from_slice(&self.mean_nn.to_le_bytes());
+        data[4..8].copy_from_slice(&self.sdnn.to_le_bytes());
+        data[8..12].copy_from_slice(&self.rmssd.to_le_bytes());
+        data[12..16].copy_from_slice(&self.pnn50.to_le_bytes());
+
+        data
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NnInterval {
+    timestamp: u128,
+    interval: u128,
+    // Whether the previous interval is a normal one too, so that their difference is meaningful.
+    successive: bool,
+}
+
+/// Computes the time-domain heart rate variability over a sliding window of the intervals between normal beats
+/// (NN intervals). The intervals that have been edited by the [`RrEditor`](super::rr_editing::RrEditor) are
+/// excluded, since they come from ectopic beats or artifacts.
+pub struct HrvAnalyser {
+    intervals: VecDeque<NnInterval>,
+    window: u128,
+    previous_was_normal: bool,
+}
+
+impl HrvAnalyser {
+    /// The minimum number of NN intervals in the window to compute the metrics.
+    pub const MIN_INTERVALS: usize = 5;
+
+    /// Creates a new `HrvAnalyser` that computes the metrics over the intervals of the last `window`.
+    pub fn new(window: Time) -> Self {
+        Self {
+            intervals: VecDeque::new(),
+            window: window.get::<millisecond>().round() as u128,
+            previous_was_normal: false,
+        }
+    }
+
+    /// Gets the window of the metrics.
+    pub fn window(&self) -> Time {
+        Time::new::<millisecond>(self.window as f32)
+    }
+
+    /// Sets the window of the metrics.
+    pub fn set_window(&mut self, window: Time) {
+        self.window = window.get::<millisecond>().round() as u128;
+    }
+
+    /// Discards all the intervals, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.intervals.clear();
+        self.previous_was_normal = false;
+    }
+
+    /// Pushes an edited interval. Only the normal ones are used.
+    pub fn push(&mut self, edited: &EditedInterval) {
+        let is_normal = edited.class == BeatClass::Normal;
+        if is_normal {
+            self.intervals.push_back(NnInterval {
+                timestamp: edited.timestamp,
+                interval: edited.interval,
+                successive: self.previous_was_normal,
+            });
+        }
+        self.previous_was_normal = is_normal;
+
+        while let Some(oldest) = self.intervals.front() {
+            if edited.timestamp.saturating_sub(oldest.timestamp) <= self.window {
+                break;
+            }
+            self.intervals.pop_front();
+        }
+    }
+
+    /// Computes the metrics over the NN intervals in the window, if there are enough.
+    pub fn time_domain(&self) -> Option<TimeDomainHrv> {
+        if self.intervals.len() < Self::MIN_INTERVALS {
+            return None;
+        }
+
+        let count = self.intervals.len() as f32;
+        let mean_nn = self
+            .intervals
+            .iter()
+            .map(|nn| nn.interval as f32)
+            .sum::<f32>()
+            / count;
+        let variance = self
+            .intervals
+            .iter()
+            .map(|nn| (nn.interval as f32 - mean_nn).powi(2))
+            .sum::<f32>()
+            / (count - 1.0);
+
+        // Only the differences between the intervals of consecutive normal beats are meaningful.
+        let differences: Vec<f32> = self
+            .intervals
+            .iter()
+            .zip(self.intervals.iter().skip(1))
+            .filter(|(_, nn)| nn.successive)
+            .map(|(previous, nn)| nn.interval as f32 - previous.interval as f32)
+            .collect();
+        let (rmssd, pnn50) = if differences.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let count = differences.len() as f32;
+            (
+                (differences.iter().map(|d| d.powi(2)).sum::<f32>() / count).sqrt(),
+                differences.iter().filter(|d| d.abs() > 50.0).count() as f32 / count * 100.0,
+            )
+        };
+
+        Some(TimeDomainHrv {
+            mean_nn,
+            sdnn: variance.sqrt(),
+            rmssd,
+            pnn50,
+        })
+    }
+}
+
+/// The frequency-domain heart rate variability metrics over a window of NN intervals.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrequencyDomainHrv {
+    /// The power in the very low frequency band, in square milliseconds.
+    pub vlf: f32,
+    /// The power in the low frequency band, in square milliseconds.
+    pub lf: f32,
+    /// The power in the high frequency band, in square milliseconds.
+    pub hf: f32,
+    /// The ratio between the low and the high frequency powers.
+    pub lf_hf_ratio: f32,
+}
+
+impl FrequencyDomainHrv {
+    pub fn serialise(&self) -> [u8; 16] {
+        let mut data = [0; 16];
+
+        data[0..4].copy_from_slice(&self.vlf.to_le_bytes());
+        data[4..8].copy_from_slice(&self.lf.to_le_bytes());
+        data[8..12].copy_from_slice(&self.hf.to_le_bytes());
+        data[12..16].copy_from_slice(&self.lf_hf_ratio.to_le_bytes());
+
+        data
+    }
+}
+
+/// Computes the frequency-domain heart rate variability from the Lomb–Scargle periodogram of the NN intervals
+/// in a sliding window, so that the tachogram does not need to be resampled nor interpolated where the edited
+/// intervals have been excluded. The periodogram is scaled so that its integral is the variance of the intervals.
+pub struct SpectralHrvAnalyser {
+    intervals: VecDeque<(u128, u128)>,
+    window: u128,
+    period: u128,
+    last_estimate: Option<u128>,
+    frequencies: Vec<f32>,
+}
+
+impl SpectralHrvAnalyser {
+    /// The very low frequency band, in hertz.
+    pub const VLF_BAND: (f32, f32) = (0.0033, 0.04);
+    /// The low frequency band, in hertz.
+    pub const LF_BAND: (f32, f32) = (0.04, 0.15);
+    /// The high frequency band, in hertz.
+    pub const HF_BAND: (f32, f32) = (0.15, 0.4);
+    /// The distance between the frequencies of the periodogram, in hertz.
+    pub const FREQUENCY_STEP: f32 = 0.002;
+    /// The shortest window, in milliseconds.
+    pub const MIN_WINDOW: u128 = 120_000;
+    /// The longest window, in milliseconds.
+    pub const MAX_WINDOW: u128 = 300_000;
+
+    /// Creates a new `SpectralHrvAnalyser` that estimates the metrics every `period` over the intervals of the
+    /// last `window`, between 2 and 5 minutes.
+    pub fn new(window: Time, period: Time) -> Self {
+        let mut analyser = Self {
+            intervals: VecDeque::new(),
+            window: Self::MAX_WINDOW,
+            period: 0,
+            last_estimate: None,
+            frequencies: (1..)
+                .map(|i| i as f32 * Self::FREQUENCY_STEP)
+                .take_while(|&frequency| frequency <= Self::HF_BAND.1)
+                .collect(),
+        };
+        analyser.set_window(window);
+        analyser.set_period(period);
+
+        analyser
+    }
+
+    /// Gets the window of the metrics.
+    pub fn window(&self) -> Time {
+        Time::new::<millisecond>(self.window as f32)
+    }
+
+    /// Sets the window of the metrics, clamped between 2 and 5 minutes.
+    pub fn set_window(&mut self, window: Time) {
+        self.window =
+            (window.get::<millisecond>().round() as u128).clamp(Self::MIN_WINDOW, Self::MAX_WINDOW);
+    }
+
+    /// Gets the interval between the estimates.
+    pub fn period(&self) -> Time {
+        Time::new::<millisecond>(self.period as f32)
+    }
+
+    /// Sets the interval between the estimates.
+    pub fn set_period(&mut self, period: Time) {
+        self.period = period.get::<millisecond>().round() as u128;
+    }
+
+    /// Discards all the intervals, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.intervals.clear();
+        self.last_estimate = None;
+    }
+
+    /// Pushes an edited interval, and returns the metrics when the normal intervals cover the whole window and a
+    /// period has elapsed since the last estimate. Only the normal intervals are used.
+    pub fn push(&mut self, edited: &EditedInterval) -> Option<FrequencyDomainHrv> {
+        let timestamp = edited.timestamp;
+        if edited.class == BeatClass::Normal {
+            self.intervals.push_back((timestamp, edited.interval));
+        }
+        while let Some(&(oldest, _)) = self.intervals.front() {
+            if timestamp.saturating_sub(oldest) <= self.window {
+                break;
+            }
+            self.intervals.pop_front();
+        }
+
+        // The oldest interval starts at the beat before its own.
+        let &(first, first_interval) = self.intervals.front()?;
+        if (timestamp + first_interval).saturating_sub(first) < self.window {
+            return None;
+        }
+        if let Some(last_estimate) = self.last_estimate {
+            if timestamp.saturating_sub(last_estimate) < self.period {
+                return None;
+            }
+        }
+        self.last_estimate = Some(timestamp);
+
+        Some(self.estimate())
+    }
+
+    /// Estimates the metrics from the periodogram of the intervals in the window.
+    fn estimate(&self) -> FrequencyDomainHrv {
+        let first = self
+            .intervals
+            .front()
+            .map_or(0, |&(timestamp, _)| timestamp);
+        let times: Vec<f32> = self
+            .intervals
+            .iter()
+            .map(|&(timestamp, _)| (timestamp - first) as f32 / 1000.0)
+            .collect();
+        let values: Vec<f32> = self
+            .intervals
+            .iter()
+            .map(|&(_, interval)| interval as f32)
+            .collect();
+
+        let count = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / count;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / count;
+
+        let periodogram = lomb_scargle(&times, &values, &self.frequencies);
+        let total = periodogram.iter().sum::<f32>() * Self::FREQUENCY_STEP;
+        let scale = if total > 0.0 { variance / total } else { 0.0 };
+
+        let band_power = |(low, high): (f32, f32)| {
+            self.frequencies
+                .iter()
+                .zip(&periodogram)
+                .filter(|(&frequency, _)| frequency >= low && frequency < high)
+                .map(|(_, power)| power * scale * Self::FREQUENCY_STEP)
+                .sum::<f32>()
+        };
+        let (vlf, lf, hf) = (
+            band_power(Self::VLF_BAND),
+            band_power(Self::LF_BAND),
+            band_power(Self::HF_BAND),
+        );
+
+        FrequencyDomainHrv {
+            vlf,
+            lf,
+            hf,
+            lf_hf_ratio: if hf > 0.0 { lf / hf } else { 0.0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use uom::si::time::second;
+
+    use super::*;
+    use crate::{pipeline::tests::measure, synthetic::PpgConfiguration};
+
+    /// Edits the given intervals with their classes, each ending at the sum of the intervals so far.
+    fn edited(intervals: impl IntoIterator<Item = (u128, BeatClass)>) -> Vec<EditedInterval> {
+        let mut timestamp = 0;
+        intervals
+            .into_iter()
+            .map(|(interval, class)| {
+                timestamp += interval;
+                EditedInterval {
+                    timestamp,
+                    interval,
+                    class,
+                }
+            })
+            .collect()
+    }
+
+    fn normal(intervals: impl IntoIterator<Item = u128>) -> Vec<EditedInterval> {
+        edited(
+            intervals
+                .into_iter()
+                .map(|interval| (interval, BeatClass::Normal)),
+        )
+    }
+
+    fn time_domain(window: f32, intervals: &[EditedInterval]) -> Option<TimeDomainHrv> {
+        let mut analyser = HrvAnalyser::new(Time::new::<second>(window));
+        for interval in intervals {
+            analyser.push(interval);
+        }
+        analyser.time_domain()
+    }
+
+    #[test]
+    fn time_domain_of_alternating_intervals() {
+        let hrv = time_domain(60.0, &normal([800, 900].repeat(5))).unwrap();
+
+        assert_eq!(hrv.mean_nn, 850.0);
+        // The sample standard deviation of ten intervals 50 ms away from their mean.
+        assert!(
+            (hrv.sdnn - 50.0 * (10f32 / 9.0).sqrt()).abs() < 1e-3,
+            "{:?}",
+            hrv
+        );
+        assert_eq!(hrv.rmssd, 100.0);
+        assert_eq!(hrv.pnn50, 100.0);
+    }
+
+    #[test]
+    fn time_domain_needs_enough_intervals() {
+        let intervals = normal([800; HrvAnalyser::MIN_INTERVALS]);
+        assert_eq!(
+            time_domain(60.0, &intervals[..HrvAnalyser::MIN_INTERVALS - 1]),
+            None
+        );
+        assert_eq!(
+            time_domain(60.0, &intervals),
+            Some(TimeDomainHrv {
+                mean_nn: 800.0,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn time_domain_excludes_the_edited_intervals() {
+        let intervals = edited([
+            (800, BeatClass::Normal),
+            (800, BeatClass::Normal),
+            (800, BeatClass::Normal),
+            (500, BeatClass::Ectopic),
+            (900, BeatClass::Normal),
+            (900, BeatClass::Normal),
+            (900, BeatClass::Normal),
+        ]);
+        let hrv = time_domain(60.0, &intervals).unwrap();
+
+        assert_eq!(hrv.mean_nn, 850.0);
+        assert!(hrv.sdnn > 0.0);
+        // The difference between the intervals around the ectopic beat is not successive.
+        assert_eq!(hrv.rmssd, 0.0);
+        assert_eq!(hrv.pnn50, 0.0);
+    }
+
+    #[test]
+    fn time_domain_over_the_window() {
+        let mut analyser = HrvAnalyser::new(Time::new::<second>(10.0));
+        for interval in normal([1000].repeat(10).into_iter().chain([800].repeat(20))) {
+            analyser.push(&interval);
+        }
+        // Only the last 10 s of intervals are left.
+        assert_eq!(analyser.time_domain().unwrap().mean_nn, 800.0);
+
+        analyser.reset();
+        assert_eq!(analyser.time_domain(), None);
+    }
+
+    /// NN intervals of 1 s on average, modulated by a sinusoid with the given frequency and an amplitude of 50 ms.
+    fn modulated(frequency: f32, duration: f32) -> Vec<EditedInterval> {
+        let mut time = 0.0;
+        normal(std::iter::from_fn(|| {
+            let interval = 1000.0 + 50.0 * (2.0 * PI * frequency * time).sin();
+            time += interval / 1000.0;
+            (time <= duration).then(|| interval.round() as u128)
+        }))
+    }
+
+    fn spectral(intervals: &[EditedInterval]) -> Vec<(u128, FrequencyDomainHrv)> {
+        let mut analyser =
+            SpectralHrvAnalyser::new(Time::new::<second>(120.0), Time::new::<second>(10.0));
+        intervals
+            .iter()
+            .filter_map(|interval| Some((interval.timestamp, analyser.push(interval)?)))
+            .collect()
+    }
+
+    #[test]
+    fn spectral_estimates_once_the_window_is_covered_and_every_period() {
+        let estimates = spectral(&normal([1000; 200]));
+        let timestamps: Vec<u128> = estimates.iter().map(|&(timestamp, _)| timestamp).collect();
+        assert_eq!(
+            timestamps,
+            (120_000..=200_000).step_by(10_000).collect::<Vec<_>>()
+        );
+        // Constant intervals have no variability.
+        assert!(estimates
+            .iter()
+            .all(|(_, estimate)| *estimate == FrequencyDomainHrv::default()));
+    }
+
+    #[test]
+    fn spectral_power_is_in_the_band_of_the_modulation() {
+        // The variance of the modulation.
+        let variance = 50f32.powi(2) / 2.0;
+        for (frequency, band) in [(0.02, 0), (0.1, 1), (0.25, 2)] {
+            let estimates = spectral(&modulated(frequency, 200.0));
+
+            assert!(!estimates.is_empty());
+            for (_, estimate) in estimates {
+                let powers = [estimate.vlf, estimate.lf, estimate.hf];
+                assert!(
+                    powers[band] > 0.9 * powers.iter().sum::<f32>(),
+                    "{:?} for {} Hz",
+                    estimate,
+                    frequency
+                );
+                // The periodogram is scaled to the variance of the intervals.
+                assert!(
+                    (powers.iter().sum::<f32>() - variance).abs() < 0.15 * variance,
+                    "{:?} for {} Hz",
+                    estimate,
+                    frequency
+                );
+                assert_eq!(estimate.lf_hf_ratio, estimate.lf / estimate.hf);
+            }
+        }
+    }
+
+    #[test]
+    fn spectral_metrics_exclude_the_edited_intervals() {
+        // Every tenth interval is an artifact far from the others.
+        let intervals: Vec<EditedInterval> = modulated(0.25, 200.0)
+            .into_iter()
+            .enumerate()
+            .map(|(n, interval)| {
+                if n % 10 == 5 {
+                    EditedInterval {
+                        interval: 3000,
+                        class: BeatClass::Artifact,
+                        ..interval
+                    }
+                } else {
+                    interval
+                }
+            })
+            .collect();
+
+        let estimates = spectral(&intervals);
+        assert!(!estimates.is_empty());
+        for (_, estimate) in estimates {
+            assert!(estimate.hf > 10.0 * estimate.lf, "{:?}", estimate);
+            assert!(estimate.hf < 1.15 * 50f32.powi(2) / 2.0, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn time_domain_of_synthetic_intervals() {
+        for variability in [10.0, 40.0] {
+            let outputs = measure(
+                PpgConfiguration {
+                    heart_rate_variability: Time::new::<millisecond>(variability),
+                    respiration_frequency_modulation: 0.0,
+                    ..Default::default()
+                },
+                150.0,
+                |_| {},
+            );
+            let hrv = outputs.iter().rev().find_map(|output| output.hrv).unwrap();
+
+            assert!((hrv.mean_nn - 60_000.0 / 72.0).abs() < 15.0, "{:?}", hrv);
+            // The 30 ms sampling adds some variability to the detected peaks.
+            assert!(
+                hrv.sdnn > 0.8 * variability && hrv.sdnn < variability + 15.0,
+                "{:?} for {} ms",
+                hrv,
+                variability
+            );
+            // The successive differences of independent intervals have √2 times their standard deviation.
+            assert!(
+                (hrv.rmssd / hrv.sdnn - 2f32.sqrt()).abs() < 0.3,
+                "{:?}",
+                hrv
+            );
+        }
+    }
+
+    #[test]
+    fn spectral_metrics_over_a_two_minute_window() {
+        let outputs = measure(PpgConfiguration::default(), 170.0, |pipeline| {
+            pipeline.set_spectral_hrv_window(Time::new::<second>(120.0));
+            pipeline.set_spectral_hrv_period(Time::new::<second>(10.0));
+        });
+        let estimates: Vec<FrequencyDomainHrv> = outputs
+            .iter()
+            .filter_map(|output| output.spectral_hrv)
+            .collect();
+
+        assert!(estimates.len() >= 3, "{:?}", estimates);
+        for estimate in estimates {
+            // The respiratory sinus arrhythmia at 15 breaths per minute, 0.25 Hz, is in the high frequency band.
+            assert!(estimate.hf > estimate.lf, "{:?}", estimate);
+            assert!(estimate.lf_hf_ratio < 1.0, "{:?}", estimate);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::f32::consts::PI;
+
+/// Computes the Lomb–Scargle periodogram of the unevenly sampled `values`, taken at `times` (in seconds), at the
+/// given `frequencies` (in hertz). The mean of the values is removed, and the power at each frequency is the one of
+/// the least squares fit of a sinusoid at that frequency, so it does not depend on the sampling times.
+pub fn lomb_scargle(times: &[f32], values: &[f32], frequencies: &[f32]) -> Vec<f32> {
+    let count = times.len().min(values.len());
+    if count < 2 {
+        return vec![0.0; frequencies.len()];
+    }
+    let (times, values) = (&times[..count], &values[..count]);
+
+    // The times are relative to the first one, to keep the precision of the phases.
+    let start = times[0];
+    let mean = values.iter().sum::<f32>() / count as f32;
+
+    frequencies
+        .iter()
+        .map(|&frequency| {
+            let omega = 2.0 * PI * frequency;
+
+            // The time offset that makes the sine and cosine terms orthogonal.
+            let (sin_sum, cos_sum) = times.iter().fold((0.0, 0.0), |(sin_sum, cos_sum), time| {
+                let phase = 2.0 * omega * (time - start);
+                (sin_sum + phase.sin(), cos_sum + phase.cos())
+            });
+            let tau = sin_sum.atan2(cos_sum) / (2.0 * omega);
+
+            let (mut yc, mut ys, mut cc, mut ss) = (0.0, 0.0, 0.0, 0.0);
+            for (time, value) in times.iter().zip(values) {
+                let phase = omega * (time - start - tau);
+                let (sin, cos) = phase.sin_cos();
+                let value = value - mean;
+
+                yc += value * cos;
+                ys += value * sin;
+                cc += cos * cos;
+                ss += sin * sin;
+            }
+
+            let cosine_term = if cc > 0.0 { yc * yc / cc } else { 0.0 };
+            let sine_term = if ss > 0.0 { ys * ys / ss } else { 0.0 };
+
+            0.5 * (cosine_term + sine_term)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_processing::tests::white_noise;
+
+    /// Times every 0.8 s on average, with a deterministic jitter of up to 0.3 s.
+    fn uneven_times(count: usize) -> Vec<f32> {
+        (0..count)
+            .map(|n| n as f32 * 0.8 + 0.3 * (n as f32 * 1.7).sin())
+            .collect()
+    }
+
+    #[test]
+    fn peak_of_an_unevenly_sampled_sinusoid() {
+        let times = uneven_times(150);
+        let frequencies: Vec<f32> = (1..=300).map(|i| i as f32 * 0.002).collect();
+        for frequency in [0.05, 0.11, 0.23, 0.4] {
+            let values: Vec<f32> = times
+                .iter()
+                .map(|time| 5.0 + 3.0 * (2.0 * PI * frequency * time + 0.4).sin())
+                .collect();
+            let periodogram = lomb_scargle(&times, &values, &frequencies);
+
+            let peak = (0..frequencies.len())
+                .max_by(|&a, &b| periodogram[a].total_cmp(&periodogram[b]))
+                .unwrap();
+            assert!(
+                (frequencies[peak] - frequency).abs() <= 0.002,
+                "{} for {} Hz",
+                frequencies[peak],
+                frequency
+            );
+            // The power of a sinusoid is a quarter of its squared amplitude for each value.
+            let expected = 150.0 * 3f32.powi(2) / 4.0;
+            assert!(
+                (periodogram[peak] - expected).abs() < 0.1 * expected,
+                "{} for {} Hz",
+                periodogram[peak],
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn power_at_the_fourier_frequencies_sums_to_the_variance() {
+        // With even sampling, the power at the Fourier frequencies up to the Nyquist one adds up to half the sum
+        // of the squared deviations from the mean, as with Parseval's theorem.
+        let count = 200;
+        let period = 0.5;
+        let times: Vec<f32> = (0..count).map(|n| n as f32 * period).collect();
+        let values: Vec<f32> = white_noise(count)
+            .iter()
+            .zip(&times)
+            .map(|(noise, time)| 2.0 + noise + (2.0 * PI * 0.3 * time).sin())
+            .collect();
+        let frequencies: Vec<f32> = (1..=count / 2)
+            .map(|k| k as f32 / (count as f32 * period))
+            .collect();
+
+        let power = lomb_scargle(&times, &values, &frequencies)
+            .iter()
+            .sum::<f32>();
+        let mean = values.iter().sum::<f32>() / count as f32;
+        let squared_deviations = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>();
+        assert!(
+            (power - squared_deviations / 2.0).abs() < 0.01 * squared_deviations,
+            "{} for {}",
+            power,
+            squared_deviations
+        );
+    }
+
+    #[test]
+    fn fewer_than_two_values_have_no_power() {
+        let frequencies = [0.1, 0.2, 0.3];
+        assert_eq!(lomb_scargle(&[], &[], &frequencies), vec![0.0; 3]);
+        assert_eq!(lomb_scargle(&[1.0], &[5.0], &frequencies), vec![0.0; 3]);
+        // Only the times with a value are used.
+        assert_eq!(
+            lomb_scargle(&[1.0, 2.0, 3.0], &[5.0], &frequencies),
+            vec![0.0; 3]
+        );
+    }
+
+    #[test]
+    fn constant_values_have_no_power() {
+        let times = uneven_times(50);
+        let frequencies: Vec<f32> = (1..=200).map(|i| i as f32 * 0.002).collect();
+        let periodogram = lomb_scargle(&times, &[812.0; 50], &frequencies);
+
+        assert_eq!(periodogram.len(), frequencies.len());
+        assert!(periodogram.iter().all(|&power| power == 0.0));
+    }
+}
@@ -0,0 +1,57 @@
+pub mod autocorrelation;
+pub mod averaging;
+pub mod beat_detection;
+pub mod dot_product;
+pub mod filters;
+pub mod fusion;
+pub mod hrv;
+pub mod lomb_scargle;
+pub mod motion;
+pub mod perfusion;
+pub mod quality;
+pub mod respiration;
+pub mod rhythm;
+pub mod rr_editing;
+pub mod spectral;
+pub mod spo2;
+pub mod spo2_calibration;
+pub mod standard_deviation;
+
+/// A heart rate estimated over a window of samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HeartRateEstimate {
+    /// The heart rate, in bpm.
+    pub heart_rate: f32,
+    /// How periodic the signal is in the window, from 0 (not periodic) to 1 (perfectly periodic).
+    pub confidence: f32,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::f32::consts::PI;
+
+    /// Samples `signal`, a function of the time in seconds, every `crate::SAMPLE_PERIOD` for `duration` seconds.
+    pub(crate) fn sampled(duration: f32, signal: impl Fn(f32) -> f32) -> Vec<f32> {
+        (0..(duration / crate::SAMPLE_PERIOD).round() as usize)
+            .map(|n| signal(n as f32 * crate::SAMPLE_PERIOD))
+            .collect()
+    }
+
+    /// A unit sinusoid at the given heart rate, in bpm, at the given time, in seconds.
+    pub(crate) fn sinusoid(heart_rate: f32, time: f32) -> f32 {
+        (2.0 * PI * heart_rate / 60.0 * time).sin()
+    }
+
+    /// Uniform white noise in [-1, 1], with a xorshift generator so that the tests are deterministic.
+    pub(crate) fn white_noise(count: usize) -> Vec<f32> {
+        let mut state: u32 = 0x1234_5678;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                2.0 * state as f32 / u32::MAX as f32 - 1.0
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+use crate::protocol::FilteredData;
+
+/// The motion detected over the recent samples, with the optical features it is detected from.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MotionAssessment {
+    /// Whether a feature has exceeded its threshold in the window or during the hold time.
+    pub moving: bool,
+    /// One minus the correlation of the LED1 (green) and LED3 (IR) AC signals over the window, from 0 to 2, or 0 if
+    /// one of them has been clipped.
+    pub channel_disagreement: f32,
+    /// The largest relative change of the DC level of a channel that has not been clipped over the window.
+    pub dc_jump: f32,
+    /// The RMS of the LED1 AC signal over the window, over its median over the recent windows, or 1 if it has been
+    /// clipped.
+    pub ac_burst: f32,
+    /// The peak-to-peak ambient light current over the window, in amperes.
+    pub ambient_variation: f32,
+}
+
+/// Detects the motion artefacts from the optical signals only, until an accelerometer is available. The pulse is
+/// the same on every channel while the motion affects them differently, shifts the DC level, adds large AC
+/// components and lets the ambient light leak under the sensor. The segment is flagged as moving as soon as a
+/// feature exceeds its threshold over the window, and until the hold time has elapsed after the last one. A clipped
+/// channel carries no pulse, so it is left out of the features rather than taken for motion.
+pub struct MotionDetector {
+    samples: VecDeque<(FilteredData, [bool; 3], f32)>,
+    window_length: usize,
+    rms_history: VecDeque<f32>,
+    samples_since_rms: usize,
+    last_motion: Option<u128>,
+    hold: u128,
+}
+
+impl MotionDetector {
+    /// The channel disagreement above which the samples are flagged as moving.
+    pub const MAX_CHANNEL_DISAGREEMENT: f32 = 0.5;
+    /// The relative DC change above which the samples are flagged as moving.
+    pub const MAX_DC_JUMP: f32 = 0.05;
+    /// The AC burst ratio above which the samples are flagged as moving.
+    pub const MAX_AC_BURST: f32 = 2.0;
+    /// The ambient variation above which the samples are flagged as moving, in amperes.
+    pub const MAX_AMBIENT_VARIATION: f32 = 0.1e-6;
+    /// The number of recent windows whose median RMS is the reference of the AC burst.
+    pub const RMS_HISTORY_LENGTH: usize = 8;
+    /// The minimum number of recent windows to detect the AC bursts.
+    pub const MIN_RMS_HISTORY_LENGTH: usize = 3;
+
+    /// Creates a new `MotionDetector` for signals sampled every `sample_period`, that computes the features over
+    /// the last `window` and keeps flagging the motion for `hold` after it has stopped.
+    pub fn new(sample_period: Time, window: Time, hold: Time) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            window_length: ((window.get::<millisecond>() / sample_period.get::<millisecond>())
+                .round() as usize)
+                .max(2),
+            rms_history: VecDeque::new(),
+            samples_since_rms: 0,
+            last_motion: None,
+            hold: hold.get::<millisecond>().round() as u128,
+        }
+    }
+
+    /// Discards the samples and the reference RMS, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.rms_history.clear();
+        self.samples_since_rms = 0;
+        self.last_motion = None;
+    }
+
+    /// Gets the timestamp of the latest sample at which a feature has exceeded its threshold, if any.
+    pub fn last_motion(&self) -> Option<u128> {
+        self.last_motion
+    }
+
+    /// Pushes the filtered data, with whether the reading of each channel has been clipped, and the ambient light
+    /// current, in amperes, taken at `timestamp`, and returns the assessment. The features are 0 until the window
+    /// is full.
+    pub fn push(
+        &mut self,
+        filtered_data: FilteredData,
+        clipped: [bool; 3],
+        ambient_current: f32,
+        timestamp: u128,
+    ) -> MotionAssessment {
+        if self.samples.len() == self.window_length {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back((filtered_data, clipped, ambient_current));
+        if self.samples.len() < self.window_length {
+            return MotionAssessment::default();
+        }
+
+        let count = self.samples.len() as f32;
+        let green: Vec<f32> = self.samples.iter().map(|(data, _, _)| data[0].1).collect();
+        let ir: Vec<f32> = self.samples.iter().map(|(data, _, _)| data[2].1).collect();
+        let mut channel_clipped = [false; 3];
+        for (_, clipped, _) in self.samples.iter() {
+            for (channel_clipped, clipped) in channel_clipped.iter_mut().zip(clipped.iter()) {
+                *channel_clipped |= clipped;
+            }
+        }
+
+        let channel_disagreement = if channel_clipped[0] || channel_clipped[2] {
+            0.0
+        } else {
+            1.0 - correlation(&green, &ir)
+        };
+
+        let (first, _, _) = self.samples[0];
+        let dc_jump = (0..3)
+            .filter(|&i| !channel_clipped[i] && first[i].0.abs() > 0.0)
+            .map(|i| ((filtered_data[i].0 - first[i].0) / first[i].0).abs())
+            .fold(0.0, f32::max);
+
+        // The reference RMS is updated once per window, its median ignores the bursts.
+        let rms = (green.iter().map(|ac| ac.powi(2)).sum::<f32>() / count).sqrt();
+        let mut history: Vec<f32> = self.rms_history.iter().copied().collect();
+        history.sort_unstable_by(f32::total_cmp);
+        let ac_burst = match history.get(history.len() / 2) {
+            Some(&reference)
+                if !channel_clipped[0]
+                    && history.len() >= Self::MIN_RMS_HISTORY_LENGTH
+                    && reference > 0.0 =>
+            {
+                rms / reference
+            }
+            _ => 1.0,
+        };
+        self.samples_since_rms += 1;
+        if self.samples_since_rms >= self.window_length && !channel_clipped[0] {
+            self.samples_since_rms = 0;
+            if self.rms_history.len() == Self::RMS_HISTORY_LENGTH {
+                self.rms_history.pop_front();
+            }
+            self.rms_history.push_back(rms);
+        }
+
+        let (minimum, maximum) = self.samples.iter().fold(
+            (f32::MAX, f32::MIN),
+            |(minimum, maximum), &(_, _, ambient)| (minimum.min(ambient), maximum.max(ambient)),
+        );
+        let ambient_variation = maximum - minimum;
+
+        if channel_disagreement > Self::MAX_CHANNEL_DISAGREEMENT
+            || dc_jump > Self::MAX_DC_JUMP
+            || ac_burst > Self::MAX_AC_BURST
+            || ambient_variation > Self::MAX_AMBIENT_VARIATION
+        {
+            self.last_motion = Some(timestamp);
+        }
+        let moving = self
+            .last_motion
+            .is_some_and(|last_motion| timestamp.saturating_sub(last_motion) <= self.hold);
+
+        MotionAssessment {
+            moving,
+            channel_disagreement,
+            dc_jump,
+            ac_burst,
+            ambient_variation,
+        }
+    }
+}
+
+/// Computes the Pearson correlation of two signals of the same length, or 0 if one is constant.
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let count = a.len() as f32;
+    let (mean_a, mean_b) = (a.iter().sum::<f32>() / count, b.iter().sum::<f32>() / count);
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+
+    if variance_a > 0.0 && variance_b > 0.0 {
+        covariance / (variance_a * variance_b).sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::tests::measure, signal_processing::tests::sinusoid, synthetic::PpgConfiguration,
+    };
+
+    const SAMPLE_PERIOD: u128 = 30;
+    /// The number of samples in the window of 2 s.
+    const WINDOW_LENGTH: usize = 67;
+    const HOLD: u128 = 2000;
+
+    /// A sample of the optical signals, with the clipping of each channel and the ambient light current.
+    #[derive(Clone, Copy)]
+    struct Sample {
+        dc: [f32; 3],
+        ac: [f32; 3],
+        clipped: [bool; 3],
+        ambient_current: f32,
+    }
+
+    /// A clean pulse at 72 bpm at the given timestamp, stronger on the green channel.
+    fn clean(timestamp: u128) -> Sample {
+        let pulse = sinusoid(72.0, timestamp as f32 / 1000.0);
+        Sample {
+            dc: [0.5, 0.6, 0.7],
+            ac: [3.0 * pulse, pulse, pulse],
+            clipped: [false; 3],
+            ambient_current: 1e-6,
+        }
+    }
+
+    /// Pushes the samples every 30 ms for `duration` milliseconds and returns the timestamped assessments.
+    fn detect(
+        detector: &mut MotionDetector,
+        duration: u128,
+        sample: impl Fn(u128) -> Sample,
+    ) -> Vec<(u128, MotionAssessment)> {
+        (0..duration)
+            .step_by(SAMPLE_PERIOD as usize)
+            .map(|timestamp| {
+                let sample = sample(timestamp);
+                let mut filtered_data = FilteredData::default();
+                for i in 0..3 {
+                    filtered_data[i] = (sample.dc[i], sample.ac[i]);
+                }
+                let assessment = detector.push(
+                    filtered_data,
+                    sample.clipped,
+                    sample.ambient_current,
+                    timestamp,
+                );
+                (timestamp, assessment)
+            })
+            .collect()
+    }
+
+    fn detector() -> MotionDetector {
+        MotionDetector::new(
+            Time::new::<millisecond>(SAMPLE_PERIOD as f32),
+            Time::new::<millisecond>(2000.0),
+            Time::new::<millisecond>(HOLD as f32),
+        )
+    }
+
+    #[test]
+    fn features_are_computed_once_the_window_is_full() {
+        let mut detector = detector();
+        let assessments = detect(&mut detector, 10_000, clean);
+
+        assert!(assessments[..WINDOW_LENGTH - 1]
+            .iter()
+            .all(|(_, assessment)| *assessment == MotionAssessment::default()));
+        // The AC burst is 1 until enough windows have been seen.
+        assert_eq!(assessments[WINDOW_LENGTH - 1].1.ac_burst, 1.0);
+
+        // The window is filled again after a reset.
+        detector.reset();
+        let assessments = detect(&mut detector, 10_000, clean);
+        assert!(assessments[..WINDOW_LENGTH - 1]
+            .iter()
+            .all(|(_, assessment)| *assessment == MotionAssessment::default()));
+    }
+
+    #[test]
+    fn clean_pulse_is_not_flagged() {
+        let assessments = detect(&mut detector(), 30_000, clean);
+
+        for (timestamp, assessment) in assessments.into_iter().skip(WINDOW_LENGTH - 1) {
+            assert!(!assessment.moving, "{:?} at {} ms", assessment, timestamp);
+            assert!(assessment.channel_disagreement < 1e-3, "{:?}", assessment);
+            assert_eq!(assessment.dc_jump, 0.0);
+            assert!((assessment.ac_burst - 1.0).abs() < 0.05, "{:?}", assessment);
+            assert_eq!(assessment.ambient_variation, 0.0);
+        }
+    }
+
+    /// Disturbs the clean pulse from 10 to 12 s, and checks that the motion is flagged while the given feature
+    /// exceeds its threshold, and cleared after the hold time.
+    fn assert_flagged(
+        disturb: impl Fn(&mut Sample),
+        feature: impl Fn(&MotionAssessment) -> f32,
+        threshold: f32,
+    ) {
+        let assessments = detect(&mut detector(), 30_000, |timestamp| {
+            let mut sample = clean(timestamp);
+            if (10_000..12_000).contains(&timestamp) {
+                disturb(&mut sample);
+            }
+            sample
+        });
+
+        let exceeded: Vec<u128> = assessments
+            .iter()
+            .filter(|(_, assessment)| feature(assessment) > threshold)
+            .map(|&(timestamp, _)| timestamp)
+            .collect();
+        // The disturbance exceeds the threshold as long as it is in the window.
+        assert!(exceeded.contains(&11_970), "{:?}", exceeded);
+        let (first, last) = (exceeded[0], *exceeded.last().unwrap());
+        assert!(first >= 10_000 && last < 14_000, "{:?}", exceeded);
+
+        for (timestamp, assessment) in assessments {
+            let moving = (first..=last + HOLD).contains(&timestamp);
+            assert_eq!(
+                assessment.moving, moving,
+                "{:?} at {} ms",
+                assessment, timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn channel_disagreement_is_flagged() {
+        assert_flagged(
+            |sample| sample.ac[2] = -sample.ac[2],
+            |assessment| assessment.channel_disagreement,
+            MotionDetector::MAX_CHANNEL_DISAGREEMENT,
+        );
+    }
+
+    #[test]
+    fn dc_jump_is_flagged() {
+        assert_flagged(
+            |sample| sample.dc[1] *= 1.1,
+            |assessment| assessment.dc_jump,
+            MotionDetector::MAX_DC_JUMP,
+        );
+    }
+
+    #[test]
+    fn ac_burst_is_flagged() {
+        assert_flagged(
+            |sample| sample.ac[0] *= 4.0,
+            |assessment| assessment.ac_burst,
+            MotionDetector::MAX_AC_BURST,
+        );
+    }
+
+    #[test]
+    fn ambient_variation_is_flagged() {
+        assert_flagged(
+            |sample| sample.ambient_current += 0.5e-6,
+            |assessment| assessment.ambient_variation,
+            MotionDetector::MAX_AMBIENT_VARIATION,
+        );
+    }
+
+    #[test]
+    fn clipped_channels_are_left_out() {
+        // The IR channel carries no pulse and its DC level jumps while it is clipped.
+        let mut ir_clipped = detector();
+        let assessments = detect(&mut ir_clipped, 30_000, |timestamp| {
+            let mut sample = clean(timestamp);
+            if (10_000..12_000).contains(&timestamp) {
+                sample.clipped[2] = true;
+                sample.ac[2] = 0.0;
+                sample.dc[2] = 1.2;
+            }
+            sample
+        });
+
+        for (timestamp, assessment) in assessments {
+            assert!(!assessment.moving, "{:?} at {} ms", assessment, timestamp);
+        }
+        assert_eq!(ir_clipped.last_motion(), None);
+
+        // A clipped green channel leaves out the AC burst.
+        let assessments = detect(&mut detector(), 30_000, |timestamp| {
+            let mut sample = clean(timestamp);
+            if (10_000..12_000).contains(&timestamp) {
+                sample.clipped[0] = true;
+                sample.ac[0] *= 4.0;
+            }
+            sample
+        });
+        assert!(assessments.iter().all(|(_, assessment)| {
+            !assessment.moving && assessment.ac_burst < MotionDetector::MAX_AC_BURST
+        }));
+    }
+
+    #[test]
+    fn motion_bursts_are_flagged() {
+        // Bursts from 15 to 18 s, from 30 to 33 s and from 45 to 48 s.
+        let (interval, duration) = (15_000, 3_000);
+        let configuration = PpgConfiguration {
+            motion_interval: Time::new::<millisecond>(interval as f32),
+            motion_duration: Time::new::<millisecond>(duration as f32),
+            motion_amplitude: 0.2,
+            ..Default::default()
+        };
+        let outputs = measure(configuration, 60.0, |_| {});
+
+        for start in [interval, 2 * interval, 3 * interval] {
+            // The motion is flagged before the end of the burst, despite the delay of the filters.
+            assert!(
+                outputs
+                    .iter()
+                    .filter(|output| (start..start + duration).contains(&output.timestamp))
+                    .any(|output| output.motion.unwrap().moving),
+                "burst at {} ms",
+                start
+            );
+            // The flag is cleared once the burst has left the window and the hold time has elapsed.
+            let settled = start + duration + 7_000;
+            for output in outputs
+                .iter()
+                .filter(|output| (settled..start + interval).contains(&output.timestamp))
+            {
+                let motion = output.motion.unwrap();
+                assert!(!motion.moving, "{:?} at {} ms", motion, output.timestamp);
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+use super::beat_detection::{Beat, BeatDetector};
+use crate::protocol::FilteredData;
+
+/// The amplitude of the pulse of a channel over a single beat.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PulseAmplitude {
+    /// The peak-to-peak AC amplitude, between the systolic peak and the diastolic foot.
+    pub ac: f32,
+    /// The DC level at the systolic peak.
+    pub dc: f32,
+}
+
+impl PulseAmplitude {
+    /// Gets the peak-to-peak perfusion index, the AC amplitude over the DC level, in percent.
+    pub fn perfusion_index(&self) -> f32 {
+        if self.dc > 0.0 {
+            self.ac / self.dc * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Measures the pulse amplitude of every channel over the beats detected on LED1. Since the pulse of the other
+/// channels may be slightly shifted, their extrema are searched in the beat widened by a margin.
+pub struct PulseAmplitudeMeter {
+    history: VecDeque<(u128, FilteredData)>,
+    history_length: usize,
+}
+
+impl PulseAmplitudeMeter {
+    /// The time the beat is widened by on each side, in milliseconds.
+    pub const MARGIN: u128 = 90;
+
+    /// Creates a new `PulseAmplitudeMeter` for signals sampled every `sample_period`.
+    pub fn new(sample_period: Time) -> Self {
+        // Long enough to measure a beat found by the search-back after the longest interval.
+        let duration = BeatDetector::SEARCH_BACK_RATIO * BeatDetector::INTERVAL_RANGE.1 as f32
+            + (BeatDetector::MAX_RISE_TIME + Self::MARGIN) as f32;
+
+        Self {
+            history: VecDeque::new(),
+            history_length: ((duration / sample_period.get::<millisecond>()).round() as usize)
+                .max(2),
+        }
+    }
+
+    /// Discards the history, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Pushes the filtered data taken at `timestamp`.
+    pub fn push(&mut self, filtered_data: FilteredData, timestamp: u128) {
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back((timestamp, filtered_data));
+    }
+
+    /// Measures the pulse amplitude of LED1, LED2 and LED3 over the beat, from its onset to its systolic peak.
+    /// The photodiode current is minimum at the systolic peak and maximum at the diastolic foot.
+    pub fn measure(&self, beat: &Beat) -> Option<[PulseAmplitude; 3]> {
+        let start = beat.onset.saturating_sub(Self::MARGIN);
+        let end = beat.peak + Self::MARGIN;
+        let samples: Vec<&(u128, FilteredData)> = self
+            .history
+            .iter()
+            .filter(|(timestamp, _)| (start..=end).contains(timestamp))
+            .collect();
+        if samples.len() < 2 {
+            return None;
+        }
+
+        let &(_, peak_data) = samples
+            .iter()
+            .min_by_key(|(timestamp, _)| timestamp.abs_diff(beat.peak))?;
+
+        let mut amplitudes = [PulseAmplitude::default(); 3];
+        for (i, amplitude) in amplitudes.iter_mut().enumerate() {
+            let (minimum, maximum) = samples
+                .iter()
+                .map(|(_, data)| data[i].1)
+                .fold((f32::MAX, f32::MIN), |(minimum, maximum), ac| {
+                    (minimum.min(ac), maximum.max(ac))
+                });
+            *amplitude = PulseAmplitude {
+                ac: maximum - minimum,
+                dc: peak_data[i].0,
+            };
+        }
+
+        Some(amplitudes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::tests::measure,
+        synthetic::{PpgConfiguration, SyntheticPpg},
+    };
+
+    #[test]
+    fn measures_the_synthetic_perfusion_index() {
+        let mut attenuations = vec![];
+        for perfusion_index in [0.5, 1.2, 3.0] {
+            let configuration = PpgConfiguration {
+                perfusion_index,
+                respiration_baseline_modulation: 0.0,
+                respiration_amplitude_modulation: 0.0,
+                ..Default::default()
+            };
+            let outputs = measure(configuration, 40.0, |_| {});
+            let amplitudes: Vec<[PulseAmplitude; 3]> = outputs
+                .iter()
+                .filter_map(|output| output.pulse_amplitudes)
+                .collect();
+            assert!(amplitudes.len() > 30);
+            let mean = |channel: usize| {
+                amplitudes
+                    .iter()
+                    .map(|amplitudes| amplitudes[channel].perfusion_index())
+                    .sum::<f32>()
+                    / amplitudes.len() as f32
+            };
+            let (green, red, ir) = (mean(0), mean(1), mean(2));
+
+            let ratio = |value: f32, expected: f32| (value / expected - 1.0).abs() < 0.05;
+            assert!(
+                ratio(green / ir, configuration.green_perfusion_ratio),
+                "{} {}",
+                green,
+                ir
+            );
+            assert!(
+                ratio(red / ir, SyntheticPpg::new(configuration).r()),
+                "{} {}",
+                red,
+                ir
+            );
+            attenuations.push(ir / perfusion_index);
+        }
+
+        // The AC band-pass filter removes the harmonics of the pulse, which lowers its peak-to-peak amplitude by the
+        // same factor at any perfusion.
+        for &attenuation in &attenuations {
+            assert!(attenuation > 0.5 && attenuation < 0.8, "{:?}", attenuations);
+            assert!(
+                (attenuation / attenuations[0] - 1.0).abs() < 0.05,
+                "{:?}",
+                attenuations
+            );
+        }
+    }
+}
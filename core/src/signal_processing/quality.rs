@@ -0,0 +1,556 @@
+use std::collections::VecDeque;
+
+use uom::si::{
+    electric_potential::volt,
+    f32::{ElectricPotential, Time},
+    time::millisecond,
+};
+
+use super::beat_detection::{Beat, BeatDetector};
+
+/// The quality of the pulse over a beat, with the metrics it is computed from.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SignalQuality {
+    /// The signal quality index of the beat, from 0 (unusable) to 100 (clean).
+    pub sqi: f32,
+    /// The mean signal quality index of the beats in the window, from 0 to 100.
+    pub window_sqi: f32,
+    /// The correlation of the beat with the template, the average of the recent clean beats, from -1 to 1.
+    pub correlation: f32,
+    /// The skewness of the pulse over the beat, positive for a sharp systolic peak over a wide diastole.
+    pub skewness: f32,
+    /// The peak-to-peak perfusion index of the channel, in percent.
+    pub perfusion_index: f32,
+    /// The ratio of the perfusion index to the median one of the recent beats.
+    pub perfusion_ratio: f32,
+    /// The number of times the pulse crosses its mean over the beat, 2 for a clean beat.
+    pub zero_crossings: usize,
+    /// Whether a reading of the ADC has been clipped during the beat.
+    pub clipped: bool,
+}
+
+/// Assesses the quality of the pulse of a channel over every beat, from the previous systolic peak to the current one.
+/// Each metric is scored from 0 to 1 and the scores are multiplied into a signal quality index from 0 to 100, so
+/// that a single bad metric is enough to reject the beat. It is 0 if the ADC has been clipped.
+pub struct SignalQualityAssessor {
+    history: VecDeque<(u128, f32, bool)>,
+    history_length: usize,
+    template: Option<[f32; Self::TEMPLATE_LENGTH]>,
+    perfusion_indices: VecDeque<f32>,
+    template_beats: usize,
+    rejected_beats: usize,
+    beats: VecDeque<(u128, f32)>,
+    window: u128,
+}
+
+impl SignalQualityAssessor {
+    /// The minimum signal quality index of a beat for it to be used by the estimators.
+    pub const MIN_SQI: f32 = 50.0;
+    /// The number of points the beats are resampled to, to be compared with the template.
+    pub const TEMPLATE_LENGTH: usize = 32;
+    /// The number of beats averaged to learn the template.
+    pub const TEMPLATE_BEATS: usize = 4;
+    /// The weight of a clean beat in the template, once it has been learned.
+    pub const TEMPLATE_WEIGHT: f32 = 0.1;
+    /// The number of consecutive beats with a low signal quality index after which the template is learned again,
+    /// since the shape of the pulse may have changed.
+    pub const MAX_REJECTED_BEATS: usize = 16;
+    /// The correlation with the template below which the beat is scored 0, and above which it is scored 1.
+    pub const CORRELATION_RANGE: (f32, f32) = (0.7, 0.95);
+    /// The skewness below which the beat is scored 0, and above which it is scored 1.
+    pub const SKEWNESS_RANGE: (f32, f32) = (-1.0, -0.3);
+    /// The perfusion index, in percent, below which the beat is scored 0, and above which it is scored 1.
+    pub const PERFUSION_INDEX_RANGE: (f32, f32) = (0.05, 0.2);
+    /// The number of recent beats whose median perfusion index is the reference one. The median is not
+    /// moved by the artefacts as long as most beats are clean.
+    pub const REFERENCE_BEATS: usize = 32;
+    /// The ratio of the perfusion index to the reference one, or its inverse, above which the beat is scored
+    /// 0, and below which it is scored 1. The motion artefacts usually change the amplitude of the pulse.
+    pub const PERFUSION_RATIO_RANGE: (f32, f32) = (2.5, 1.5);
+    /// The fraction of the peak-to-peak amplitude around the mean that the pulse has to leave to cross it.
+    pub const HYSTERESIS: f32 = 0.1;
+    /// The absolute reading, in volts, from which the ADC is considered clipped, just below its full scale of 1.2 V.
+    pub const CLIPPING_LEVEL: f32 = 0.99 * 1.2;
+
+    /// Creates a new `SignalQualityAssessor` for signals sampled every `sample_period`, with the window of the mean
+    /// signal quality index.
+    pub fn new(sample_period: Time, window: Time) -> Self {
+        Self {
+            history: VecDeque::new(),
+            history_length: ((BeatDetector::INTERVAL_RANGE.1 as f32
+                / sample_period.get::<millisecond>())
+            .round() as usize)
+                .max(2),
+            template: None,
+            perfusion_indices: VecDeque::new(),
+            template_beats: 0,
+            rejected_beats: 0,
+            beats: VecDeque::new(),
+            window: window.get::<millisecond>().round() as u128,
+        }
+    }
+
+    /// Discards the history, the template and the recent beats, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.template = None;
+        self.perfusion_indices.clear();
+        self.template_beats = 0;
+        self.rejected_beats = 0;
+        self.beats.clear();
+    }
+
+    /// Checks whether a reading of the ADC has been clipped.
+    pub fn clipped(reading: ElectricPotential) -> bool {
+        reading.get::<volt>().abs() >= Self::CLIPPING_LEVEL
+    }
+
+    /// Pushes the pulse taken at `timestamp`, with whether the readings it is computed from have been clipped.
+    pub fn push(&mut self, pulse: f32, clipped: bool, timestamp: u128) {
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back((timestamp, pulse, clipped));
+    }
+
+    /// Assesses the quality of the beat, with the peak-to-peak perfusion index of the channel over it. Returns `None` if
+    /// the interval from the previous beat is unknown or not physiological.
+    pub fn assess(&mut self, beat: &Beat, perfusion_index: f32) -> Option<SignalQuality> {
+        let interval = beat.interval.filter(|interval| {
+            (BeatDetector::INTERVAL_RANGE.0..=BeatDetector::INTERVAL_RANGE.1).contains(interval)
+        })?;
+        let start = beat.peak.saturating_sub(interval);
+        let samples: Vec<(u128, f32)> = self
+            .history
+            .iter()
+            .filter(|(timestamp, _, _)| (start..=beat.peak).contains(timestamp))
+            .map(|&(timestamp, pulse, _)| (timestamp, pulse))
+            .collect();
+        if samples.len() < 4 {
+            return None;
+        }
+        let clipped = self
+            .history
+            .iter()
+            .any(|&(timestamp, _, clipped)| clipped && (start..=beat.peak).contains(&timestamp));
+
+        let count = samples.len() as f32;
+        let mean = samples.iter().map(|&(_, pulse)| pulse).sum::<f32>() / count;
+        let deviation = (samples
+            .iter()
+            .map(|&(_, pulse)| (pulse - mean).powi(2))
+            .sum::<f32>()
+            / count)
+            .sqrt();
+        let skewness = if deviation > 0.0 {
+            samples
+                .iter()
+                .map(|&(_, pulse)| ((pulse - mean) / deviation).powi(3))
+                .sum::<f32>()
+                / count
+        } else {
+            0.0
+        };
+
+        let beat_shape = Self::resample(&samples, start, beat.peak);
+        let correlation = self.template.map_or(0.0, |template| {
+            template
+                .iter()
+                .zip(beat_shape.iter())
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / Self::TEMPLATE_LENGTH as f32
+        });
+        let zero_crossings = Self::zero_crossings(&samples, mean);
+        // A clean beat crosses its mean twice, the noise adds crossings and a baseline shift removes them.
+        let crossing_score = if zero_crossings == 0 {
+            0.0
+        } else {
+            (2.0 / zero_crossings as f32).min(1.0)
+        };
+
+        if !clipped {
+            if self.perfusion_indices.len() == Self::REFERENCE_BEATS {
+                self.perfusion_indices.pop_front();
+            }
+            self.perfusion_indices.push_back(perfusion_index);
+        }
+        let mut reference: Vec<f32> = self.perfusion_indices.iter().copied().collect();
+        reference.sort_unstable_by(f32::total_cmp);
+        let perfusion_ratio = match reference.get(reference.len() / 2) {
+            Some(&reference) if reference > 0.0 => perfusion_index / reference,
+            _ => 1.0,
+        };
+
+        let score =
+            |value: f32, (low, high): (f32, f32)| ((value - low) / (high - low)).clamp(0.0, 1.0);
+        let sqi = if clipped {
+            0.0
+        } else {
+            100.0
+                * score(correlation, Self::CORRELATION_RANGE)
+                * score(skewness, Self::SKEWNESS_RANGE)
+                * score(perfusion_index, Self::PERFUSION_INDEX_RANGE)
+                * score(
+                    perfusion_ratio.max(1.0 / perfusion_ratio),
+                    Self::PERFUSION_RATIO_RANGE,
+                )
+                * crossing_score
+        };
+
+        self.update_template(&beat_shape, sqi, clipped);
+
+        self.beats.push_back((beat.peak, sqi));
+        while let Some(&(oldest, _)) = self.beats.front() {
+            if beat.peak.saturating_sub(oldest) <= self.window {
+                break;
+            }
+            self.beats.pop_front();
+        }
+        let window_sqi =
+            self.beats.iter().map(|&(_, sqi)| sqi).sum::<f32>() / self.beats.len() as f32;
+
+        Some(SignalQuality {
+            sqi,
+            window_sqi,
+            correlation,
+            skewness,
+            perfusion_index,
+            perfusion_ratio,
+            zero_crossings,
+            clipped,
+        })
+    }
+
+    /// Learns the template from the first beats, then updates it with the clean beats only.
+    fn update_template(
+        &mut self,
+        beat_shape: &[f32; Self::TEMPLATE_LENGTH],
+        sqi: f32,
+        clipped: bool,
+    ) {
+        if clipped {
+            return;
+        }
+
+        if sqi < Self::MIN_SQI && self.template_beats >= Self::TEMPLATE_BEATS {
+            self.rejected_beats += 1;
+            if self.rejected_beats >= Self::MAX_REJECTED_BEATS {
+                log::debug!("Learning the beat template again.");
+                self.template = None;
+                self.template_beats = 0;
+                self.rejected_beats = 0;
+            }
+            return;
+        }
+        self.rejected_beats = 0;
+
+        // The learned template is the mean of the first beats.
+        let weight = if self.template_beats < Self::TEMPLATE_BEATS {
+            1.0 / (self.template_beats + 1) as f32
+        } else {
+            Self::TEMPLATE_WEIGHT
+        };
+        let mut template = self.template.unwrap_or([0.0; Self::TEMPLATE_LENGTH]);
+        for (value, new) in template.iter_mut().zip(beat_shape.iter()) {
+            *value += weight * (new - *value);
+        }
+        Self::normalise(&mut template);
+        self.template = Some(template);
+        self.template_beats += 1;
+    }
+
+    /// Resamples the beat to the template length with linear interpolation, normalised to zero mean and unit
+    /// variance.
+    fn resample(samples: &[(u128, f32)], start: u128, end: u128) -> [f32; Self::TEMPLATE_LENGTH] {
+        let mut shape = [0.0; Self::TEMPLATE_LENGTH];
+        let duration = end.saturating_sub(start) as f32;
+        let mut next = 1;
+        for (i, value) in shape.iter_mut().enumerate() {
+            let time = start as f32 + duration * i as f32 / (Self::TEMPLATE_LENGTH - 1) as f32;
+            while next < samples.len() - 1 && (samples[next].0 as f32) < time {
+                next += 1;
+            }
+            let (t0, v0) = samples[next - 1];
+            let (t1, v1) = samples[next];
+            let fraction = ((time - t0 as f32) / (t1 - t0).max(1) as f32).clamp(0.0, 1.0);
+            *value = v0 + fraction * (v1 - v0);
+        }
+        Self::normalise(&mut shape);
+
+        shape
+    }
+
+    /// Normalises the values to zero mean and unit variance.
+    fn normalise(values: &mut [f32]) {
+        let count = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / count;
+        let deviation = (values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / count)
+            .sqrt();
+        for value in values.iter_mut() {
+            *value = if deviation > 0.0 {
+                (*value - mean) / deviation
+            } else {
+                0.0
+            };
+        }
+    }
+
+    /// Counts the times the pulse crosses its mean, with a hysteresis so that the noise does not add crossings.
+    fn zero_crossings(samples: &[(u128, f32)], mean: f32) -> usize {
+        let (minimum, maximum) = samples
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(minimum, maximum), &(_, pulse)| {
+                (minimum.min(pulse), maximum.max(pulse))
+            });
+        let hysteresis = Self::HYSTERESIS * (maximum - minimum);
+
+        let mut crossings = 0;
+        let mut above: Option<bool> = None;
+        for &(_, pulse) in samples {
+            let side = if pulse > mean + hysteresis {
+                Some(true)
+            } else if pulse < mean - hysteresis {
+                Some(false)
+            } else {
+                None
+            };
+            if let Some(side) = side {
+                if above.is_some_and(|above| above != side) {
+                    crossings += 1;
+                }
+                above = Some(side);
+            }
+        }
+
+        crossings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipeline::tests::measure, synthetic::PpgConfiguration};
+
+    const SAMPLE_PERIOD: u128 = 30;
+    const INTERVAL: u128 = 900;
+    /// The time of the systolic peak since the onset of the pulse.
+    const PEAK: u128 = 150;
+    /// A pulse starting at its onset, sampled every 30 ms: a slow then a steep upstroke up to the peak after
+    /// 150 ms, and a linear decay back to the baseline.
+    const PULSE: [f32; 21] = [
+        0.0, 0.05, 0.1, 0.4, 0.7, 1.0, 0.93, 0.86, 0.79, 0.72, 0.65, 0.58, 0.51, 0.44, 0.37, 0.3,
+        0.23, 0.16, 0.09, 0.02, 0.0,
+    ];
+
+    /// Gets the pulse at the given time since its onset.
+    fn pulse(time: u128) -> f32 {
+        PULSE
+            .get(((time % INTERVAL) / SAMPLE_PERIOD) as usize)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The pulse delayed by half an interval, so that its shape between the peaks of the beats is different.
+    fn delayed_pulse(time: u128) -> f32 {
+        pulse(time + INTERVAL / 2)
+    }
+
+    /// A sample of a beat: its index and its time since the onset.
+    struct Sample {
+        beat: usize,
+        time: u128,
+    }
+
+    /// Feeds `beats` pulses with the given value of each sample, with whether it is clipped, and assesses every
+    /// beat after the first one with the given perfusion index.
+    fn assess(
+        beats: usize,
+        value: impl Fn(&Sample) -> (f32, bool),
+        perfusion_index: impl Fn(usize) -> f32,
+    ) -> Vec<SignalQuality> {
+        let mut assessor = SignalQualityAssessor::new(
+            Time::new::<millisecond>(SAMPLE_PERIOD as f32),
+            Time::new::<millisecond>(5000.0),
+        );
+        let mut qualities = Vec::new();
+        for timestamp in (0..beats as u128 * INTERVAL).step_by(SAMPLE_PERIOD as usize) {
+            let sample = Sample {
+                beat: (timestamp / INTERVAL) as usize,
+                time: timestamp % INTERVAL,
+            };
+            let (pulse, clipped) = value(&sample);
+            assessor.push(pulse, clipped, timestamp);
+
+            if sample.time == PEAK && sample.beat > 0 {
+                let beat = Beat {
+                    onset: timestamp - PEAK,
+                    peak: timestamp,
+                    foot: timestamp - PEAK + 50,
+                    amplitude: 1.0,
+                    interval: Some(INTERVAL),
+                    searched_back: false,
+                };
+                qualities.extend(assessor.assess(&beat, perfusion_index(sample.beat)));
+            }
+        }
+
+        qualities
+    }
+
+    fn clean(sample: &Sample) -> (f32, bool) {
+        (pulse(sample.time), false)
+    }
+
+    #[test]
+    fn clean_beats_are_accepted() {
+        let qualities = assess(20, clean, |_| 0.5);
+
+        assert_eq!(qualities.len(), 19);
+        // The first beat has no template to be compared with.
+        assert_eq!(qualities[0].sqi, 0.0);
+        for quality in &qualities[1..] {
+            assert!(quality.sqi > 99.0, "{:?}", quality);
+            assert!(quality.correlation > 0.99, "{:?}", quality);
+            assert!(quality.skewness > 0.0, "{:?}", quality);
+            assert_eq!(quality.perfusion_ratio, 1.0);
+            assert_eq!(quality.zero_crossings, 2);
+            assert!(!quality.clipped);
+        }
+    }
+
+    #[test]
+    fn clipped_beat_is_rejected() {
+        let qualities = assess(
+            20,
+            |sample| (pulse(sample.time), sample.beat == 10 && sample.time == 300),
+            |_| 0.5,
+        );
+
+        // The clipped sample is in the beat that ends at the next peak.
+        let clipped = &qualities[10];
+        assert!(clipped.clipped);
+        assert_eq!(clipped.sqi, 0.0);
+        // The window of 5 s covers the clipped beat and the five previous ones.
+        let window = &qualities[5..=10];
+        let mean_sqi = window.iter().map(|quality| quality.sqi).sum::<f32>() / 6.0;
+        assert!(
+            (clipped.window_sqi - mean_sqi).abs() < 1e-3,
+            "{:?}",
+            clipped
+        );
+
+        assert!(qualities[11..].iter().all(|quality| quality.sqi > 99.0));
+    }
+
+    #[test]
+    fn weak_beats_are_rejected() {
+        let (low, high) = SignalQualityAssessor::PERFUSION_INDEX_RANGE;
+        let weak = assess(10, clean, |_| 0.8 * low);
+        assert!(weak.iter().all(|quality| quality.sqi == 0.0));
+
+        // The score rises linearly across the range.
+        let middle = assess(10, clean, |_| (low + high) / 2.0);
+        assert!(middle[1..]
+            .iter()
+            .all(|quality| (quality.sqi - 50.0).abs() < 1.0));
+    }
+
+    #[test]
+    fn sudden_change_of_the_perfusion_index_is_rejected() {
+        let qualities = assess(20, clean, |beat| if beat == 12 { 1.5 } else { 0.5 });
+
+        // The median perfusion index of the recent beats is not moved by a single beat.
+        assert_eq!(qualities[11].perfusion_ratio, 3.0);
+        assert_eq!(qualities[11].sqi, 0.0);
+        assert!(qualities[12..].iter().all(|quality| quality.sqi > 99.0));
+    }
+
+    #[test]
+    fn noisy_beat_is_rejected() {
+        // An oscillation between consecutive samples, larger than the hysteresis.
+        let qualities = assess(
+            20,
+            |sample| {
+                let noise = if sample.beat == 10 && sample.time >= 300 {
+                    if (sample.time / SAMPLE_PERIOD) % 2 == 0 {
+                        0.3
+                    } else {
+                        -0.3
+                    }
+                } else {
+                    0.0
+                };
+                (pulse(sample.time) + noise, false)
+            },
+            |_| 0.5,
+        );
+
+        let noisy = &qualities[10];
+        assert!(noisy.zero_crossings > 2, "{:?}", noisy);
+        assert!(noisy.sqi < SignalQualityAssessor::MIN_SQI, "{:?}", noisy);
+        assert!(qualities[11..].iter().all(|quality| quality.sqi > 99.0));
+    }
+
+    #[test]
+    fn template_is_learned_again_when_the_shape_changes() {
+        let qualities = assess(
+            40,
+            |sample| {
+                if sample.beat < 10 {
+                    clean(sample)
+                } else {
+                    (delayed_pulse(sample.time), false)
+                }
+            },
+            |_| 0.5,
+        );
+
+        // The beat over the change of shape is rejected too, and the template is learned again after the last
+        // rejected beat.
+        let relearned = 9 + SignalQualityAssessor::MAX_REJECTED_BEATS;
+        assert!(qualities[9].sqi < SignalQualityAssessor::MIN_SQI);
+        for quality in &qualities[10..relearned] {
+            assert!(
+                quality.correlation < SignalQualityAssessor::CORRELATION_RANGE.0,
+                "{:?}",
+                quality
+            );
+            assert_eq!(quality.sqi, 0.0);
+        }
+        assert_eq!(qualities[relearned].correlation, 0.0);
+
+        // Once the template has been learned from the new shape, the beats are accepted.
+        for quality in &qualities[relearned + 1..] {
+            assert!(quality.sqi > 99.0, "{:?}", quality);
+        }
+    }
+
+    #[test]
+    fn synthetic_pulse_is_accepted() {
+        let qualities: Vec<SignalQuality> = measure(PpgConfiguration::default(), 40.0, |_| {})
+            .iter()
+            .filter_map(|output| output.signal_quality)
+            .skip(SignalQualityAssessor::TEMPLATE_BEATS + 1)
+            .collect();
+
+        assert!(qualities.len() > 25);
+        for quality in qualities {
+            assert!(
+                quality.sqi >= SignalQualityAssessor::MIN_SQI,
+                "{:?}",
+                quality
+            );
+            assert!(
+                quality.correlation > SignalQualityAssessor::CORRELATION_RANGE.1,
+                "{:?}",
+                quality
+            );
+            assert_eq!(quality.zero_crossings, 2, "{:?}", quality);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+use super::{beat_detection::Beat, lomb_scargle::lomb_scargle};
+
+/// A respiration rate estimated from the respiratory modulations of the pulse.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RespirationEstimate {
+    /// The fused respiration rate, in breaths per minute.
+    pub respiration_rate: f32,
+    /// Whether the modulations agree on the respiration rate.
+    pub reliable: bool,
+    /// The respiration rate from the respiratory induced intensity variation (RIIV), in breaths per minute.
+    pub intensity_rate: Option<f32>,
+    /// The respiration rate from the respiratory induced amplitude variation (RIAV), in breaths per minute.
+    pub amplitude_rate: Option<f32>,
+    /// The respiration rate from the respiratory induced frequency variation (RIFV), in breaths per minute.
+    pub frequency_rate: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BeatFeatures {
+    timestamp: u128,
+    intensity: f32,
+    amplitude: f32,
+    interval: Option<f32>,
+}
+
+/// Estimates the respiration rate from the beat-to-beat modulations of the pulse: its baseline intensity (RIIV),
+/// its amplitude (RIAV) and the interval between the beats (RIFV). The rate of each modulation is the peak of the
+/// Lomb–Scargle periodogram of its beat series over a sliding window, and the rates are fused with their mean when
+/// they agree.
+pub struct RespirationEstimator {
+    beats: VecDeque<BeatFeatures>,
+    window: u128,
+    period: u128,
+    last_estimate: Option<u128>,
+    frequencies: Vec<f32>,
+}
+
+impl RespirationEstimator {
+    /// The minimum respiration rate that can be estimated, in breaths per minute.
+    pub const MIN_RESPIRATION_RATE: f32 = 6.0;
+    /// The maximum respiration rate that can be estimated, in breaths per minute.
+    pub const MAX_RESPIRATION_RATE: f32 = 42.0;
+    /// The distance between the frequencies of the periodogram, in breaths per minute.
+    pub const RESOLUTION: f32 = 0.5;
+    /// The minimum number of beats in the window for a modulation to be used.
+    pub const MIN_BEATS: usize = 16;
+    /// The maximum standard deviation of the rates of the modulations for the fused rate to be reliable, in breaths
+    /// per minute.
+    pub const MAX_DEVIATION: f32 = 4.0;
+
+    /// Creates a new `RespirationEstimator` that estimates the respiration rate every `period` over the beats of
+    /// the last `window`.
+    pub fn new(window: Time, period: Time) -> Self {
+        Self {
+            beats: VecDeque::new(),
+            window: window.get::<millisecond>().round() as u128,
+            period: period.get::<millisecond>().round() as u128,
+            last_estimate: None,
+            frequencies: (0..)
+                .map(|i| (Self::MIN_RESPIRATION_RATE + i as f32 * Self::RESOLUTION) / 60.0)
+                .take_while(|&frequency| frequency <= Self::MAX_RESPIRATION_RATE / 60.0)
+                .collect(),
+        }
+    }
+
+    /// Discards all the beats, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.beats.clear();
+        self.last_estimate = None;
+    }
+
+    /// Pushes a beat, with the baseline intensity of the pulse at its peak, and returns the estimate when the beats
+    /// span the window and a period has elapsed since the last estimate.
+    pub fn push(&mut self, beat: &Beat, intensity: f32) -> Option<RespirationEstimate> {
+        let timestamp = beat.peak;
+        self.beats.push_back(BeatFeatures {
+            timestamp,
+            intensity,
+            amplitude: beat.amplitude,
+            interval: beat.interval.map(|interval| interval as f32),
+        });
+        while let Some(oldest) = self.beats.front() {
+            if timestamp.saturating_sub(oldest.timestamp) <= self.window {
+                break;
+            }
+            self.beats.pop_front();
+        }
+
+        // Wait until the window is almost full, one beat may be missing at its start.
+        let first = self.beats.front()?.timestamp;
+        if timestamp.saturating_sub(first) < self.window * 9 / 10 {
+            return None;
+        }
+        if let Some(last_estimate) = self.last_estimate {
+            if timestamp.saturating_sub(last_estimate) < self.period {
+                return None;
+            }
+        }
+        self.last_estimate = Some(timestamp);
+
+        self.estimate()
+    }
+
+    /// Estimates the respiration rate from the modulations of the beats in the window.
+    fn estimate(&self) -> Option<RespirationEstimate> {
+        let intensity_rate = self.rate(|beat| Some(beat.intensity));
+        let amplitude_rate = self.rate(|beat| Some(beat.amplitude));
+        let frequency_rate = self.rate(|beat| beat.interval);
+
+        let rates: Vec<f32> = [intensity_rate, amplitude_rate, frequency_rate]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        if rates.is_empty() {
+            return None;
+        }
+
+        let count = rates.len() as f32;
+        let respiration_rate = rates.iter().sum::<f32>() / count;
+        let deviation = (rates
+            .iter()
+            .map(|rate| (rate - respiration_rate).powi(2))
+            .sum::<f32>()
+            / count)
+            .sqrt();
+
+        Some(RespirationEstimate {
+            respiration_rate,
+            // A single modulation cannot be cross-checked.
+            reliable: rates.len() > 1 && deviation <= Self::MAX_DEVIATION,
+            intensity_rate,
+            amplitude_rate,
+            frequency_rate,
+        })
+    }
+
+    /// Gets the respiration rate of a modulation, in breaths per minute, from the peak of its periodogram.
+    fn rate<F: Fn(&BeatFeatures) -> Option<f32>>(&self, feature: F) -> Option<f32> {
+        let (times, values): (Vec<f32>, Vec<f32>) = self
+            .beats
+            .iter()
+            .filter_map(|beat| {
+                feature(beat).map(|value| {
+                    (
+                        (beat.timestamp - self.beats[0].timestamp) as f32 / 1000.0,
+                        value,
+                    )
+                })
+            })
+            .unzip();
+        if values.len() < Self::MIN_BEATS {
+            return None;
+        }
+
+        // Remove the linear trend, which would leak into the lowest frequencies.
+        let values = detrend(&times, &values);
+
+        // The beats sample the modulation, so it aliases above half the mean heart rate.
+        let span = times[times.len() - 1] - times[0];
+        let nyquist = (times.len() - 1) as f32 / span / 2.0;
+        let frequencies: Vec<f32> = self
+            .frequencies
+            .iter()
+            .copied()
+            .take_while(|&frequency| frequency < nyquist)
+            .collect();
+        let periodogram = lomb_scargle(&times, &values, &frequencies);
+
+        let (peak, _) = periodogram
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        Some(frequencies[peak] * 60.0)
+    }
+}
+
+/// Removes the least squares line from the values, taken at the given times.
+fn detrend(times: &[f32], values: &[f32]) -> Vec<f32> {
+    let count = values.len() as f32;
+    let mean_time = times.iter().sum::<f32>() / count;
+    let mean_value = values.iter().sum::<f32>() / count;
+
+    let covariance: f32 = times
+        .iter()
+        .zip(values)
+        .map(|(time, value)| (time - mean_time) * (value - mean_value))
+        .sum();
+    let variance: f32 = times.iter().map(|time| (time - mean_time).powi(2)).sum();
+    let slope = if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    };
+
+    times
+        .iter()
+        .zip(values)
+        .map(|(time, value)| value - mean_value - slope * (time - mean_time))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipeline::tests::measure, synthetic::PpgConfiguration};
+
+    #[test]
+    fn estimates_the_synthetic_respiration_rate() {
+        for respiration_rate in [12.0, 15.0, 24.0] {
+            let outputs = measure(
+                PpgConfiguration {
+                    respiration_rate,
+                    ..Default::default()
+                },
+                90.0,
+                |_| {},
+            );
+            let estimates: Vec<RespirationEstimate> = outputs
+                .iter()
+                .filter_map(|output| output.respiration)
+                .collect();
+
+            assert!(estimates.len() >= 8);
+            for estimate in estimates {
+                assert!(
+                    (estimate.respiration_rate - respiration_rate).abs() < 1.0,
+                    "{:?} for {} breaths/min",
+                    estimate,
+                    respiration_rate
+                );
+                assert!(estimate.reliable, "{:?}", estimate);
+                // The synthetic pulse has all three respiratory modulations.
+                for rate in [
+                    estimate.intensity_rate,
+                    estimate.amplitude_rate,
+                    estimate.frequency_rate,
+                ] {
+                    assert!(
+                        rate.is_some_and(|rate| (rate - respiration_rate).abs() < 1.0),
+                        "{:?}",
+                        estimate
+                    );
+                }
+            }
+        }
+    }
+}
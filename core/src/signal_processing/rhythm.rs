@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+/// The assessment of the heart rhythm over a window of RR intervals.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RhythmAssessment {
+    /// Whether the rhythm is irregular, as in atrial fibrillation: all the metrics exceed their thresholds.
+    pub irregular: bool,
+    /// How strongly the metrics indicate an irregular rhythm, from 0 to 1.
+    pub confidence: f32,
+    /// The RMSSD divided by the mean interval.
+    pub normalised_rmssd: f32,
+    /// The Shannon entropy of the histogram of the intervals, normalised from 0 to 1.
+    pub entropy: f32,
+    /// The number of turning points divided by the number of intervals that can be turning points.
+    pub turning_point_ratio: f32,
+}
+
+/// Detects an irregular rhythm, such as atrial fibrillation, from the RR intervals of a sliding window.
+/// The rhythm is irregular when the intervals are variable (normalised RMSSD), spread over many values (Shannon
+/// entropy) and randomly ordered (turning point ratio), since in atrial fibrillation the beats are uncorrelated.
+pub struct IrregularRhythmDetector {
+    intervals: VecDeque<(u128, u128)>,
+    window: u128,
+}
+
+impl IrregularRhythmDetector {
+    /// The minimum number of intervals in the window to assess the rhythm.
+    pub const MIN_INTERVALS: usize = 32;
+    /// The normalised RMSSD above which the intervals are considered variable.
+    pub const RMSSD_THRESHOLD: f32 = 0.1;
+    /// The normalised Shannon entropy above which the intervals are considered spread.
+    pub const ENTROPY_THRESHOLD: f32 = 0.7;
+    /// The number of bins of the histogram used for the Shannon entropy.
+    pub const HISTOGRAM_BINS: usize = 16;
+    /// The turning point ratios within this number of standard deviations from the one of a random series are
+    /// considered random.
+    pub const TURNING_POINT_DEVIATIONS: f32 = 2.0;
+
+    /// Creates a new `IrregularRhythmDetector` that assesses the rhythm over the intervals of the last `window`.
+    pub fn new(window: Time) -> Self {
+        Self {
+            intervals: VecDeque::new(),
+            window: window.get::<millisecond>().round() as u128,
+        }
+    }
+
+    /// Discards all the intervals, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.intervals.clear();
+    }
+
+    /// Pushes the interval, in milliseconds, ending at `timestamp`, and returns the assessment of the rhythm if
+    /// there are enough intervals in the window.
+    pub fn push(&mut self, timestamp: u128, interval: u128) -> Option<RhythmAssessment> {
+        self.intervals.push_back((timestamp, interval));
+        while let Some(&(oldest, _)) = self.intervals.front() {
+            if timestamp.saturating_sub(oldest) <= self.window {
+                break;
+            }
+            self.intervals.pop_front();
+        }
+
+        if self.intervals.len() < Self::MIN_INTERVALS {
+            return None;
+        }
+
+        let intervals: Vec<f32> = self
+            .intervals
+            .iter()
+            .map(|&(_, interval)| interval as f32)
+            .collect();
+        let normalised_rmssd = normalised_rmssd(&intervals);
+        let entropy = entropy(&intervals, Self::HISTOGRAM_BINS);
+        let turning_point_ratio = turning_point_ratio(&intervals);
+
+        // The mean and the standard deviation of the turning point ratio of a random series.
+        let count = intervals.len() as f32;
+        let random_ratio = 2.0 / 3.0;
+        let random_deviation = ((16.0 * count - 29.0) / 90.0).sqrt() / (count - 2.0);
+        let turning_point_distance = (turning_point_ratio - random_ratio).abs() / random_deviation;
+
+        let irregular = normalised_rmssd > Self::RMSSD_THRESHOLD
+            && entropy > Self::ENTROPY_THRESHOLD
+            && turning_point_distance < Self::TURNING_POINT_DEVIATIONS;
+
+        // Each metric scores from 0, at half its threshold, to 1, at one and a half its threshold.
+        let ramp = |value: f32, threshold: f32| ((value / threshold) - 0.5).clamp(0.0, 1.0);
+        let confidence = (ramp(normalised_rmssd, Self::RMSSD_THRESHOLD)
+            + ramp(entropy, Self::ENTROPY_THRESHOLD)
+            + ramp(
+                2.0 * Self::TURNING_POINT_DEVIATIONS - turning_point_distance,
+                Self::TURNING_POINT_DEVIATIONS,
+            ))
+            / 3.0;
+
+        Some(RhythmAssessment {
+            irregular,
+            confidence,
+            normalised_rmssd,
+            entropy,
+            turning_point_ratio,
+        })
+    }
+}
+
+/// Computes the RMSSD of the intervals divided by their mean.
+fn normalised_rmssd(intervals: &[f32]) -> f32 {
+    let mean = intervals.iter().sum::<f32>() / intervals.len() as f32;
+    let differences = intervals.len().saturating_sub(1).max(1) as f32;
+    let rmssd = (intervals
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).powi(2))
+        .sum::<f32>()
+        / differences)
+        .sqrt();
+
+    if mean > 0.0 {
+        rmssd / mean
+    } else {
+        0.0
+    }
+}
+
+/// Computes the Shannon entropy of the histogram of the intervals, divided by its maximum value.
+/// The shortest and the longest intervals, one bin worth each, are discarded as outliers.
+fn entropy(intervals: &[f32], bins: usize) -> f32 {
+    let mut sorted = intervals.to_vec();
+    sorted.sort_unstable_by(f32::total_cmp);
+    let outliers = sorted.len() / bins;
+    let sorted = &sorted[outliers..sorted.len() - outliers];
+
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    if max <= min {
+        return 0.0;
+    }
+
+    let mut histogram = vec![0; bins];
+    for interval in sorted {
+        let bin = ((interval - min) / (max - min) * bins as f32) as usize;
+        histogram[bin.min(bins - 1)] += 1;
+    }
+
+    let count = sorted.len() as f32;
+    let entropy: f32 = histogram
+        .iter()
+        .filter(|&&frequency| frequency > 0)
+        .map(|&frequency| {
+            let probability = frequency as f32 / count;
+            -probability * probability.ln()
+        })
+        .sum();
+
+    entropy / (bins as f32).ln()
+}
+
+/// Computes the number of turning points, the intervals longer or shorter than both their neighbours, divided by
+/// the number of intervals that have two neighbours.
+fn turning_point_ratio(intervals: &[f32]) -> f32 {
+    let turning_points = intervals
+        .windows(3)
+        .filter(|triplet| {
+            (triplet[1] > triplet[0] && triplet[1] > triplet[2])
+                || (triplet[1] < triplet[0] && triplet[1] < triplet[2])
+        })
+        .count();
+
+    turning_points as f32 / intervals.len().saturating_sub(2).max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::{PpgConfiguration, SyntheticPpg};
+
+    /// Gets the rhythm assessments of the RR intervals of a synthetic pulse over 90 s, with the given heart rate
+    /// variability.
+    fn assess(heart_rate_variability: f32) -> Vec<RhythmAssessment> {
+        let mut ppg = SyntheticPpg::new(PpgConfiguration {
+            heart_rate: 90.0,
+            heart_rate_variability: Time::new::<millisecond>(heart_rate_variability),
+            ..Default::default()
+        });
+        let mut detector = IrregularRhythmDetector::new(Time::new::<millisecond>(120_000.0));
+        let mut assessments = vec![];
+        let mut beat_count = ppg.beat_count();
+        while ppg.time().get::<millisecond>() < 90_000.0 {
+            ppg.next();
+            if ppg.beat_count() == beat_count {
+                continue;
+            }
+            beat_count = ppg.beat_count();
+            if let Some(interval) = ppg.last_rr_interval() {
+                let timestamp = ppg.time().get::<millisecond>().round() as u128;
+                assessments.extend(
+                    detector.push(timestamp, interval.get::<millisecond>().round() as u128),
+                );
+            }
+        }
+
+        assessments
+    }
+
+    #[test]
+    fn regular_synthetic_rhythm() {
+        let assessments = assess(20.0);
+        assert!(assessments.len() > 80);
+        // The synthetic intervals are random, but not variable enough for atrial fibrillation.
+        for assessment in assessments {
+            assert!(!assessment.irregular, "{:?}", assessment);
+            assert!(
+                assessment.normalised_rmssd < IrregularRhythmDetector::RMSSD_THRESHOLD,
+                "{:?}",
+                assessment
+            );
+        }
+    }
+
+    #[test]
+    fn irregular_synthetic_rhythm() {
+        // Uncorrelated intervals spread over ±150 ms, as in atrial fibrillation.
+        let assessments = assess(150.0);
+        assert!(assessments.len() > 80);
+        let irregular = assessments
+            .iter()
+            .filter(|assessment| assessment.irregular)
+            .count();
+        assert!(
+            irregular as f32 > 0.9 * assessments.len() as f32,
+            "{:?}",
+            assessments
+        );
+        let last = assessments.last().unwrap();
+        assert!(last.confidence > 0.5, "{:?}", last);
+    }
+}
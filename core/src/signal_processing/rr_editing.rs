@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+
+/// The class of an RR interval, assigned by the [`RrEditor`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BeatClass {
+    /// The interval between two normal beats.
+    #[default]
+    Normal,
+    /// A premature beat followed by a compensatory pause. Both intervals are replaced by their average.
+    Ectopic,
+    /// A beat that has not been detected. The interval is split into equal ones.
+    Missed,
+    /// A false beat in the middle of an interval. The two intervals are merged.
+    Extra,
+    /// An interval too short or too long for any other class. It is replaced by the reference interval.
+    Artifact,
+}
+
+/// An RR interval after the editing.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EditedInterval {
+    /// The time of the beat that ends the interval, in milliseconds.
+    pub timestamp: u128,
+    /// The interval, in milliseconds.
+    pub interval: u128,
+    /// The class of the original interval. Only the normal intervals have not been edited.
+    pub class: BeatClass,
+}
+
+/// Classifies the RR intervals from the differences with a reference interval, the median of the recent ones, and
+/// corrects the ones that are not normal. Since the classification of an interval depends on the next one, the
+/// intervals are edited with a delay of one beat.
+pub struct RrEditor {
+    recent_intervals: VecDeque<u128>,
+    pending: Option<(u128, u128)>,
+    edited: VecDeque<bool>,
+}
+
+impl RrEditor {
+    /// The maximum relative difference from the reference interval of a normal interval.
+    pub const THRESHOLD: f32 = 0.2;
+    /// The number of recent intervals whose median is the reference interval.
+    pub const REFERENCE_LENGTH: usize = 9;
+    /// The minimum number of recent intervals to edit the intervals. Before, all intervals are normal.
+    pub const MIN_REFERENCE_LENGTH: usize = 3;
+    /// The maximum number of consecutive missed beats that are corrected.
+    pub const MAX_MISSED_BEATS: u128 = 2;
+    /// The number of recent edited intervals used to compute the fraction of the edited ones.
+    pub const EDITED_FRACTION_LENGTH: usize = 60;
+
+    /// Creates a new `RrEditor`.
+    pub fn new() -> Self {
+        Self {
+            recent_intervals: VecDeque::with_capacity(Self::REFERENCE_LENGTH),
+            pending: None,
+            edited: VecDeque::with_capacity(Self::EDITED_FRACTION_LENGTH),
+        }
+    }
+
+    /// Discards all the intervals, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.recent_intervals.clear();
+        self.pending = None;
+        self.edited.clear();
+    }
+
+    /// Gets the fraction of the recent intervals that have been edited, from 0 to 1.
+    pub fn edited_fraction(&self) -> f32 {
+        if self.edited.is_empty() {
+            0.0
+        } else {
+            self.edited.iter().filter(|&&edited| edited).count() as f32 / self.edited.len() as f32
+        }
+    }
+
+    /// Pushes the interval, in milliseconds, ending at `timestamp`, and returns the edited intervals of the
+    /// previous one, if any.
+    pub fn push(&mut self, timestamp: u128, interval: u128) -> Vec<EditedInterval> {
+        let edited = match (self.pending, self.reference()) {
+            (Some(pending), Some(reference)) => {
+                self.edit(pending, (timestamp, interval), reference)
+            }
+            (Some((pending_timestamp, pending_interval)), None) => {
+                self.pending = Some((timestamp, interval));
+                vec![EditedInterval {
+                    timestamp: pending_timestamp,
+                    interval: pending_interval,
+                    class: BeatClass::Normal,
+                }]
+            }
+            (None, _) => {
+                self.pending = Some((timestamp, interval));
+                vec![]
+            }
+        };
+
+        if self.recent_intervals.len() == Self::REFERENCE_LENGTH {
+            self.recent_intervals.pop_front();
+        }
+        self.recent_intervals.push_back(interval);
+
+        for interval in &edited {
+            if self.edited.len() == Self::EDITED_FRACTION_LENGTH {
+                self.edited.pop_front();
+            }
+            self.edited.push_back(interval.class != BeatClass::Normal);
+        }
+
+        edited
+    }
+
+    /// Gets the median of the recent intervals, if there are enough.
+    fn reference(&self) -> Option<u128> {
+        if self.recent_intervals.len() < Self::MIN_REFERENCE_LENGTH {
+            return None;
+        }
+
+        let mut intervals: Vec<u128> = self.recent_intervals.iter().copied().collect();
+        intervals.sort_unstable();
+
+        Some(intervals[intervals.len() / 2])
+    }
+
+    /// Classifies and corrects the pending interval, knowing the next one.
+    fn edit(
+        &mut self,
+        (pending_timestamp, pending): (u128, u128),
+        (next_timestamp, next): (u128, u128),
+        reference: u128,
+    ) -> Vec<EditedInterval> {
+        let reference_f32 = reference as f32;
+        let tolerance = Self::THRESHOLD * reference_f32;
+        let close_to = |interval: u128, target: f32| (interval as f32 - target).abs() <= tolerance;
+        let edited = |timestamp: u128, interval: u128, class: BeatClass| EditedInterval {
+            timestamp,
+            interval,
+            class,
+        };
+
+        // The next interval is kept pending, unless it is consumed by the correction.
+        self.pending = Some((next_timestamp, next));
+
+        if (pending as f32) < reference_f32 - tolerance {
+            if close_to(pending + next, reference_f32) {
+                self.pending = None;
+                return vec![edited(next_timestamp, pending + next, BeatClass::Extra)];
+            }
+            if (next as f32) > reference_f32 + tolerance
+                && close_to(pending + next, 2.0 * reference_f32)
+            {
+                self.pending = None;
+                let average = (pending + next) / 2;
+                return vec![
+                    edited(
+                        next_timestamp.saturating_sub(average),
+                        average,
+                        BeatClass::Ectopic,
+                    ),
+                    edited(next_timestamp, average, BeatClass::Ectopic),
+                ];
+            }
+
+            return vec![edited(pending_timestamp, reference, BeatClass::Artifact)];
+        }
+
+        if (pending as f32) > reference_f32 + tolerance {
+            let beats = (pending as f32 / reference_f32).round() as u128;
+            if (2..=Self::MAX_MISSED_BEATS + 1).contains(&beats)
+                && close_to(pending / beats, reference_f32)
+            {
+                let split = pending / beats;
+                return (0..beats)
+                    .rev()
+                    .map(|i| {
+                        edited(
+                            pending_timestamp.saturating_sub(i * split),
+                            split,
+                            BeatClass::Missed,
+                        )
+                    })
+                    .collect();
+            }
+
+            return vec![edited(pending_timestamp, reference, BeatClass::Artifact)];
+        }
+
+        vec![edited(pending_timestamp, pending, BeatClass::Normal)]
+    }
+}
+
+impl Default for RrEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::time::millisecond;
+
+    use super::*;
+    use crate::synthetic::{PpgConfiguration, SyntheticPpg};
+
+    /// Gets the first `count` RR intervals of a synthetic pulse, in milliseconds.
+    fn synthetic_intervals(count: usize) -> Vec<u128> {
+        let mut ppg = SyntheticPpg::new(PpgConfiguration::default());
+        let mut intervals = vec![];
+        let mut beat_count = ppg.beat_count();
+        while intervals.len() < count {
+            ppg.next();
+            if ppg.beat_count() != beat_count {
+                beat_count = ppg.beat_count();
+                if let Some(interval) = ppg.last_rr_interval() {
+                    intervals.push(interval.get::<millisecond>().round() as u128);
+                }
+            }
+        }
+
+        intervals
+    }
+
+    /// Pushes the intervals into a new editor and returns the edited ones.
+    fn edit(intervals: &[u128]) -> Vec<EditedInterval> {
+        let mut editor = RrEditor::new();
+        let mut timestamp = 0;
+        intervals
+            .iter()
+            .flat_map(|&interval| {
+                timestamp += interval;
+                editor.push(timestamp, interval)
+            })
+            .collect()
+    }
+
+    fn count(edited: &[EditedInterval], class: BeatClass) -> usize {
+        edited
+            .iter()
+            .filter(|interval| interval.class == class)
+            .count()
+    }
+
+    #[test]
+    fn synthetic_intervals_are_normal() {
+        let intervals = synthetic_intervals(100);
+        let edited = edit(&intervals);
+
+        assert_eq!(edited.len(), intervals.len() - 1);
+        assert_eq!(count(&edited, BeatClass::Normal), edited.len());
+        assert!(edited
+            .iter()
+            .zip(&intervals)
+            .all(|(edited, &interval)| edited.interval == interval));
+    }
+
+    #[test]
+    fn ectopic_missed_and_extra_beats_are_corrected() {
+        let mut intervals = synthetic_intervals(100);
+        // A premature beat followed by a compensatory pause.
+        intervals[20] -= 300;
+        intervals[21] += 300;
+        // A beat that has not been detected.
+        let missed = intervals.remove(41);
+        intervals[40] += missed;
+        // A false beat in the middle of an interval.
+        let extra = intervals[60] - 300;
+        intervals[60] = 300;
+        intervals.insert(61, extra);
+
+        let edited = edit(&intervals);
+        assert_eq!(count(&edited, BeatClass::Ectopic), 2);
+        assert_eq!(count(&edited, BeatClass::Missed), 2);
+        assert_eq!(count(&edited, BeatClass::Extra), 1);
+        assert_eq!(count(&edited, BeatClass::Artifact), 0);
+        assert_eq!(edited.len(), 99);
+
+        // The corrections keep the timing of the beats, up to the rounding of the split intervals.
+        let original: u128 = intervals[..intervals.len() - 1].iter().sum();
+        let corrected: u128 = edited.iter().map(|interval| interval.interval).sum();
+        assert!(
+            original - corrected <= 2,
+            "{} ms instead of {} ms",
+            corrected,
+            original
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use uom::si::{f32::Time, time::second};
+
+use super::{dot_product::SineProduct, HeartRateEstimate};
+
+/// Estimates the heart rate from the spectrum of the AC signal over a sliding window, computed with a bank of
+/// [`SineProduct`]s. The dominant cardiac frequency is found with a harmonic sum, so that a strong second harmonic
+/// (e.g. caused by the dicrotic notch) is not mistaken for the fundamental, and the peak is tracked across windows,
+/// so that the heart rate is robust even when individual beats are unreadable.
+pub struct SpectralEstimator {
+    samples: VecDeque<f32>,
+    window_length: usize,
+    hop_length: usize,
+    samples_since_estimate: usize,
+    taper: Vec<f32>,
+    fundamentals: Vec<SineProduct>,
+    harmonics: Vec<SineProduct>,
+    main_lobe_bins: usize,
+    tracked_heart_rate: Option<f32>,
+}
+
+impl SpectralEstimator {
+    /// The minimum heart rate that can be estimated, in bpm.
+    pub const MIN_HEART_RATE: f32 = 40.0;
+    /// The maximum heart rate that can be estimated, in bpm.
+    pub const MAX_HEART_RATE: f32 = 200.0;
+    /// The distance between the frequencies of the bank, in bpm.
+    pub const RESOLUTION: f32 = 3.0;
+    /// The weight of the second harmonic in the harmonic sum.
+    pub const HARMONIC_WEIGHT: f32 = 0.5;
+    /// The spectral peaks at least this fraction of the highest one can be tracked.
+    pub const TRACKING_RATIO: f32 = 0.5;
+    /// The maximum change of the heart rate between two windows for the peak to be tracked, in bpm.
+    /// When no peak is close enough, the track is lost and the highest peak is chosen.
+    pub const MAX_TRACKING_JUMP: f32 = 20.0;
+
+    /// Creates a new `SpectralEstimator` that estimates the heart rate every `hop` over the last `window`
+    /// of samples, taken every `sample_period`.
+    pub fn new(window: Time, hop: Time, sample_period: Time) -> Self {
+        let samples_in = |time: Time| (time / sample_period).value.round() as usize;
+        let window_length = samples_in(window).max(2);
+        let sampling_period = sample_period.get::<second>();
+        let window_period = window_length as f32 * sampling_period;
+
+        // One more frequency at each end, so that the peaks at the edges of the range can be found.
+        let bins =
+            ((Self::MAX_HEART_RATE - Self::MIN_HEART_RATE) / Self::RESOLUTION).ceil() as usize + 3;
+        let frequency =
+            |bin: usize| (Self::MIN_HEART_RATE + (bin as f32 - 1.0) * Self::RESOLUTION) / 60.0;
+
+        Self {
+            samples: VecDeque::with_capacity(window_length),
+            window_length,
+            hop_length: samples_in(hop).max(1),
+            samples_since_estimate: 0,
+            // Hann window, to reduce the leakage of the baseline wander and of the harmonics.
+            taper: (0..window_length)
+                .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / (window_length - 1) as f32).cos())
+                .collect(),
+            fundamentals: (0..bins)
+                .map(|bin| SineProduct::new(frequency(bin), sampling_period, window_period))
+                .collect(),
+            harmonics: (0..bins)
+                .map(|bin| SineProduct::new(2.0 * frequency(bin), sampling_period, window_period))
+                .collect(),
+            // The main lobe of the Hann window is four frequency resolutions wide.
+            main_lobe_bins: (2.0 * 60.0 / window_period / Self::RESOLUTION).ceil() as usize,
+            tracked_heart_rate: None,
+        }
+    }
+
+    /// Pushes a new sample and returns the estimate when the window is full and a hop has elapsed.
+    pub fn push(&mut self, sample: f32) -> Option<HeartRateEstimate> {
+        if self.samples.len() == self.window_length {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.samples_since_estimate += 1;
+
+        if self.samples.len() == self.window_length
+            && self.samples_since_estimate >= self.hop_length
+        {
+            self.samples_since_estimate = 0;
+            self.estimate()
+        } else {
+            None
+        }
+    }
+
+    /// Discards the samples and the tracked peak, e.g. after a change of the frontend settings.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.samples_since_estimate = 0;
+        self.tracked_heart_rate = None;
+    }
+
+    /// Estimates the heart rate from the spectrum of the samples in the window.
+    fn estimate(&mut self) -> Option<HeartRateEstimate> {
+        let mean = self.samples.iter().sum::<f32>() / self.samples.len() as f32;
+        let window: Vec<f32> = self
+            .samples
+            .iter()
+            .zip(&self.taper)
+            .map(|(sample, taper)| (sample - mean) * taper)
+            .collect();
+
+        let mut magnitude = |product: &mut SineProduct| {
+            product.reset(product.sine_frequency);
+            for sample in &window {
+                product.process(*sample);
+            }
+            product.magnitude()
+        };
+        let fundamentals: Vec<f32> = self.fundamentals.iter_mut().map(&mut magnitude).collect();
+        let harmonics: Vec<f32> = self.harmonics.iter_mut().map(&mut magnitude).collect();
+
+        let scores: Vec<f32> = fundamentals
+            .iter()
+            .zip(&harmonics)
+            .map(|(fundamental, harmonic)| fundamental + Self::HARMONIC_WEIGHT * harmonic)
+            .collect();
+
+        // Find the local maxima of the harmonic sum.
+        let peaks: Vec<usize> = (1..scores.len() - 1)
+            .filter(|&bin| scores[bin] >= scores[bin - 1] && scores[bin] > scores[bin + 1])
+            .collect();
+        let highest = *peaks
+            .iter()
+            .max_by(|&&a, &&b| scores[a].total_cmp(&scores[b]))?;
+
+        // Follow the tracked peak among the strong ones, if it has not moved too far.
+        let heart_rate_of =
+            |bin: usize| Self::MIN_HEART_RATE + (bin as f32 - 1.0) * Self::RESOLUTION;
+        let tracked = self.tracked_heart_rate.and_then(|tracked_heart_rate| {
+            peaks
+                .iter()
+                .copied()
+                .filter(|&bin| scores[bin] >= Self::TRACKING_RATIO * scores[highest])
+                .filter(|&bin| {
+                    (heart_rate_of(bin) - tracked_heart_rate).abs() <= Self::MAX_TRACKING_JUMP
+                })
+                .min_by(|&a, &b| {
+                    let distance = |bin: usize| (heart_rate_of(bin) - tracked_heart_rate).abs();
+                    distance(a).total_cmp(&distance(b))
+                })
+        });
+        let bin = tracked.unwrap_or(highest);
+
+        // Refine the frequency with a parabolic interpolation around the peak.
+        let (previous, peak, next) = (scores[bin - 1], scores[bin], scores[bin + 1]);
+        let curvature = previous - 2.0 * peak + next;
+        let offset = if curvature < 0.0 {
+            (0.5 * (previous - next) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let heart_rate = heart_rate_of(bin) + offset * Self::RESOLUTION;
+
+        // The confidence is the fraction of the power of the cardiac band in the main lobe of the peak.
+        let power = |range: &[f32]| range.iter().map(|magnitude| magnitude.powi(2)).sum::<f32>();
+        let lobe = bin.saturating_sub(self.main_lobe_bins)
+            ..(bin + self.main_lobe_bins + 1).min(fundamentals.len());
+        let total_power = power(&fundamentals);
+        let confidence = if total_power > 0.0 {
+            power(&fundamentals[lobe]) / total_power
+        } else {
+            0.0
+        };
+
+        self.tracked_heart_rate = Some(heart_rate);
+
+        Some(HeartRateEstimate {
+            heart_rate,
+            confidence: confidence.clamp(0.0, 1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::tests::measure,
+        signal_processing::tests::{sampled, sinusoid, white_noise},
+        synthetic::PpgConfiguration,
+    };
+
+    /// An estimator over 8 s windows, every second.
+    fn estimator() -> SpectralEstimator {
+        SpectralEstimator::new(
+            Time::new::<second>(8.0),
+            Time::new::<second>(1.0),
+            Time::new::<second>(crate::SAMPLE_PERIOD),
+        )
+    }
+
+    fn estimates(estimator: &mut SpectralEstimator, samples: &[f32]) -> Vec<HeartRateEstimate> {
+        samples
+            .iter()
+            .filter_map(|&sample| estimator.push(sample))
+            .collect()
+    }
+
+    #[test]
+    fn sinusoids() {
+        for heart_rate in [45.0, 72.0, 120.0, 190.0] {
+            let estimates = estimates(
+                &mut estimator(),
+                &sampled(20.0, |time| sinusoid(heart_rate, time)),
+            );
+
+            assert_eq!(estimates.len(), 13);
+            for estimate in estimates {
+                assert!(
+                    (estimate.heart_rate - heart_rate).abs() < 1.0,
+                    "{:?} for {} bpm",
+                    estimate,
+                    heart_rate
+                );
+                assert!(estimate.confidence > 0.95, "{:?}", estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn second_harmonic_is_not_mistaken_for_the_fundamental() {
+        // The second harmonic is stronger than the fundamental, as with a pronounced dicrotic notch.
+        let pulse = |time: f32| sinusoid(60.0, time) + 1.5 * sinusoid(120.0, time + 0.1);
+        let estimates = estimates(&mut estimator(), &sampled(20.0, pulse));
+
+        assert!(!estimates.is_empty());
+        for estimate in estimates {
+            assert!((estimate.heart_rate - 60.0).abs() < 1.0, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn flat_input_has_no_estimate() {
+        assert!(estimates(&mut estimator(), &sampled(20.0, |_| 1.0)).is_empty());
+    }
+
+    #[test]
+    fn white_noise_has_a_low_confidence() {
+        let estimates = estimates(&mut estimator(), &white_noise(2000));
+
+        assert!(!estimates.is_empty());
+        for estimate in estimates {
+            assert!(estimate.confidence < 0.5, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn follows_a_frequency_step() {
+        // A small step is tracked and a large one loses the track, but both end on the new heart rate.
+        for heart_rate in [84.0, 150.0] {
+            let mut estimator = estimator();
+            estimates(&mut estimator, &sampled(12.0, |time| sinusoid(72.0, time)));
+            let estimates = estimates(
+                &mut estimator,
+                &sampled(20.0, |time| sinusoid(heart_rate, time)),
+            );
+
+            // The estimates move once the new heart rate dominates the window.
+            for estimate in &estimates[8..] {
+                assert!(
+                    (estimate.heart_rate - heart_rate).abs() < 1.0,
+                    "{:?} for {} bpm",
+                    estimate,
+                    heart_rate
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tracked_peak_is_kept_when_a_stronger_one_appears() {
+        let interference = |time: f32| sinusoid(72.0, time) + 1.3 * sinusoid(110.0, time);
+
+        // Without a track, the highest peak is chosen.
+        let estimates_without_track = estimates(&mut estimator(), &sampled(20.0, interference));
+        assert!(estimates_without_track
+            .iter()
+            .all(|estimate| (estimate.heart_rate - 110.0).abs() < 1.0));
+
+        // With a track on the weaker peak, it is followed.
+        let mut estimator = estimator();
+        estimates(&mut estimator, &sampled(12.0, |time| sinusoid(72.0, time)));
+        let tracked_estimates = estimates(&mut estimator, &sampled(20.0, interference));
+        assert!(!tracked_estimates.is_empty());
+        // The peak is followed while the window fills with the interference, when it is spread by its leakage.
+        assert!(tracked_estimates
+            .iter()
+            .all(|estimate| (estimate.heart_rate - 72.0).abs() < 10.0));
+        for estimate in &tracked_estimates[8..] {
+            assert!((estimate.heart_rate - 72.0).abs() < 1.0, "{:?}", estimate);
+        }
+
+        // The track is lost after a reset.
+        estimator.reset();
+        assert!(estimates(&mut estimator, &sampled(20.0, interference))
+            .iter()
+            .all(|estimate| (estimate.heart_rate - 110.0).abs() < 1.0));
+    }
+
+    #[test]
+    fn estimates_the_synthetic_heart_rate() {
+        for heart_rate in [50.0, 75.0, 120.0] {
+            let outputs = measure(
+                PpgConfiguration {
+                    heart_rate,
+                    ..Default::default()
+                },
+                45.0,
+                |_| {},
+            );
+            let mut estimator = SpectralEstimator::new(
+                Time::new::<second>(8.0),
+                Time::new::<second>(1.0),
+                Time::new::<second>(crate::SAMPLE_PERIOD),
+            );
+            let estimates: Vec<HeartRateEstimate> = outputs
+                .iter()
+                .filter_map(|output| estimator.push(output.filtered_data?[0].1))
+                .collect();
+
+            assert!(estimates.len() > 20);
+            let mean = estimates
+                .iter()
+                .map(|estimate| estimate.heart_rate)
+                .sum::<f32>()
+                / estimates.len() as f32;
+            assert!(
+                (mean - heart_rate).abs() < 1.5,
+                "{} for {} bpm",
+                mean,
+                heart_rate
+            );
+            // The estimates follow the variability of the synthetic heart rate.
+            for estimate in estimates {
+                assert!(
+                    (estimate.heart_rate - heart_rate).abs() < 5.0,
+                    "{:?} for {} bpm",
+                    estimate,
+                    heart_rate
+                );
+                assert!(estimate.confidence > 0.5, "{:?}", estimate);
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use uom::si::{f32::Time, time::millisecond};
+
+use super::{perfusion::PulseAmplitude, spo2_calibration::Spo2Calibration};
+
+/// A blood oxygen saturation estimated from the ratio of ratios of the recent beats.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Spo2Estimate {
+    /// The blood oxygen saturation, in percent, or `None` if it is invalid.
+    pub spo2: Option<f32>,
+    /// The mean ratio of ratios of the beats that are not outliers, or 0 if there are none.
+    pub r: f32,
+    /// How consistent the ratios of ratios of the recent beats are, from 0 to 1.
+    pub confidence: f32,
+}
+
+/// Estimates the blood oxygen saturation from the ratio of ratios of the beats in a sliding window: the
+/// peak-to-peak perfusion index of LED2 (red) over the one of LED3 (IR). The beats whose ratio is far from the
+/// median one are rejected as outliers, and the estimate is invalid when too few beats remain or they disagree.
+pub struct Spo2Estimator {
+    beats: VecDeque<(u128, Option<f32>)>,
+    window: u128,
+    calibration: Spo2Calibration,
+}
+
+impl Spo2Estimator {
+    /// The minimum peak-to-peak perfusion index of LED2 and LED3, in percent, for a beat to be used.
+    pub const MIN_PERFUSION_INDEX: f32 = 0.02;
+    /// The maximum relative difference from the median ratio of ratios of the beats that are not outliers.
+    pub const OUTLIER_THRESHOLD: f32 = 0.1;
+    /// The minimum number of beats that are not outliers for a valid estimate.
+    pub const MIN_BEATS: usize = 4;
+    /// The relative standard deviation of the ratios of ratios at which the confidence drops to 0.
+    pub const MAX_RELATIVE_DEVIATION: f32 = 0.1;
+    /// The minimum confidence of a valid estimate.
+    pub const MIN_CONFIDENCE: f32 = 0.5;
+    /// The lowest valid blood oxygen saturation, in percent. The calibration is extrapolated below.
+    pub const MIN_SPO2: f32 = 50.0;
+
+    /// Creates a new `Spo2Estimator` that estimates the blood oxygen saturation over the beats of the last
+    /// `window`.
+    pub fn new(window: Time) -> Self {
+        Self {
+            beats: VecDeque::new(),
+            window: window.get::<millisecond>().round() as u128,
+            calibration: Spo2Calibration::default(),
+        }
+    }
+
+    /// Gets the curve that converts the ratio of ratios into the blood oxygen saturation.
+    pub fn calibration(&self) -> Spo2Calibration {
+        self.calibration
+    }
+
+    /// Sets the curve that converts the ratio of ratios into the blood oxygen saturation.
+    pub fn set_calibration(&mut self, calibration: Spo2Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Discards all the beats, e.g. after the signal has been lost.
+    pub fn reset(&mut self) {
+        self.beats.clear();
+    }
+
+    /// Pushes the pulse amplitudes of LED1, LED2 and LED3 over a beat at `timestamp`, and returns the estimate.
+    pub fn push(&mut self, timestamp: u128, amplitudes: &[PulseAmplitude; 3]) -> Spo2Estimate {
+        let (red_pi, ir_pi) = (
+            amplitudes[1].perfusion_index(),
+            amplitudes[2].perfusion_index(),
+        );
+        // The beats with a weak pulse are kept, so that they lower the confidence.
+        let ratio = (red_pi >= Self::MIN_PERFUSION_INDEX && ir_pi >= Self::MIN_PERFUSION_INDEX)
+            .then(|| red_pi / ir_pi);
+        self.beats.push_back((timestamp, ratio));
+        self.expire(timestamp);
+
+        self.estimate()
+    }
+
+    /// Discards the beats older than the window at `timestamp`, and returns the new estimate if any beat has been
+    /// discarded.
+    pub fn update(&mut self, timestamp: u128) -> Option<Spo2Estimate> {
+        self.expire(timestamp).then(|| self.estimate())
+    }
+
+    /// Discards the beats older than the window, and returns whether any has been discarded.
+    fn expire(&mut self, timestamp: u128) -> bool {
+        let count = self.beats.len();
+        while let Some(&(oldest, _)) = self.beats.front() {
+            if timestamp.saturating_sub(oldest) <= self.window {
+                break;
+            }
+            self.beats.pop_front();
+        }
+
+        self.beats.len() < count
+    }
+
+    /// Estimates the blood oxygen saturation from the beats in the window.
+    fn estimate(&self) -> Spo2Estimate {
+        let mut ratios: Vec<f32> = self.beats.iter().filter_map(|&(_, ratio)| ratio).collect();
+        if ratios.is_empty() {
+            return Spo2Estimate::default();
+        }
+        ratios.sort_unstable_by(f32::total_cmp);
+        let median = ratios[ratios.len() / 2];
+
+        let inliers: Vec<f32> = ratios
+            .into_iter()
+            .filter(|ratio| (ratio - median).abs() <= Self::OUTLIER_THRESHOLD * median)
+            .collect();
+        let count = inliers.len() as f32;
+        let r = inliers.iter().sum::<f32>() / count;
+        let deviation =
+            (inliers.iter().map(|ratio| (ratio - r).powi(2)).sum::<f32>() / count).sqrt();
+
+        // Both the outliers and the spread of the remaining ratios lower the confidence.
+        let inlier_fraction = count / self.beats.len() as f32;
+        let consistency = (1.0 - deviation / r / Self::MAX_RELATIVE_DEVIATION).clamp(0.0, 1.0);
+        let confidence = inlier_fraction * consistency;
+
+        let spo2 = self.calibration.spo2(r);
+        let valid = inliers.len() >= Self::MIN_BEATS
+            && confidence >= Self::MIN_CONFIDENCE
+            && spo2 >= Self::MIN_SPO2;
+
+        Spo2Estimate {
+            spo2: valid.then(|| spo2.min(100.0)),
+            r,
+            confidence,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::tests::measure,
+        synthetic::{PpgConfiguration, SyntheticPpg},
+    };
+
+    #[test]
+    fn estimates_the_synthetic_spo2() {
+        for spo2 in [85.0, 92.0, 98.0] {
+            let configuration = PpgConfiguration {
+                spo2,
+                ..Default::default()
+            };
+            let outputs = measure(configuration, 40.0, |_| {});
+            let estimates: Vec<Spo2Estimate> = outputs
+                .iter()
+                .filter_map(|output| output.spo2)
+                .filter(|estimate| estimate.spo2.is_some())
+                .collect();
+
+            assert!(estimates.len() > 20);
+            let r = SyntheticPpg::new(configuration).r();
+            for estimate in estimates {
+                assert!((estimate.r - r).abs() < 0.03, "{:?} for R {}", estimate, r);
+                assert!(
+                    (estimate.spo2.unwrap() - spo2).abs() < 2.0,
+                    "{:?} for {}%",
+                    estimate,
+                    spo2
+                );
+                assert!(
+                    estimate.confidence > Spo2Estimator::MIN_CONFIDENCE,
+                    "{:?}",
+                    estimate
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use std::convert::TryFrom;
+
+/// The body site where the pulse.loop is worn, which changes the optical path and so the calibration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WearSite {
+    /// On the back of the wrist, where the light is reflected by the tissues around the radius and the ulna.
+    #[default]
+    Wrist,
+    /// On the finger, where the pulse is stronger.
+    Finger,
+}
+
+impl WearSite {
+    /// Serialises the wear site as a single byte, 0 for the wrist and 1 for the finger.
+    pub fn serialise(&self) -> u8 {
+        match self {
+            WearSite::Wrist => 0,
+            WearSite::Finger => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for WearSite {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WearSite::Wrist),
+            1 => Ok(WearSite::Finger),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The calibration curve that converts the ratio of ratios R into the blood oxygen saturation, in percent:
+/// `SpO2 = quadratic * R² + linear * R + constant`. A linear curve has no quadratic coefficient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spo2Calibration {
+    /// The coefficient of R², in percent.
+    pub quadratic: f32,
+    /// The coefficient of R, in percent.
+    pub linear: f32,
+    /// The SpO2 at R = 0, in percent.
+    pub constant: f32,
+}
+
+impl Spo2Calibration {
+    /// The hardware revision of the current board.
+    pub const HARDWARE_REVISION: u8 = 1;
+    /// The calibration curves measured for each hardware revision and wear site.
+    pub const PRESETS: [(u8, WearSite, Spo2Calibration); 2] = [
+        (
+            1,
+            WearSite::Wrist,
+            Spo2Calibration::linear(-75.2050, 160.8698),
+        ),
+        (
+            1,
+            WearSite::Finger,
+            Spo2Calibration::linear(-53.5799, 123.9541),
+        ),
+    ];
+
+    /// Creates a linear calibration curve.
+    pub const fn linear(slope: f32, offset: f32) -> Self {
+        Self::quadratic(0.0, slope, offset)
+    }
+
+    /// Creates a quadratic calibration curve.
+    pub const fn quadratic(quadratic: f32, linear: f32, constant: f32) -> Self {
+        Self {
+            quadratic,
+            linear,
+            constant,
+        }
+    }
+
+    /// Gets the calibration curve of the given hardware revision and wear site, if it has been measured.
+    pub fn preset(hardware_revision: u8, wear_site: WearSite) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(revision, site, _)| *revision == hardware_revision && *site == wear_site)
+            .map(|&(_, _, calibration)| calibration)
+    }
+
+    /// Converts the ratio of ratios into the blood oxygen saturation, in percent.
+    pub fn spo2(&self, r: f32) -> f32 {
+        (self.quadratic * r + self.linear) * r + self.constant
+    }
+
+    /// Serialises the quadratic, linear and constant coefficients, in this order, as little-endian `f32`.
+    pub fn serialise(&self) -> [u8; 12] {
+        let mut data = [0; 12];
+
+        data[0..4].copy_from_slice(&self.quadratic.to_le_bytes());
+        data[4..8].copy_from_slice(&self.linear.to_le_bytes());
+        data[8..12].copy_from_slice(&self.constant.to_le_bytes());
+
+        data
+    }
+
+    /// Reads a calibration curve serialised with [`Spo2Calibration::serialise`]. Returns `None` if the data is
+    /// too short or a coefficient is not finite.
+    pub fn deserialise(data: &[u8]) -> Option<Self> {
+        if data.len() < 12 {
+            return None;
+        }
+
+        let coefficient = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[i..i + 4]);
+            Some(f32::from_le_bytes(bytes)).filter(|value| value.is_finite())
+        };
+
+        Some(Self::quadratic(
+            coefficient(0)?,
+            coefficient(4)?,
+            coefficient(8)?,
+        ))
+    }
+
+    /// Fits a linear calibration curve to the pairs of R and reference SpO2 with the least squares method.
+    /// Returns `None` if there are less than 2 distinct values of R.
+    pub fn fit_linear(pairs: &[(f32, f32)]) -> Option<Self> {
+        let [linear, constant] = least_squares::<2>(pairs)?;
+
+        Some(Self::linear(linear, constant))
+    }
+
+    /// Fits a quadratic calibration curve to the pairs of R and reference SpO2 with the least squares method.
+    /// Returns `None` if there are less than 3 distinct values of R.
+    pub fn fit_quadratic(pairs: &[(f32, f32)]) -> Option<Self> {
+        let [quadratic, linear, constant] = least_squares::<3>(pairs)?;
+
+        Some(Self::quadratic(quadratic, linear, constant))
+    }
+
+    /// Computes the root mean square difference between the curve and the reference SpO2 of the pairs.
+    pub fn rms_error(&self, pairs: &[(f32, f32)]) -> f32 {
+        if pairs.is_empty() {
+            return 0.0;
+        }
+
+        (pairs
+            .iter()
+            .map(|&(r, spo2)| (self.spo2(r) - spo2).powi(2))
+            .sum::<f32>()
+            / pairs.len() as f32)
+            .sqrt()
+    }
+}
+
+impl Default for Spo2Calibration {
+    fn default() -> Self {
+        Self::preset(Self::HARDWARE_REVISION, WearSite::default()).unwrap_or(Self::PRESETS[0].2)
+    }
+}
+
+/// Fits the polynomial of degree `N - 1` that minimises the squared differences with the pairs (x, y), solving
+/// the normal equations with the Gaussian elimination. The coefficients are returned from the highest degree.
+fn least_squares<const N: usize>(pairs: &[(f32, f32)]) -> Option<[f32; N]> {
+    if pairs.len() < N {
+        return None;
+    }
+
+    // The augmented matrix of the normal equations, in double precision since the powers of x are summed.
+    let mut matrix = [[0.0f64; N]; N];
+    let mut vector = [0.0f64; N];
+    for &(x, y) in pairs {
+        let (x, y) = (x as f64, y as f64);
+        for (row, (matrix_row, value)) in matrix.iter_mut().zip(&mut vector).enumerate() {
+            for (column, element) in matrix_row.iter_mut().enumerate() {
+                *element += x.powi((2 * N - 2 - row - column) as i32);
+            }
+            *value += y * x.powi((N - 1 - row) as i32);
+        }
+    }
+
+    for pivot in 0..N {
+        let best = (pivot..N)
+            .max_by(|&a, &b| matrix[a][pivot].abs().total_cmp(&matrix[b][pivot].abs()))?;
+        if matrix[best][pivot].abs() < 1e-9 {
+            return None;
+        }
+        matrix.swap(pivot, best);
+        vector.swap(pivot, best);
+
+        for row in pivot + 1..N {
+            let factor = matrix[row][pivot] / matrix[pivot][pivot];
+            for column in pivot..N {
+                matrix[row][column] -= factor * matrix[pivot][column];
+            }
+            vector[row] -= factor * vector[pivot];
+        }
+    }
+
+    let mut solution = [0.0f64; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N)
+            .map(|column| matrix[row][column] * solution[column])
+            .sum();
+        solution[row] = (vector[row] - sum) / matrix[row][row];
+    }
+
+    let mut coefficients = [0.0f32; N];
+    for (coefficient, value) in coefficients.iter_mut().zip(solution) {
+        *coefficient = value as f32;
+    }
+
+    Some(coefficients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples the curve at the ratios of ratios from 0.4 to 1.4.
+    fn pairs(curve: Spo2Calibration) -> Vec<(f32, f32)> {
+        (0..=10)
+            .map(|i| 0.4 + 0.1 * i as f32)
+            .map(|r| (r, curve.spo2(r)))
+            .collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-3 * expected.abs().max(1.0),
+                "{:?} for {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn least_squares_recovers_exact_polynomials() {
+        let line: Vec<(f32, f32)> = (0..6).map(|x| (x as f32, 2.0 * x as f32 + 3.0)).collect();
+        assert_close(&least_squares::<2>(&line).unwrap(), &[2.0, 3.0]);
+
+        let parabola: Vec<(f32, f32)> = (-3..4)
+            .map(|x| x as f32)
+            .map(|x| (x, -1.5 * x * x + 4.0 * x + 100.0))
+            .collect();
+        assert_close(&least_squares::<3>(&parabola).unwrap(), &[-1.5, 4.0, 100.0]);
+        // A line is a parabola without a quadratic coefficient.
+        assert_close(&least_squares::<3>(&line).unwrap(), &[0.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn fits_recover_the_curves() {
+        for &(_, _, preset) in Spo2Calibration::PRESETS.iter() {
+            let fit = Spo2Calibration::fit_linear(&pairs(preset)).unwrap();
+            assert_close(
+                &[fit.quadratic, fit.linear, fit.constant],
+                &[0.0, preset.linear, preset.constant],
+            );
+            assert!(fit.rms_error(&pairs(preset)) < 1e-3);
+        }
+
+        let curve = Spo2Calibration::quadratic(-16.666, 8.1575, 100.6);
+        let fit = Spo2Calibration::fit_quadratic(&pairs(curve)).unwrap();
+        assert_close(
+            &[fit.quadratic, fit.linear, fit.constant],
+            &[curve.quadratic, curve.linear, curve.constant],
+        );
+        assert!(fit.rms_error(&pairs(curve)) < 1e-3);
+        // The best line through a parabola leaves an error.
+        let line = Spo2Calibration::fit_linear(&pairs(curve)).unwrap();
+        assert!(line.rms_error(&pairs(curve)) > 0.1);
+    }
+
+    #[test]
+    fn degenerate_pairs_are_rejected() {
+        // All the pairs at the same ratio of ratios.
+        let same_r = [(0.7, 95.0), (0.7, 96.0), (0.7, 97.0), (0.7, 98.0)];
+        assert_eq!(Spo2Calibration::fit_linear(&same_r), None);
+        assert_eq!(Spo2Calibration::fit_quadratic(&same_r), None);
+        // Only two distinct ratios for three coefficients.
+        let two_r = [(0.5, 99.0), (0.5, 98.0), (1.0, 85.0), (1.0, 86.0)];
+        assert!(Spo2Calibration::fit_linear(&two_r).is_some());
+        assert_eq!(Spo2Calibration::fit_quadratic(&two_r), None);
+
+        // Fewer pairs than coefficients.
+        assert_eq!(Spo2Calibration::fit_linear(&[]), None);
+        assert_eq!(Spo2Calibration::fit_linear(&[(0.5, 99.0)]), None);
+        assert_eq!(
+            Spo2Calibration::fit_quadratic(&[(0.5, 99.0), (1.0, 85.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn serialisation_round_trip() {
+        let curves = Spo2Calibration::PRESETS
+            .iter()
+            .map(|&(_, _, curve)| curve)
+            .chain([Spo2Calibration::quadratic(-16.666, 8.1575, 100.6)]);
+        for curve in curves {
+            assert_eq!(
+                Spo2Calibration::deserialise(&curve.serialise()),
+                Some(curve)
+            );
+        }
+
+        for site in [WearSite::Wrist, WearSite::Finger] {
+            assert_eq!(WearSite::try_from(site.serialise()), Ok(site));
+        }
+        assert_eq!(WearSite::try_from(2), Err(()));
+    }
+
+    #[test]
+    fn invalid_payloads_are_rejected() {
+        let data = Spo2Calibration::quadratic(-16.666, 8.1575, 100.6).serialise();
+        for length in 0..12 {
+            assert_eq!(Spo2Calibration::deserialise(&data[..length]), None);
+        }
+
+        for coefficient in 0..3 {
+            for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+                let mut data = data;
+                data[4 * coefficient..4 * coefficient + 4].copy_from_slice(&value.to_le_bytes());
+                assert_eq!(
+                    Spo2Calibration::deserialise(&data),
+                    None,
+                    "{} as coefficient {}",
+                    value,
+                    coefficient
+                );
+            }
+        }
+    }
+}
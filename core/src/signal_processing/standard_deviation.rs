@@ -1,12 +1,12 @@
-pub(crate) struct MovingStandardDeviation {
-    pub(crate) values: Vec<f32>,
-    pub(crate) next_element: usize,
-    pub(crate) size: usize,
-    pub(crate) quadratic_sum: f32,
+pub struct MovingStandardDeviation {
+    pub values: Vec<f32>,
+    pub next_element: usize,
+    pub size: usize,
+    pub quadratic_sum: f32,
 }
 
 impl MovingStandardDeviation {
-    pub(crate) fn new(size: usize) -> Self {
+    pub fn new(size: usize) -> Self {
         Self {
             values: Vec::with_capacity(size),
             next_element: 0,
@@ -16,7 +16,7 @@ impl MovingStandardDeviation {
     }
 
     // Returns the standard deviation of the values in the buffer.
-    pub(crate) fn push(&mut self, value: f32) -> f32 {
+    pub fn push(&mut self, value: f32) -> f32 {
         if self.values.len() < self.size {
             self.values.push(value.powi(2));
         } else {
@@ -0,0 +1,447 @@
+// A configurable synthetic PPG source, used to test the signal processing and to drive the simulated frontend.
+// The optical model produces the photodiode currents of every LED from the LED currents, the conversion model turns
+// them into the voltages read by the AFE4404, including offset cancellation, noise and ADC clipping.
+
+use std::f32::consts::PI;
+
+use uom::si::{
+    electric_current::{microampere, milliampere},
+    electric_potential::volt,
+    electrical_resistance::ohm,
+    f32::{ElectricCurrent, ElectricPotential, ElectricalResistance, Frequency, Time},
+    frequency::hertz,
+    time::{millisecond, second},
+};
+
+use crate::protocol::RawData;
+
+/// The physiological, environmental and optical parameters of the synthetic signal.
+#[derive(Debug, Clone, Copy)]
+pub struct PpgConfiguration {
+    /// The mean heart rate in beats per minute.
+    pub heart_rate: f32,
+    /// The standard deviation of the RR intervals, excluding the respiratory sinus arrhythmia.
+    pub heart_rate_variability: Time,
+    /// The target oxygen saturation in percent, converted into the red/IR ratio with the wrist calibration curve.
+    pub spo2: f32,
+    /// The IR peak-to-peak pulsatile to static current ratio, in percent.
+    pub perfusion_index: f32,
+    /// The green to IR perfusion index ratio.
+    pub green_perfusion_ratio: f32,
+
+    /// The respiration rate in breaths per minute.
+    pub respiration_rate: f32,
+    /// The relative modulation of the static current due to respiration (RIIV).
+    pub respiration_baseline_modulation: f32,
+    /// The relative modulation of the pulsatile amplitude due to respiration (RIAV).
+    pub respiration_amplitude_modulation: f32,
+    /// The relative modulation of the RR intervals due to respiration (RIFV, respiratory sinus arrhythmia).
+    pub respiration_frequency_modulation: f32,
+
+    /// The relative amplitude of the baseline wander.
+    pub baseline_wander_amplitude: f32,
+    /// The frequency of the baseline wander.
+    pub baseline_wander_frequency: Frequency,
+
+    /// The photodiode current due to the steady ambient light.
+    pub ambient_current: ElectricCurrent,
+    /// The peak photodiode current due to the ambient light flicker.
+    pub ambient_flicker_current: ElectricCurrent,
+    /// The frequency of the ambient light flicker, twice the mains frequency for most lamps.
+    pub ambient_flicker_frequency: Frequency,
+
+    /// The time between the start of two motion bursts, no motion is generated if zero.
+    pub motion_interval: Time,
+    /// The duration of a motion burst.
+    pub motion_duration: Time,
+    /// The relative amplitude of the static current modulation during a motion burst.
+    pub motion_amplitude: f32,
+
+    /// The skin reflectance parameters (alpha = i_led / i_photodiode) of LED1, LED2 and LED3.
+    pub alpha: [f32; 3],
+    /// The standard deviation of the noise added to every ADC conversion.
+    pub noise: ElectricPotential,
+    /// The seed of the pseudo-random generator, so that sessions can be reproduced.
+    pub seed: u32,
+}
+
+impl Default for PpgConfiguration {
+    fn default() -> Self {
+        Self {
+            heart_rate: 72.0,
+            heart_rate_variability: Time::new::<millisecond>(20.0),
+            spo2: 97.0,
+            perfusion_index: 1.2,
+            green_perfusion_ratio: 3.0,
+            respiration_rate: 15.0,
+            respiration_baseline_modulation: 0.002,
+            respiration_amplitude_modulation: 0.1,
+            respiration_frequency_modulation: 0.03,
+            baseline_wander_amplitude: 0.002,
+            baseline_wander_frequency: Frequency::new::<hertz>(0.05),
+            ambient_current: ElectricCurrent::new::<microampere>(0.05),
+            ambient_flicker_current: ElectricCurrent::new::<microampere>(0.0),
+            ambient_flicker_frequency: Frequency::new::<hertz>(100.0),
+            motion_interval: Time::new::<second>(0.0),
+            motion_duration: Time::new::<second>(3.0),
+            motion_amplitude: 0.05,
+            alpha: [12000.0, 400.0, 350.0],
+            noise: ElectricPotential::new::<volt>(0.0005),
+            seed: 0x1234_5678,
+        }
+    }
+}
+
+/// The frontend settings used to convert the photodiode currents into readings.
+#[derive(Debug, Clone, Copy)]
+pub struct FrontendSettings {
+    /// The currents of LED1, LED2 and LED3.
+    pub led_currents: [ElectricCurrent; 3],
+    /// The offset currents of LED1, LED2 and LED3.
+    pub offset_currents: [ElectricCurrent; 3],
+    /// The TIA resistor used for the ambient light and LED1.
+    pub resistor1: ElectricalResistance,
+    /// The TIA resistor used for LED2 and LED3.
+    pub resistor2: ElectricalResistance,
+    /// The time between two readings.
+    pub sample_period: Time,
+    /// The number of ADC conversions averaged for every reading.
+    pub averages: u8,
+    /// False if the photodiode is disconnected, so that only the offset currents are read.
+    pub photodiode_connected: bool,
+}
+
+impl Default for FrontendSettings {
+    fn default() -> Self {
+        // The settings applied by `frontend::configure`, with the LED currents giving about 0.75 V.
+        Self {
+            led_currents: [
+                ElectricCurrent::new::<milliampere>(9.6),
+                ElectricCurrent::new::<milliampere>(12.8),
+                ElectricCurrent::new::<milliampere>(12.8),
+            ],
+            offset_currents: [ElectricCurrent::new::<microampere>(0.0); 3],
+            resistor1: ElectricalResistance::new::<ohm>(crate::RESISTOR1),
+            resistor2: ElectricalResistance::new::<ohm>(crate::RESISTOR2),
+            sample_period: Time::new::<millisecond>(30.0),
+            averages: 8,
+            photodiode_connected: true,
+        }
+    }
+}
+
+/// The photodiode currents in a single measurement window.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhotodiodeCurrents {
+    pub ambient: ElectricCurrent,
+    /// The currents when LED1, LED2 and LED3 are on, including the ambient light.
+    pub leds: [ElectricCurrent; 3],
+}
+
+/// The synthetic PPG generator.
+pub struct SyntheticPpg {
+    configuration: PpgConfiguration,
+    settings: FrontendSettings,
+
+    time: Time,
+    beat_phase: f32,
+    rr_interval: Time,
+    last_rr_interval: Option<Time>,
+    beat_count: usize,
+    next_motion_burst: Time,
+    motion_burst_end: Time,
+    random_state: u32,
+
+    // The minimum and the peak-to-peak amplitude of the blood volume waveform, used to normalise it.
+    waveform_minimum: f32,
+    waveform_amplitude: f32,
+}
+
+impl SyntheticPpg {
+    /// The full scale of the AFE4404 ADC.
+    const ADC_FULL_SCALE: f32 = 1.2;
+
+    /// Creates a new `SyntheticPpg` that converts the samples with the default frontend settings.
+    pub fn new(configuration: PpgConfiguration) -> Self {
+        let mut waveform_minimum = f32::MAX;
+        let mut waveform_maximum = f32::MIN;
+        for i in 0..1000 {
+            let value = Self::waveform(i as f32 / 1000.0);
+            waveform_minimum = waveform_minimum.min(value);
+            waveform_maximum = waveform_maximum.max(value);
+        }
+
+        let mut ppg = Self {
+            configuration,
+            settings: FrontendSettings::default(),
+            time: Time::new::<second>(0.0),
+            beat_phase: 0.0,
+            rr_interval: Time::new::<second>(60.0 / configuration.heart_rate),
+            last_rr_interval: None,
+            beat_count: 0,
+            next_motion_burst: configuration.motion_interval,
+            motion_burst_end: Time::new::<second>(0.0),
+            random_state: configuration.seed.max(1),
+            waveform_minimum,
+            waveform_amplitude: waveform_maximum - waveform_minimum,
+        };
+        ppg.rr_interval = ppg.next_rr_interval();
+
+        ppg
+    }
+
+    /// Gets an immutable reference of the configuration.
+    pub fn configuration(&self) -> &PpgConfiguration {
+        &self.configuration
+    }
+
+    /// Gets a mutable reference of the configuration.
+    pub fn configuration_mut(&mut self) -> &mut PpgConfiguration {
+        &mut self.configuration
+    }
+
+    /// Gets an immutable reference of the frontend settings used by the iterator.
+    pub fn settings(&self) -> &FrontendSettings {
+        &self.settings
+    }
+
+    /// Gets a mutable reference of the frontend settings used by the iterator.
+    pub fn settings_mut(&mut self) -> &mut FrontendSettings {
+        &mut self.settings
+    }
+
+    /// Gets the time of the next sample.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Gets the number of completed heart beats.
+    pub fn beat_count(&self) -> usize {
+        self.beat_count
+    }
+
+    /// Gets the last completed RR interval.
+    pub fn last_rr_interval(&self) -> Option<Time> {
+        self.last_rr_interval
+    }
+
+    /// Checks if a motion burst is in progress.
+    pub fn is_moving(&self) -> bool {
+        self.time < self.motion_burst_end
+    }
+
+    /// Gets the red to IR perfusion ratio that corresponds to the configured SpO2.
+    pub fn r(&self) -> f32 {
+        // Inverse of the wrist calibration curve used by the pipeline.
+        (160.8698 - self.configuration.spo2) / 75.2050
+    }
+
+    /// Produces the photodiode currents at the current time and advances the time by the given step.
+    pub fn photodiode_currents(
+        &mut self,
+        led_currents: [ElectricCurrent; 3],
+        time_step: Time,
+    ) -> PhotodiodeCurrents {
+        let configuration = self.configuration;
+        let t = self.time.get::<second>();
+
+        // Respiration.
+        let respiration = (2.0 * PI * configuration.respiration_rate / 60.0 * t).sin();
+
+        // Motion bursts.
+        if configuration.motion_interval.value > 0.0 && self.time >= self.next_motion_burst {
+            self.motion_burst_end = self.time + configuration.motion_duration;
+            self.next_motion_burst += configuration.motion_interval;
+        }
+        let motion = if self.is_moving() {
+            configuration.motion_amplitude
+                * (0.6 * (2.0 * PI * 1.7 * t).sin() + 0.4 * (2.0 * PI * 3.1 * t + 1.0).sin())
+        } else {
+            0.0
+        };
+
+        // The static current modulation.
+        let baseline = 1.0
+            + configuration.respiration_baseline_modulation * respiration
+            + configuration.baseline_wander_amplitude
+                * (2.0 * PI * configuration.baseline_wander_frequency.get::<hertz>() * t).sin()
+            + motion;
+
+        // The pulsatile component, between -0.5 and 0.5.
+        let pulse = ((Self::waveform(self.beat_phase) - self.waveform_minimum)
+            / self.waveform_amplitude
+            - 0.5)
+            * (1.0 + configuration.respiration_amplitude_modulation * respiration);
+
+        let ir_perfusion = configuration.perfusion_index / 100.0;
+        let perfusion = [
+            ir_perfusion * configuration.green_perfusion_ratio,
+            ir_perfusion * self.r(),
+            ir_perfusion,
+        ];
+
+        let ambient = configuration.ambient_current
+            + configuration.ambient_flicker_current
+                * (0.5
+                    + 0.5
+                        * (2.0 * PI * configuration.ambient_flicker_frequency.get::<hertz>() * t)
+                            .sin());
+
+        let mut currents = PhotodiodeCurrents {
+            ambient,
+            leds: [ambient; 3],
+        };
+        for (i, current) in currents.leds.iter_mut().enumerate() {
+            // The blood volume increase during systole reduces the reflected light.
+            *current +=
+                led_currents[i] / configuration.alpha[i] * baseline * (1.0 - perfusion[i] * pulse);
+        }
+
+        // Advance the time and the cardiac cycle.
+        self.time += time_step;
+        self.beat_phase += (time_step / self.rr_interval).value;
+        while self.beat_phase >= 1.0 {
+            self.beat_phase -= 1.0;
+            self.beat_count += 1;
+            self.last_rr_interval = Some(self.rr_interval);
+            self.rr_interval = self.next_rr_interval();
+        }
+
+        currents
+    }
+
+    /// Produces the readings at the current time with the given frontend settings and advances the time by their
+    /// sample period.
+    pub fn sample(&mut self, settings: &FrontendSettings) -> RawData {
+        let mut currents = self.photodiode_currents(settings.led_currents, settings.sample_period);
+        if !settings.photodiode_connected {
+            currents = PhotodiodeCurrents::default();
+        }
+
+        let no_offset = ElectricCurrent::new::<microampere>(0.0);
+        RawData {
+            ambient: self.convert(currents.ambient, no_offset, settings.resistor1, settings),
+            led1: self.convert(
+                currents.leds[0],
+                settings.offset_currents[0],
+                settings.resistor1,
+                settings,
+            ),
+            led2: self.convert(
+                currents.leds[1],
+                settings.offset_currents[1],
+                settings.resistor2,
+                settings,
+            ),
+            led3: self.convert(
+                currents.leds[2],
+                settings.offset_currents[2],
+                settings.resistor2,
+                settings,
+            ),
+        }
+    }
+
+    /// The blood volume during a cardiac cycle: a systolic peak followed by a smaller dicrotic wave.
+    fn waveform(phase: f32) -> f32 {
+        let systolic = (phase - 0.2) / 0.08;
+        let dicrotic = (phase - 0.45) / 0.1;
+        (-0.5 * systolic * systolic).exp() + 0.4 * (-0.5 * dicrotic * dicrotic).exp()
+    }
+
+    /// Draws the next RR interval, modulated by the respiration.
+    fn next_rr_interval(&mut self) -> Time {
+        let configuration = self.configuration;
+        let respiration =
+            (2.0 * PI * configuration.respiration_rate / 60.0 * self.time.get::<second>()).sin();
+        let mean = 60.0 / configuration.heart_rate
+            * (1.0 + configuration.respiration_frequency_modulation * respiration);
+        let rr = mean + configuration.heart_rate_variability.get::<second>() * self.gaussian();
+
+        Time::new::<second>(rr.clamp(0.25, 2.5))
+    }
+
+    /// Converts the photodiode and offset currents into the TIA output voltage, saturated at the ADC full scale.
+    fn convert(
+        &mut self,
+        photodiode_current: ElectricCurrent,
+        offset_current: ElectricCurrent,
+        resistor: ElectricalResistance,
+        settings: &FrontendSettings,
+    ) -> ElectricPotential {
+        let noise =
+            self.configuration.noise * self.gaussian() / (settings.averages.max(1) as f32).sqrt();
+        let voltage = 2.0 * resistor * (photodiode_current + offset_current) + noise;
+
+        ElectricPotential::new::<volt>(
+            voltage
+                .get::<volt>()
+                .clamp(-Self::ADC_FULL_SCALE, Self::ADC_FULL_SCALE),
+        )
+    }
+
+    /// Uniform noise in (0, 1].
+    fn uniform(&mut self) -> f32 {
+        // Xorshift, deterministic so that synthetic sessions can be reproduced.
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 17;
+        self.random_state ^= self.random_state << 5;
+
+        (self.random_state as f32 / u32::MAX as f32).max(f32::MIN_POSITIVE)
+    }
+
+    /// Standard normal noise (Box-Muller transform).
+    fn gaussian(&mut self) -> f32 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+impl Default for SyntheticPpg {
+    fn default() -> Self {
+        Self::new(PpgConfiguration::default())
+    }
+}
+
+/// Produces the readings with the frontend settings of the generator, at their sample rate.
+impl Iterator for SyntheticPpg {
+    type Item = RawData;
+
+    fn next(&mut self) -> Option<RawData> {
+        let settings = self.settings;
+        Some(self.sample(&settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beats_at_the_configured_heart_rate() {
+        let mut ppg = SyntheticPpg::new(PpgConfiguration {
+            heart_rate: 90.0,
+            ..Default::default()
+        });
+        while ppg.time().get::<second>() < 60.0 {
+            ppg.next();
+        }
+
+        assert!(
+            (ppg.beat_count() as i32 - 90).abs() <= 2,
+            "{} beats",
+            ppg.beat_count()
+        );
+    }
+
+    #[test]
+    fn readings_are_clipped_at_the_full_scale() {
+        let mut ppg = SyntheticPpg::default();
+        ppg.settings_mut().led_currents = [ElectricCurrent::new::<milliampere>(50.0); 3];
+
+        let raw_data = ppg.next().unwrap();
+        for reading in [raw_data.led1, raw_data.led2, raw_data.led3] {
+            assert_eq!(reading.get::<volt>(), SyntheticPpg::ADC_FULL_SCALE);
+        }
+    }
+}
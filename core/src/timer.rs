@@ -0,0 +1,80 @@
+use crate::clock::{Clock, MonotonicClock};
+
+/// A timer that can be used to measure time passed from its creation or last reset.
+pub struct Timer<C: Clock = MonotonicClock> {
+    duration: u128,
+    start: u128,
+    clock: C,
+}
+
+impl Timer {
+    /// Starts a new timer with the given duration in milliseconds, measured with the system monotonic clock.
+    pub fn new(milliseconds: u128) -> Self {
+        Self::with_clock(milliseconds, MonotonicClock::new())
+    }
+}
+
+impl<C: Clock> Timer<C> {
+    /// Starts a new timer with the given duration in milliseconds, measured with the given clock.
+    pub fn with_clock(milliseconds: u128, clock: C) -> Self {
+        Timer {
+            duration: milliseconds,
+            start: clock.now(),
+            clock,
+        }
+    }
+
+    /// Resets the timer.
+    pub fn reset(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    /// Checks if the timer has expired.
+    pub fn is_expired(&self) -> bool {
+        self.clock.now().saturating_sub(self.start) >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn expires_after_its_duration() {
+        let clock = ManualClock::new();
+        clock.set(1000);
+        let timer = Timer::with_clock(500, clock.clone());
+
+        assert!(!timer.is_expired());
+        clock.advance(499);
+        assert!(!timer.is_expired());
+        clock.advance(1);
+        assert!(timer.is_expired());
+        clock.advance(10_000);
+        assert!(timer.is_expired());
+    }
+
+    #[test]
+    fn restarts_when_reset() {
+        let clock = ManualClock::new();
+        let mut timer = Timer::with_clock(500, clock.clone());
+        clock.advance(600);
+        assert!(timer.is_expired());
+
+        timer.reset();
+        assert!(!timer.is_expired());
+        clock.advance(500);
+        assert!(timer.is_expired());
+    }
+
+    #[test]
+    fn does_not_expire_when_the_clock_goes_back() {
+        let clock = ManualClock::new();
+        clock.set(1000);
+        let timer = Timer::with_clock(500, clock.clone());
+
+        clock.set(0);
+        assert!(!timer.is_expired());
+    }
+}
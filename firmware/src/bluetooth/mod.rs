@@ -1,6 +1,10 @@
 #![allow(clippy::module_name_repetitions, dead_code)]
 
-use bluedroid::gatt_server::{Profile, GLOBAL_GATT_SERVER};
+use std::sync::RwLock;
+
+use bluedroid::gatt_server::{Characteristic, Profile, GLOBAL_GATT_SERVER};
+
+use crate::error::FirmwareError;
 
 mod battery;
 mod calibration;
@@ -20,7 +24,6 @@ pub struct BluetoothAPI {
     // pub(crate) pulse_oximeter: pulse_oximeter::PulseOximeterServiceContainer,
     // pub(crate) heart_rate: heart_rate::HeartRateServiceContainer,
     // pub(crate) historic_data: historic_data::HistoricDataServiceContainer,
-    // pub(crate) settings: settings::SettingsServiceContainer,
     // pub(crate) device_information: device_information::DeviceInformationServiceContainer,
     // pub(crate) current_time: current_time::CurrentTimeServiceContainer,
     // pub(crate) battery: battery::BatteryServiceContainer,
@@ -30,6 +33,7 @@ pub struct BluetoothAPI {
         optical_frontend_configuration::OpticalFrontendConfigurationServiceContainer,
     pub(crate) calibration: calibration::CalibrationServiceContainer,
     pub(crate) results: results::ResultsServiceContainer,
+    pub(crate) settings: settings::SettingsServiceContainer,
     // pub(crate) firmware_upgrade: firmware_upgrade::FirmwareUpgradeServiceContainer,
 }
 
@@ -42,6 +46,7 @@ impl BluetoothAPI {
             .service(&self.optical_frontend_configuration.service)
             .service(&self.calibration.service)
             .service(&self.results.service)
+            .service(&self.settings.service)
             .build();
 
         GLOBAL_GATT_SERVER
@@ -58,7 +63,6 @@ impl BluetoothAPI {
         // let pulse_oximeter = pulse_oximeter::PulseOximeterServiceContainer::initialise();
         // let heart_rate = heart_rate::HeartRateServiceContainer::initialise();
         // let historic_data = historic_data::HistoricDataServiceContainer::initialise();
-        // let settings = settings::SettingsServiceContainer::initialise();
         // let device_information = device_information::DeviceInformationServiceContainer::initialise();
         // let current_time = current_time::CurrentTimeServiceContainer::initialise();
         // let battery = battery::BatteryServiceContainer::initialise();
@@ -67,6 +71,7 @@ impl BluetoothAPI {
         let optical_frontend_configuration = optical_frontend_configuration::OpticalFrontendConfigurationServiceContainer::initialise();
         let calibration = calibration::CalibrationServiceContainer::initialise();
         let results = results::ResultsServiceContainer::initialise();
+        let settings = settings::SettingsServiceContainer::initialise();
         // let firmware_upgrade = firmware_upgrade::FirmwareUpgradeServiceContainer::initialise();
 
         Self {
@@ -75,6 +80,20 @@ impl BluetoothAPI {
             optical_frontend_configuration,
             calibration,
             results,
+            settings,
         }
     }
 }
+
+/// Sets the value of the characteristic, notifying the subscribed clients.
+pub(crate) fn set_value<T: Into<Vec<u8>>>(
+    characteristic: &RwLock<Characteristic>,
+    value: T,
+) -> Result<(), FirmwareError> {
+    characteristic
+        .write()
+        .map_err(|_| FirmwareError::Bluetooth("Characteristic poisoned."))?
+        .set_value(value);
+
+    Ok(())
+}
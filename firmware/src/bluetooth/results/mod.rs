@@ -0,0 +1,130 @@
+use std::sync::{Arc, RwLock};
+
+use bluedroid::gatt_server::{Characteristic, Service};
+use bluedroid::utilities::{AttributePermissions, BleUuid, CharacteristicProperties};
+
+pub struct ResultsServiceContainer {
+    pub(crate) service: Arc<RwLock<Service>>,
+    pub(crate) blood_oxygen_saturation_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) heart_rate_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) r: Arc<RwLock<Characteristic>>,
+    pub(crate) led2_perfusion_index_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) led3_perfusion_index_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) wrist_presence_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) measurement_state_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) rr_interval_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) heart_rate_variability_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) spectral_heart_rate_variability_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) edited_beats_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) irregular_rhythm_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) irregular_rhythm_confidence_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) respiration_rate_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) spo2_confidence_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) signal_quality_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) motion_characteristic: Arc<RwLock<Characteristic>>,
+}
+
+impl ResultsServiceContainer {
+    pub(crate) fn initialise() -> Self {
+        let characteristic_list: [(&str, &str, u16); 17] = [
+            (
+                "0776731C-A5F8-4B40-9500-E4F97F5958D9",
+                "Blood oxygen saturation",
+                4,
+            ),
+            ("D8CE0238-F60C-4C1D-908F-5554760AA1D6", "Heart rate", 4),
+            ("459CAB03-5240-4837-9742-B71A5D8112A3", "R", 4),
+            (
+                "32D616C9-5721-4BF0-B5F3-B709C45225EE",
+                "LED2 perfusion index",
+                4,
+            ),
+            (
+                "C11839D6-50E7-4210-AD45-E44C5AB085AC",
+                "LED3 perfusion index",
+                4,
+            ),
+            ("9439189D-C1C2-4970-BD64-B9F1932F159F", "Wrist presence", 1),
+            (
+                "4FD6CF4E-6856-43A0-B6FF-C2B72EE2024E",
+                "Measurement state",
+                1,
+            ),
+            ("2EEA2806-9120-4DDB-877E-982F6C8B4722", "RR interval", 2),
+            (
+                "23C153BB-1D22-41A8-BBC3-6317A8C28AE9",
+                "Heart rate variability",
+                16,
+            ),
+            (
+                "C661FA56-6B40-4695-AAD3-FFCC4752F036",
+                "Spectral heart rate variability",
+                16,
+            ),
+            ("3B04B07C-915F-4099-A340-7C1EA249F0A1", "Edited beats", 4),
+            (
+                "98072374-2EC2-429B-A7CE-5D5FB688941F",
+                "Irregular rhythm",
+                1,
+            ),
+            (
+                "8DBEC731-564B-4B12-8AFE-ADD4D7C5B5B5",
+                "Irregular rhythm confidence",
+                4,
+            ),
+            (
+                "465F2DC8-9FED-4CB7-86AF-25148DC41628",
+                "Respiration rate",
+                5,
+            ),
+            ("275D97C5-4619-4B99-8C4A-E97806384FEB", "SpO2 confidence", 4),
+            ("A9A86A79-A5D5-42BC-A436-F710C6B83425", "Signal quality", 4),
+            ("F84FF5EE-DC08-4B4C-BB1D-63CC625D090D", "Motion", 1),
+        ];
+
+        let mut characteristics: Vec<Arc<RwLock<Characteristic>>> = vec![];
+
+        let mut service = Service::new(BleUuid::from_uuid128_string(
+            "5BE2E901-D0EC-4A5F-9488-3C80CE223852",
+        ))
+        .name("Results")
+        .primary()
+        .clone();
+
+        for item in characteristic_list {
+            let characteristic = Characteristic::new(BleUuid::from_uuid128_string(item.0))
+                .name(item.1)
+                .show_name()
+                .permissions(AttributePermissions::new().read())
+                .properties(CharacteristicProperties::new().read().notify())
+                .max_value_length(item.2)
+                .build();
+
+            service.characteristic(&characteristic);
+            characteristics.push(characteristic);
+        }
+
+        let service = service.build();
+
+        Self {
+            service,
+            blood_oxygen_saturation_characteristic: characteristics[0].clone(),
+            heart_rate_characteristic: characteristics[1].clone(),
+            r: characteristics[2].clone(),
+            led2_perfusion_index_characteristic: characteristics[3].clone(),
+            led3_perfusion_index_characteristic: characteristics[4].clone(),
+            wrist_presence_characteristic: characteristics[5].clone(),
+            measurement_state_characteristic: characteristics[6].clone(),
+            rr_interval_characteristic: characteristics[7].clone(),
+            heart_rate_variability_characteristic: characteristics[8].clone(),
+            spectral_heart_rate_variability_characteristic: characteristics[9].clone(),
+            edited_beats_characteristic: characteristics[10].clone(),
+            irregular_rhythm_characteristic: characteristics[11].clone(),
+            irregular_rhythm_confidence_characteristic: characteristics[12].clone(),
+            respiration_rate_characteristic: characteristics[13].clone(),
+            spo2_confidence_characteristic: characteristics[14].clone(),
+            signal_quality_characteristic: characteristics[15].clone(),
+            motion_characteristic: characteristics[16].clone(),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, RwLock};
+
+use bluedroid::gatt_server::{Characteristic, Service};
+use bluedroid::utilities::{AttributePermissions, BleUuid, CharacteristicProperties};
+
+pub struct SensorDataServiceContainer {
+    pub(crate) service: Arc<RwLock<Service>>,
+    pub(crate) raw_optical_data_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) filtered_optical_data_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) error_count_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) dropped_samples_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) last_fault_characteristic: Arc<RwLock<Characteristic>>,
+}
+
+impl SensorDataServiceContainer {
+    pub(crate) fn initialise() -> Self {
+        let raw_optical_data_characteristic = Characteristic::new(BleUuid::from_uuid128_string(
+            "26CB3CCA-F22E-4179-8125-55874E9153AD",
+        ))
+        .name("Raw optical data")
+        .show_name()
+        .permissions(AttributePermissions::new().read())
+        .properties(CharacteristicProperties::new().read().notify())
+        .max_value_length(16)
+        .build();
+
+        let filtered_optical_data_characteristic = Characteristic::new(
+            BleUuid::from_uuid128_string("BDC0FC52-797B-4065-AABA-DC394F1DD0FD"),
+        )
+        .name("Filtered optical data")
+        .show_name()
+        .permissions(AttributePermissions::new().read())
+        .properties(CharacteristicProperties::new().read().notify())
+        .max_value_length(36)
+        .build();
+
+        let error_count_characteristic = Characteristic::new(BleUuid::from_uuid128_string(
+            "1339CFBA-1F7B-4BB0-B9D6-A74E5F76F98B",
+        ))
+        .name("Error count")
+        .show_name()
+        .permissions(AttributePermissions::new().read())
+        .properties(CharacteristicProperties::new().read().notify())
+        .max_value_length(4)
+        .on_read(|_| {
+            crate::error::ERROR_COUNT
+                .load(std::sync::atomic::Ordering::Relaxed)
+                .to_le_bytes()
+                .to_vec()
+        })
+        .build();
+
+        let dropped_samples_characteristic = Characteristic::new(BleUuid::from_uuid128_string(
+            "3AA7413A-03D3-452E-976E-7218047096E1",
+        ))
+        .name("Dropped samples")
+        .show_name()
+        .permissions(AttributePermissions::new().read())
+        .properties(CharacteristicProperties::new().read().notify())
+        .max_value_length(4)
+        .on_read(|_| {
+            crate::optical::data_reading::DROPPED_SAMPLES
+                .load(std::sync::atomic::Ordering::Relaxed)
+                .to_le_bytes()
+                .to_vec()
+        })
+        .build();
+
+        let last_fault_characteristic = Characteristic::new(BleUuid::from_uuid128_string(
+            "287A205A-43D4-4899-9A82-887ADC28A3E0",
+        ))
+        .name("Last fault")
+        .show_name()
+        .permissions(AttributePermissions::new().read())
+        .properties(CharacteristicProperties::new().read())
+        .max_value_length(64)
+        .on_read(|_| {
+            crate::supervisor::LAST_FAULT
+                .lock()
+                .map(|last_fault| last_fault.as_bytes().to_vec())
+                .unwrap_or_default()
+        })
+        .build();
+
+        let service = Service::new(BleUuid::from_uuid128_string(
+            "272DF1F7-9D28-4B8C-86F6-30DB30ACE42C",
+        ))
+        .name("Sensor data")
+        .primary()
+        .characteristic(&raw_optical_data_characteristic)
+        .characteristic(&filtered_optical_data_characteristic)
+        .characteristic(&error_count_characteristic)
+        .characteristic(&dropped_samples_characteristic)
+        .characteristic(&last_fault_characteristic)
+        .build();
+
+        Self {
+            service,
+            raw_optical_data_characteristic,
+            filtered_optical_data_characteristic,
+            error_count_characteristic,
+            dropped_samples_characteristic,
+            last_fault_characteristic,
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::{Arc, RwLock};
+
+use bluedroid::gatt_server::{Characteristic, Service};
+use bluedroid::utilities::{AttributePermissions, BleUuid, CharacteristicProperties};
+
+pub struct SettingsServiceContainer {
+    pub(crate) service: Arc<RwLock<Service>>,
+    pub(crate) spo2_calibration_preset_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) spo2_calibration_curve_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) averaging_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) heart_rate_method_characteristic: Arc<RwLock<Characteristic>>,
+    pub(crate) spectral_hrv_characteristic: Arc<RwLock<Characteristic>>,
+}
+
+impl SettingsServiceContainer {
+    pub(crate) fn initialise() -> Self {
+        let characteristic_list: [(&str, &str, u16); 5] = [
+            (
+                "6AEAE6DE-4057-4FE3-AFDF-FFC8E303495D",
+                "SpO2 calibration preset",
+                2,
+            ),
+            (
+                "EA74F2CC-36D2-4EDA-B6AC-6A0217F8BA96",
+                "SpO2 calibration curve",
+                12,
+            ),
+            ("88D30596-44E0-4C8B-B099-1C973006FC17", "Averaging", 2),
+            (
+                "C3A5953B-BB52-4EA4-9D1D-F32239392C30",
+                "Heart rate method",
+                1,
+            ),
+            ("9BBFA79F-53AA-46C1-B533-4A51048E6F28", "Spectral HRV", 4),
+        ];
+
+        let mut characteristics: Vec<Arc<RwLock<Characteristic>>> = vec![];
+
+        let mut service = Service::new(BleUuid::from_uuid128_string(
+            "821198C8-3036-4E14-B01C-364F2B20C603",
+        ))
+        .name("Settings")
+        .primary()
+        .clone();
+
+        for item in characteristic_list {
+            let characteristic = Characteristic::new(BleUuid::from_uuid128_string(item.0))
+                .name(item.1)
+                .show_name()
+                .permissions(AttributePermissions::new().read().write())
+                .properties(CharacteristicProperties::new().read().write())
+                .max_value_length(item.2)
+                .build();
+
+            service.characteristic(&characteristic);
+            characteristics.push(characteristic);
+        }
+
+        let service = service.build();
+
+        Self {
+            service,
+            spo2_calibration_preset_characteristic: characteristics[0].clone(),
+            spo2_calibration_curve_characteristic: characteristics[1].clone(),
+            averaging_characteristic: characteristics[2].clone(),
+            heart_rate_method_characteristic: characteristics[3].clone(),
+            spectral_hrv_characteristic: characteristics[4].clone(),
+        }
+    }
+}
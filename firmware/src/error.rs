@@ -0,0 +1,126 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use esp_idf_sys::EspError;
+use pulse_loop_core::error::FrontendError;
+
+/// The number of errors reported since boot, exposed over BLE.
+pub(crate) static ERROR_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// An error that occurred in the firmware.
+#[derive(Debug)]
+pub(crate) enum FirmwareError {
+    /// The optical frontend could not be accessed or returned an error.
+    Frontend(FrontendError),
+    /// An ESP-IDF peripheral, like the interrupt pin, returned an error.
+    Peripheral(EspError),
+    /// A Bluetooth characteristic or the shared data could not be accessed.
+    Bluetooth(&'static str),
+    /// The settings could not be accessed, read from or written to the NVS.
+    Storage(StorageError),
+}
+
+/// An error that occurred while accessing the settings.
+#[derive(Debug)]
+pub(crate) enum StorageError {
+    /// The NVS has not been opened.
+    NotInitialised,
+    /// The mutex of the named setting, or of the NVS, has been poisoned by a panicking thread.
+    Poisoned(&'static str),
+    /// The NVS returned an error.
+    Nvs(EspError),
+}
+
+/// What to do after an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecoveryPolicy {
+    /// Ignore the error and try again at the next iteration.
+    Retry,
+    /// Reset and configure the frontend again, then restart the measurement.
+    ReinitialiseFrontend,
+    /// Turn off the LEDs and stop using the frontend.
+    SafeState,
+}
+
+impl FirmwareError {
+    /// The consecutive errors after which the frontend is reinitialised instead of retrying.
+    pub(crate) const MAX_RETRIES: u32 = 3;
+    /// The consecutive errors after which the firmware gives up and degrades to the safe state.
+    pub(crate) const MAX_REINITIALISATIONS: u32 = 10;
+
+    /// Returns the recovery policy for this error, given how many errors occurred in a row, including this one.
+    pub(crate) fn recovery_policy(&self, consecutive_errors: u32) -> RecoveryPolicy {
+        match self {
+            FirmwareError::Frontend(FrontendError::Poisoned) => RecoveryPolicy::SafeState,
+            FirmwareError::Frontend(FrontendError::NotInitialised) => {
+                RecoveryPolicy::ReinitialiseFrontend
+            }
+            FirmwareError::Frontend(FrontendError::Device(_)) => {
+                if consecutive_errors < Self::MAX_RETRIES {
+                    RecoveryPolicy::Retry
+                } else if consecutive_errors < Self::MAX_REINITIALISATIONS {
+                    RecoveryPolicy::ReinitialiseFrontend
+                } else {
+                    RecoveryPolicy::SafeState
+                }
+            }
+            FirmwareError::Peripheral(_) => {
+                if consecutive_errors < Self::MAX_RETRIES {
+                    RecoveryPolicy::Retry
+                } else {
+                    RecoveryPolicy::SafeState
+                }
+            }
+            FirmwareError::Bluetooth(_) | FirmwareError::Storage(_) => RecoveryPolicy::Retry,
+        }
+    }
+
+    /// Logs the error and increments the error counter.
+    pub(crate) fn report(&self) {
+        log::error!("{}", self);
+        ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Display for FirmwareError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FirmwareError::Frontend(error) => write!(f, "Frontend error: {}", error),
+            FirmwareError::Peripheral(error) => write!(f, "Peripheral error: {}", error),
+            FirmwareError::Bluetooth(error) => write!(f, "Bluetooth error: {}", error),
+            FirmwareError::Storage(error) => write!(f, "Storage error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for FirmwareError {}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotInitialised => write!(f, "NVS not initialised"),
+            StorageError::Poisoned(name) => write!(f, "{} mutex poisoned", name),
+            StorageError::Nvs(error) => write!(f, "NVS error: {}", error),
+        }
+    }
+}
+
+impl From<FrontendError> for FirmwareError {
+    fn from(error: FrontendError) -> Self {
+        FirmwareError::Frontend(error)
+    }
+}
+
+impl From<StorageError> for FirmwareError {
+    fn from(error: StorageError) -> Self {
+        FirmwareError::Storage(error)
+    }
+}
+
+impl From<EspError> for FirmwareError {
+    fn from(error: EspError) -> Self {
+        FirmwareError::Peripheral(error)
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    sync::{atomic::Ordering, mpsc::Receiver, Arc, Mutex, PoisonError, RwLock},
+    thread,
+};
+
+use esp_idf_hal::{
+    gpio::PinDriver,
+    i2c::{config::Config, I2cDriver},
+    peripherals::Peripherals,
+    prelude::*,
+};
+
+// If using the `binstart` feature of `esp-idf-sys`, always keep this module imported.
+use esp_idf_sys::{self as _, esp_get_free_heap_size, esp_get_free_internal_heap_size};
+
+use pulse_loop_core::{
+    acquisition::{ring_buffer, AcquiredSample},
+    calibration::offset_measuring,
+    clock::ManualClock,
+    error::FrontendError,
+    frontend::with_frontend,
+    pipeline::VitalSignsPipeline,
+    protocol::{FilteredData, RawData, Results},
+    recording::{self, RecordedSample},
+    timer::Timer,
+};
+
+mod bluetooth;
+mod error;
+mod optical;
+mod settings;
+mod supervisor;
+
+use error::FirmwareError;
+use optical::data_reading::{self, ACQUISITION_BUFFER_SIZE};
+use settings::SettingChange;
+use supervisor::Supervisor;
+
+fn main() {
+    // Temporary. Will disappear once ESP-IDF 4.4 is released, but for now it is necessary to call this function once,
+    // or else some patches to the runtime implemented by esp-idf-sys might not link properly.
+    esp_idf_sys::link_patches();
+
+    // Initialise logger.
+    esp_idf_svc::log::EspLogger::initialize_default();
+    log::info!("Logger initialised.");
+
+    let peripherals = Peripherals::take().unwrap();
+    let config = Config::new().baudrate(400.kHz().into());
+
+    // Initialise frontend.
+    let i2c = I2cDriver::new(
+        peripherals.i2c0,
+        peripherals.pins.gpio3,
+        peripherals.pins.gpio2,
+        &config,
+    )
+    .expect("Failed to initialise I2C bus.");
+
+    let mut interrupt_pin = PinDriver::input(peripherals.pins.gpio4).unwrap();
+    let ble_api = Arc::new(RwLock::new(bluetooth::BluetoothAPI::initialise()));
+    let mut offset_currents = offset_measuring::OffsetCurrents::new();
+
+    // Load the settings, the default ones are used if the NVS cannot be opened.
+    if let Err(e) = settings::initialise() {
+        e.report();
+    }
+    settings::attach_settings_chars(&mut ble_api.write().unwrap());
+
+    if let Err(e) = optical::initialise(
+        i2c,
+        &mut interrupt_pin,
+        ble_api.clone(),
+        &mut offset_currents,
+    ) {
+        // The frontend could not be set up even after retrying.
+        e.report();
+        optical::enter_safe_state();
+    }
+
+    // The latest data that will be sent to the application.
+    let latest_raw_data: Arc<Mutex<RawData>> = Arc::new(Mutex::new(RawData::default()));
+    let latest_filtered_data: Arc<Mutex<FilteredData>> =
+        Arc::new(Mutex::new(FilteredData::default()));
+    let latest_results: Arc<Mutex<Results>> = Arc::new(Mutex::new(Results::default()));
+
+    let mut supervisor = Supervisor::new();
+
+    let ble_api_for_notify = ble_api.clone();
+    let latest_data_for_notify = latest_raw_data.clone();
+    let latest_filtered_data_for_notify = latest_filtered_data.clone();
+    let latest_results_for_notify = latest_results.clone();
+    supervisor.spawn("data_notify", 1024 * 8, move |heartbeat| {
+        optical::data_sending::notify_task(
+            ble_api_for_notify.clone(),
+            latest_data_for_notify.clone(),
+            latest_filtered_data_for_notify.clone(),
+            latest_results_for_notify.clone(),
+            heartbeat,
+        )
+    });
+
+    let (producer, consumer) = ring_buffer::<AcquiredSample, ACQUISITION_BUFFER_SIZE>();
+    // The producer and the consumer are locked by their task for its whole life, and taken over when restarted.
+    let producer = Mutex::new(producer);
+    let consumer = Mutex::new(consumer);
+
+    supervisor.spawn("data_acquisition", 1024 * 8, move |heartbeat| {
+        let mut producer = producer.lock().unwrap_or_else(PoisonError::into_inner);
+        data_reading::acquisition_task(&optical::FRONTEND, heartbeat, &mut producer)
+    });
+
+    // The offset currents are copied, so that every restart of the task starts from the measured ones.
+    let measured_offset_currents = *offset_currents.currents();
+    supervisor.spawn("data_processing", 1024 * 16, move |heartbeat| {
+        let mut consumer = consumer.lock().unwrap_or_else(PoisonError::into_inner);
+        let setting_changes = settings::setting_changes()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let ble_api = ble_api.clone();
+        let latest_raw_data = latest_raw_data.clone();
+        let latest_filtered_data = latest_filtered_data.clone();
+        let latest_results = latest_results.clone();
+
+        // The pipeline is timed with the timestamps of the samples, so that it does not depend on the processing delay.
+        let clock = ManualClock::new();
+        clock.set(data_reading::now());
+        let mut pipeline = VitalSignsPipeline::new(
+            offset_measuring::OffsetCurrents::from_currents(measured_offset_currents),
+            clock.clone(),
+        );
+        // The settings written from now on are received from the application, and applied between two samples.
+        if let Err(e) = settings::configure(&mut pipeline) {
+            e.report();
+        }
+        if let Err(e) = optical::perform_frontend_actions(pipeline.state().entry_actions()) {
+            optical::recover(e, 1);
+        }
+        let mut consecutive_errors = 0;
+
+        data_reading::processing_task(&mut consumer, heartbeat, move |sample| {
+            match process(
+                sample,
+                &mut pipeline,
+                &clock,
+                &setting_changes,
+                &ble_api,
+                &latest_raw_data,
+                &latest_filtered_data,
+                &latest_results,
+            ) {
+                Ok(()) => consecutive_errors = 0,
+                Err(e) => {
+                    consecutive_errors += 1;
+                    optical::recover(e, consecutive_errors);
+                }
+            }
+        })
+    });
+
+    let mut heap_timer = Timer::new(5000);
+    loop {
+        thread::sleep(Supervisor::SUPERVISION_PERIOD);
+        supervisor.supervise();
+
+        if heap_timer.is_expired() {
+            unsafe {
+                let x = esp_get_free_heap_size();
+                let y = esp_get_free_internal_heap_size();
+                log::info!("Free heap: {} bytes, free internal heap: {} bytes", x, y);
+            }
+            heap_timer.reset();
+        }
+    }
+}
+
+/// Processes the sample, calibrates the frontend and sends the results to the application.
+fn process(
+    sample: AcquiredSample,
+    pipeline: &mut VitalSignsPipeline<ManualClock>,
+    clock: &ManualClock,
+    setting_changes: &Receiver<SettingChange>,
+    ble_api: &RwLock<bluetooth::BluetoothAPI>,
+    latest_raw_data: &Mutex<RawData>,
+    latest_filtered_data: &Mutex<FilteredData>,
+    latest_results: &Mutex<Results>,
+) -> Result<(), FirmwareError> {
+    // Restart the measurement if the frontend has been reinitialised since the previous sample.
+    if optical::FRONTEND_REINITIALISED.swap(false, Ordering::Relaxed) {
+        if let Some(transition) = pipeline.frontend_changed() {
+            optical::perform_frontend_actions(transition.actions())?;
+        }
+    }
+
+    // Update the offset currents used to convert the readings.
+    let green_offset_current = optical::with_calibrator(&optical::CALIBRATOR_LED1, |calibrator| {
+        Ok(calibrator.offset_current)
+    })?;
+    let red_ir_offset_current =
+        optical::with_calibrator(&optical::CALIBRATOR_LED2_LED3, |calibrator| {
+            Ok(calibrator.offset_current)
+        })?;
+    pipeline.set_offset_currents(green_offset_current, red_ir_offset_current);
+    // Apply the settings written by the application since the previous sample.
+    for change in setting_changes.try_iter() {
+        change.apply(pipeline);
+    }
+
+    let raw_data = sample.raw_data;
+    clock.set(sample.timestamp);
+
+    // Log the sample with the frontend settings, so that the session can be replayed.
+    if log::log_enabled!(log::Level::Debug) {
+        let led_currents = with_frontend(&optical::FRONTEND, |frontend| {
+            Ok::<_, FrontendError>([
+                frontend.get_led1_current().unwrap_or_default(),
+                frontend.get_led2_current().unwrap_or_default(),
+                frontend.get_led3_current().unwrap_or_default(),
+            ])
+        })?;
+        let recorded_sample = RecordedSample {
+            timestamp: sample.timestamp,
+            raw_data,
+            led_currents,
+            offset_currents: [
+                green_offset_current,
+                red_ir_offset_current,
+                red_ir_offset_current,
+            ],
+        };
+        log::debug!("{}{}", recording::SAMPLE_MARKER, recorded_sample.to_csv());
+    }
+
+    let output = pipeline.process(raw_data);
+    if let Some(transition) = output.transition {
+        optical::perform_frontend_actions(transition.actions())?;
+    }
+
+    if output.state.is_wrist_present() {
+        // Calibrate.
+        if optical::with_calibrator(&optical::CALIBRATOR_LED1, |calibrator| {
+            calibrator.calibrate_dc(raw_data.led1)
+        })? {
+            log::info!("Calibrated GREEN");
+            if let Some(transition) = pipeline.frontend_changed() {
+                optical::perform_frontend_actions(transition.actions())?;
+            }
+        }
+
+        // The calibration on the RED and IR LEDs is performed together, based on the IR LED.
+        if optical::with_calibrator(&optical::CALIBRATOR_LED2_LED3, |calibrator| {
+            calibrator.calibrate_dc(raw_data.led3)
+        })? {
+            log::info!("Calibrated RED and IR");
+            if let Some(transition) = pipeline.frontend_changed() {
+                optical::perform_frontend_actions(transition.actions())?;
+            }
+        }
+
+        // Send filtered data to the application.
+        if let Some(filtered_data) = output.filtered_data {
+            let mut latest_filtered_data = latest_filtered_data
+                .lock()
+                .map_err(|_| FirmwareError::Bluetooth("Filtered data poisoned."))?;
+            latest_filtered_data.led1 = filtered_data.led1;
+            latest_filtered_data.led2 = filtered_data.led2;
+            latest_filtered_data.led3 = filtered_data.led3;
+        }
+
+        // Send the heart rate to the application.
+        if let Some(heart_rate) = output.heart_rate {
+            let ble_api = ble_api
+                .read()
+                .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+            bluetooth::set_value(
+                &ble_api.results.heart_rate_characteristic,
+                heart_rate.to_le_bytes(),
+            )?;
+        }
+
+        // Send the fraction of the edited RR intervals, the normal ones and the heart rate variability to the
+        // application.
+        if let Some(edited_fraction) = output.edited_fraction {
+            let ble_api = ble_api
+                .read()
+                .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+            bluetooth::set_value(
+                &ble_api.results.edited_beats_characteristic,
+                (edited_fraction * 100.0).to_le_bytes(),
+            )?;
+        }
+        if let Some(rr_interval) = output.rr_interval {
+            let ble_api = ble_api
+                .read()
+                .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+            bluetooth::set_value(
+                &ble_api.results.rr_interval_characteristic,
+                (rr_interval as u16).to_le_bytes(),
+            )?;
+        }
+        if let Some(hrv) = output.hrv {
+            let ble_api = ble_api
+                .read()
+                .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+            bluetooth::set_value(
+                &ble_api.results.heart_rate_variability_characteristic,
+                hrv.serialise(),
+            )?;
+        }
+        if let Some(spectral_hrv) = output.spectral_hrv {
+            let ble_api = ble_api
+                .read()
+                .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+            bluetooth::set_value(
+                &ble_api
+                    .results
+                    .spectral_heart_rate_variability_characteristic,
+                spectral_hrv.serialise(),
+            )?;
+        }
+    }
+
+    // Send the results to the application.
+    *latest_results
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Results poisoned."))? = output.results;
+    // Send raw data to the application.
+    *latest_raw_data
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Raw data poisoned."))? = raw_data;
+    // Send the beat threshold to the application.
+    latest_filtered_data
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Filtered data poisoned."))?
+        .led1_threshold = output.beat_threshold;
+
+    Ok(())
+}
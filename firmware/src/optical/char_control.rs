@@ -3,6 +3,7 @@ use std::sync::{Arc, Mutex};
 use afe4404::{device::AFE4404, modes::ThreeLedsMode};
 use core::convert::TryInto;
 use esp_idf_hal::i2c::I2cDriver;
+use pulse_loop_core::frontend::with_frontend;
 use uom::si::{
     electric_current::ampere,
     electric_potential::volt,
@@ -10,6 +11,9 @@ use uom::si::{
     time::second,
 };
 
+use super::with_calibrator;
+use crate::error::FirmwareError;
+
 macro_rules! attach_char {
     // Otical frontend uom f32 value.
     (optical frontend, $ble_characteristic:expr, $frontend:ident, $setter:ident, $getter:ident, $quantity:ident, $unit:ident) => {
@@ -19,31 +23,34 @@ macro_rules! attach_char {
             .write()
             .unwrap()
             .on_write(move |value, _| {
+                if value.len() < 4 {
+                    log::error!("Invalid value for {}: {:?}", stringify!($ble_characteristic), value);
+                    return;
+                }
+
                 let mut slice: [u8; 4] = [0; 4];
                 slice.copy_from_slice(&value[..4]);
                 let value = f32::from_le_bytes(slice);
 
                 log::info!("Setting {} to {}", stringify!($ble_characteristic), value);
 
-                let result = $frontend
-                    .lock()
-                    .unwrap()
-                    .as_mut()
-                    .unwrap()
-                    .$setter($quantity::new::<$unit>(value));
+                let result = with_frontend($frontend, |frontend| {
+                    frontend.$setter($quantity::new::<$unit>(value))
+                });
 
                 match result {
                     Ok(result) => {
                         log::info!("{} set to {:?}", stringify!($ble_characteristic), result);
                     }
                     Err(e) => {
-                        log::error!("Error setting {}: {:?}", stringify!($ble_characteristic), e);
+                        log::error!("Error setting {}.", stringify!($ble_characteristic));
+                        FirmwareError::from(e).report();
                     }
                 }
             });
 
         $ble_characteristic.write().unwrap().on_read(move |_| {
-            let result = $frontend.lock().unwrap().as_mut().unwrap().$getter();
+            let result = with_frontend($frontend, |frontend| frontend.$getter());
 
             match result {
                 Ok(result) => {
@@ -51,7 +58,8 @@ macro_rules! attach_char {
                     result.get::<$unit>().to_le_bytes().to_vec()
                 }
                 Err(e) => {
-                    log::error!("Error getting {}: {:?}", stringify!($ble_characteristic), e);
+                    log::error!("Error getting {}.", stringify!($ble_characteristic));
+                    FirmwareError::from(e).report();
                     vec![]
                 }
             }
@@ -66,38 +74,55 @@ macro_rules! attach_char {
             .write()
             .unwrap()
             .on_write(move |value, _| {
+                if value.is_empty() {
+                    log::error!("Invalid value for {}: {:?}", stringify!($ble_characteristic), value);
+                    return;
+                }
+
                 let value = value[0];
 
                 log::info!("Setting {} to {}", stringify!($ble_characteristic), value);
 
-                let result = $frontend
-                    .lock()
-                    .unwrap()
-                    .as_mut()
-                    .unwrap()
-                    .$setter(value.try_into().expect("Unable to convert u8 to enum."));
+                // The value is converted inside the closure, so that its type is inferred from the setter.
+                let result = with_frontend($frontend, |frontend| match value.try_into() {
+                    Ok(converted) => frontend.$setter(converted).map(Some),
+                    Err(_) => Ok(None),
+                });
 
                 match result {
-                    Ok(()) => {
+                    Ok(Some(())) => {
                         log::info!("{} set to {}", stringify!($ble_characteristic), value);
                     }
+                    Ok(None) => {
+                        log::error!("Invalid value for {}: {}", stringify!($ble_characteristic), value);
+                    }
                     Err(e) => {
-                        log::error!("Error setting {}: {:?}", stringify!($ble_characteristic), e);
+                        log::error!("Error setting {}.", stringify!($ble_characteristic));
+                        FirmwareError::from(e).report();
                     }
                 }
             });
 
         $ble_characteristic.write().unwrap().on_read(move |_| {
-            let result = $frontend.lock().unwrap().as_mut().unwrap().$getter();
+            let result = with_frontend($frontend, |frontend| frontend.$getter());
 
             match result {
                 Ok(result) => {
-                    let result: u8 = result.try_into().expect("Unable to convert enum to u8."); // Convert to u8.
-                    log::info!("{} is {}", stringify!($ble_characteristic), result);
-                    vec![result]
+                    let result: Result<u8, _> = result.try_into(); // Convert to u8.
+                    match result {
+                        Ok(result) => {
+                            log::info!("{} is {}", stringify!($ble_characteristic), result);
+                            vec![result]
+                        }
+                        Err(_) => {
+                            log::error!("Cannot convert {} to u8.", stringify!($ble_characteristic));
+                            vec![]
+                        }
+                    }
                 }
                 Err(e) => {
-                    log::error!("Error getting {}: {:?}", stringify!($ble_characteristic), e);
+                    log::error!("Error getting {}.", stringify!($ble_characteristic));
+                    FirmwareError::from(e).report();
                     vec![]
                 }
             }
@@ -112,24 +137,30 @@ macro_rules! attach_char {
             .write()
             .unwrap()
             .on_write(move |value, _| {
+                if value.is_empty() {
+                    log::error!("Invalid value for {}: {:?}", stringify!($ble_characteristic), value);
+                    return;
+                }
+
                 let value = value[0];
 
                 log::info!("Setting {} to {}", stringify!($ble_characteristic), value);
 
-                let result = $frontend.lock().unwrap().as_mut().unwrap().$setter(value);
+                let result = with_frontend($frontend, |frontend| frontend.$setter(value));
 
                 match result {
                     Ok(result) => {
                         log::info!("{} set to {:?}", stringify!($ble_characteristic), result);
                     }
                     Err(e) => {
-                        log::error!("Error setting {}: {:?}", stringify!($ble_characteristic), e);
+                        log::error!("Error setting {}.", stringify!($ble_characteristic));
+                        FirmwareError::from(e).report();
                     }
                 }
             });
 
         $ble_characteristic.write().unwrap().on_read(move |_| {
-            let result = $frontend.lock().unwrap().as_mut().unwrap().$getter();
+            let result = with_frontend($frontend, |frontend| frontend.$getter());
 
             match result {
                 Ok(result) => {
@@ -137,7 +168,8 @@ macro_rules! attach_char {
                     vec![result]
                 }
                 Err(e) => {
-                    log::error!("Error getting {}: {:?}", stringify!($ble_characteristic), e);
+                    log::error!("Error getting {}.", stringify!($ble_characteristic));
+                    FirmwareError::from(e).report();
                     vec![]
                 }
             }
@@ -152,28 +184,51 @@ macro_rules! attach_char {
             .write()
             .unwrap()
             .on_write(move |value, _| {
+                if value.len() < 4 {
+                    log::error!("Invalid value for {}: {:?}", stringify!($ble_characteristic), value);
+                    return;
+                }
+
                 let mut slice: [u8; 4] = [0; 4];
                 slice.copy_from_slice(&value[..4]);
                 let value = f32::from_le_bytes(slice);
 
                 log::info!("Setting {} to {}", stringify!($ble_characteristic), value);
 
-                *$calibrator.lock().unwrap().as_mut().unwrap().$setter() =
-                    $quantity::new::<$unit>(value);
+                let result = with_calibrator($calibrator, |calibrator| {
+                    *calibrator.$setter() = $quantity::new::<$unit>(value);
+                    Ok(())
+                });
 
-                log::info!(
-                    "{} set to {:?}",
-                    stringify!($ble_characteristic),
-                    $quantity::new::<$unit>(value)
-                );
+                match result {
+                    Ok(()) => {
+                        log::info!(
+                            "{} set to {:?}",
+                            stringify!($ble_characteristic),
+                            $quantity::new::<$unit>(value)
+                        );
+                    }
+                    Err(e) => {
+                        log::error!("Error setting {}.", stringify!($ble_characteristic));
+                        FirmwareError::from(e).report();
+                    }
+                }
             });
 
         $ble_characteristic.write().unwrap().on_read(move |_| {
-            let value = *$calibrator.lock().unwrap().as_mut().unwrap().$getter();
+            let result = with_calibrator($calibrator, |calibrator| Ok(*calibrator.$getter()));
 
-            log::info!("{} is {:?}", stringify!($ble_characteristic), value);
-
-            value.get::<$unit>().to_le_bytes().to_vec()
+            match result {
+                Ok(value) => {
+                    log::info!("{} is {:?}", stringify!($ble_characteristic), value);
+                    value.get::<$unit>().to_le_bytes().to_vec()
+                }
+                Err(e) => {
+                    log::error!("Error getting {}.", stringify!($ble_characteristic));
+                    FirmwareError::from(e).report();
+                    vec![]
+                }
+            }
         });
     };
 
@@ -185,23 +240,47 @@ macro_rules! attach_char {
             .write()
             .unwrap()
             .on_write(move |value, _| {
+                if value.len() < 4 {
+                    log::error!("Invalid value for {}: {:?}", stringify!($ble_characteristic), value);
+                    return;
+                }
+
                 let mut slice: [u8; 4] = [0; 4];
                 slice.copy_from_slice(&value[..4]);
                 let value = f32::from_le_bytes(slice);
 
                 log::info!("Setting {} to {}", stringify!($ble_characteristic), value);
 
-                *$calibrator.lock().unwrap().as_mut().unwrap().$setter() = value;
+                let result = with_calibrator($calibrator, |calibrator| {
+                    *calibrator.$setter() = value;
+                    Ok(())
+                });
 
-                log::info!("{} set to {}", stringify!($ble_characteristic), value);
+                match result {
+                    Ok(()) => {
+                        log::info!("{} set to {}", stringify!($ble_characteristic), value);
+                    }
+                    Err(e) => {
+                        log::error!("Error setting {}.", stringify!($ble_characteristic));
+                        FirmwareError::from(e).report();
+                    }
+                }
             });
 
         $ble_characteristic.write().unwrap().on_read(move |_| {
-            let value = *$calibrator.lock().unwrap().as_mut().unwrap().$getter();
+            let result = with_calibrator($calibrator, |calibrator| Ok(*calibrator.$getter()));
 
-            log::info!("{} is {}", stringify!($ble_characteristic), value);
-
-            value.to_le_bytes().to_vec()
+            match result {
+                Ok(value) => {
+                    log::info!("{} is {}", stringify!($ble_characteristic), value);
+                    value.to_le_bytes().to_vec()
+                }
+                Err(e) => {
+                    log::error!("Error getting {}.", stringify!($ble_characteristic));
+                    FirmwareError::from(e).report();
+                    vec![]
+                }
+            }
         });
     };
 }
@@ -707,8 +786,8 @@ pub(crate) fn attach_optical_frontend_chars(
 }
 
 pub(crate) fn attach_optical_calibration_chars(
-    calibrator1: &'static Arc<Mutex<Option<super::calibration::Calibrator>>>,
-    calibrator2: &'static Arc<Mutex<Option<super::calibration::Calibrator>>>,
+    calibrator1: &'static Arc<Mutex<Option<pulse_loop_core::calibration::Calibrator>>>,
+    calibrator2: &'static Arc<Mutex<Option<pulse_loop_core::calibration::Calibrator>>>,
     ble_api: &mut crate::bluetooth::BluetoothAPI,
 ) {
     attach_char!(
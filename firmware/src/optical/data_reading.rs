@@ -0,0 +1,143 @@
+use std::{
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use esp_idf_hal::task;
+use esp_idf_sys::{esp_timer_get_time, tskTaskControlBlock};
+use pulse_loop_core::{
+    acquisition::{AcquiredSample, Consumer, Producer, SequenceTracker},
+    frontend::{with_frontend, OpticalFrontend},
+};
+
+use crate::supervisor::Heartbeat;
+
+/// The number of samples that can wait to be processed.
+pub(crate) const ACQUISITION_BUFFER_SIZE: usize = 64;
+
+/// The longest time the acquisition task waits for a data ready interrupt, so that it keeps beating while the
+/// frontend is silent, e.g. in the safe state.
+const DATA_READY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The handle of the acquisition task, notified by the data ready interrupt, or null until the task has started.
+static ACQUISITION_TASK: AtomicPtr<tskTaskControlBlock> = AtomicPtr::new(ptr::null_mut());
+
+/// The number of data ready interrupts since boot, wrapping around. It is the sequence number of the samples.
+pub static SAMPLE_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// The time of the latest data ready interrupt, in milliseconds since boot.
+pub static SAMPLE_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+
+/// The number of samples that have not been processed since boot.
+pub static DROPPED_SAMPLES: AtomicU32 = AtomicU32::new(0);
+
+/// Gets the milliseconds since boot, the time base of the sample timestamps.
+pub fn now() -> u128 {
+    (unsafe { esp_timer_get_time() } / 1000) as u128
+}
+
+/// This function should be called by the data ready interrupt of the AFE4404.
+pub fn on_data_ready() {
+    SAMPLE_TIMESTAMP.store(now() as u64, Ordering::Relaxed);
+
+    // The interrupt is the only writer, so no read-modify-write operation is needed.
+    let sequence = SAMPLE_SEQUENCE.load(Ordering::Relaxed);
+    SAMPLE_SEQUENCE.store(sequence.wrapping_add(1), Ordering::Release);
+
+    // Wakes the acquisition task up, so that it reads the sample right away.
+    let acquisition_task = ACQUISITION_TASK.load(Ordering::Acquire);
+    if !acquisition_task.is_null() {
+        unsafe {
+            task::notify(acquisition_task, 1);
+        }
+    }
+}
+
+/// This function should be called in a separate thread to get readings from the AFE4404.
+/// The thread blocks until it is notified by the data ready interrupt, then the reading is pushed with its sequence
+/// number and timestamp to the producer. The readings that overlap with the next data ready interrupt are discarded,
+/// and they are counted as dropped by the consumer.
+/// The reading errors are recovered according to their policy, and no readings are requested in the safe state.
+pub fn acquisition_task<F>(
+    frontend: &Mutex<Option<F>>,
+    heartbeat: Heartbeat,
+    producer: &mut Producer<AcquiredSample, ACQUISITION_BUFFER_SIZE>,
+) where
+    F: OpticalFrontend,
+{
+    // The handle is replaced when the task is restarted by the supervisor.
+    if let Some(handle) = task::current() {
+        ACQUISITION_TASK.store(handle, Ordering::Release);
+    }
+    let mut last_sequence = SAMPLE_SEQUENCE.load(Ordering::Acquire);
+    let mut consecutive_errors = 0;
+
+    loop {
+        task::wait_notification(Some(DATA_READY_TIMEOUT));
+        heartbeat.beat();
+
+        if super::SAFE_STATE.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let sequence = SAMPLE_SEQUENCE.load(Ordering::Acquire);
+        if sequence == last_sequence {
+            continue;
+        }
+        last_sequence = sequence;
+        let timestamp = SAMPLE_TIMESTAMP.load(Ordering::Relaxed) as u128;
+
+        match with_frontend(frontend, |frontend| frontend.read()) {
+            Ok(raw_data) => {
+                consecutive_errors = 0;
+
+                if SAMPLE_SEQUENCE.load(Ordering::Acquire) != sequence {
+                    // The interrupt has been triggered again during frontend.read(), so the readings overlapped.
+                    log::warn!("Readings have overlapped.");
+                } else if !producer.push(AcquiredSample {
+                    sequence,
+                    timestamp,
+                    raw_data,
+                }) {
+                    log::warn!("Acquisition buffer full.");
+                }
+            }
+            Err(e) => {
+                consecutive_errors += 1;
+                super::recover(e.into(), consecutive_errors);
+            }
+        }
+    }
+}
+
+/// This function should be called in a separate thread to process the readings from the AFE4404.
+/// The callback is called with every sample popped from the consumer, in order, and the gaps in the sequence
+/// numbers are counted in `DROPPED_SAMPLES`.
+pub fn processing_task<CB>(
+    consumer: &mut Consumer<AcquiredSample, ACQUISITION_BUFFER_SIZE>,
+    heartbeat: Heartbeat,
+    mut callback: CB,
+) where
+    CB: FnMut(AcquiredSample),
+{
+    let mut tracker = SequenceTracker::new();
+
+    loop {
+        thread::sleep(Duration::from_millis(5));
+        heartbeat.beat();
+
+        while let Some(sample) = consumer.pop() {
+            let dropped = tracker.track(sample.sequence);
+            if dropped > 0 {
+                DROPPED_SAMPLES.fetch_add(dropped, Ordering::Relaxed);
+                log::warn!("{} samples dropped before {}.", dropped, sample.sequence);
+            }
+
+            callback(sample);
+            heartbeat.beat();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{atomic::Ordering, Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use pulse_loop_core::{
+    protocol::{FilteredData, RawData, Results},
+    timer::Timer,
+};
+
+use super::data_reading::DROPPED_SAMPLES;
+use crate::{
+    bluetooth::{set_value, BluetoothAPI},
+    error::{FirmwareError, ERROR_COUNT},
+    supervisor::Heartbeat,
+};
+
+/// This funtion should be called in a separate thread to send the readings from the AFE4404.
+pub fn notify_task(
+    ble_api: Arc<RwLock<BluetoothAPI>>,
+    raw_data: Arc<Mutex<RawData>>,
+    filtered_data: Arc<Mutex<FilteredData>>,
+    results: Arc<Mutex<Results>>,
+    heartbeat: Heartbeat,
+) {
+    let mut notify_timer = Timer::new(50);
+    loop {
+        thread::sleep(Duration::from_millis(10));
+        heartbeat.beat();
+
+        if notify_timer.is_expired() {
+            if let Err(e) = notify(&ble_api, &raw_data, &filtered_data, &results) {
+                e.report();
+            }
+
+            notify_timer.reset();
+        }
+    }
+}
+
+/// Sets the latest data to the characteristics.
+fn notify(
+    ble_api: &RwLock<BluetoothAPI>,
+    raw_data: &Mutex<RawData>,
+    filtered_data: &Mutex<FilteredData>,
+    results: &Mutex<Results>,
+) -> Result<(), FirmwareError> {
+    let ble_api = ble_api
+        .read()
+        .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+    let raw_data = *raw_data
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Raw data poisoned."))?;
+    let filtered_data = *filtered_data
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Filtered data poisoned."))?;
+    let results = *results
+        .lock()
+        .map_err(|_| FirmwareError::Bluetooth("Results poisoned."))?;
+
+    set_value(
+        &ble_api.sensor_data.raw_optical_data_characteristic,
+        raw_data.serialise(),
+    )?;
+    set_value(
+        &ble_api.sensor_data.filtered_optical_data_characteristic,
+        filtered_data.serialise(),
+    )?;
+    set_value(
+        &ble_api.sensor_data.error_count_characteristic,
+        ERROR_COUNT.load(Ordering::Relaxed).to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.sensor_data.dropped_samples_characteristic,
+        DROPPED_SAMPLES.load(Ordering::Relaxed).to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.wrist_presence_characteristic,
+        (results.wrist_presence as u8).to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.measurement_state_characteristic,
+        results.measurement_state.serialise().to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.blood_oxygen_saturation_characteristic,
+        // NaN reports an invalid SpO2.
+        results.spo2.unwrap_or(f32::NAN).to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.spo2_confidence_characteristic,
+        results.spo2_confidence.to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.signal_quality_characteristic,
+        results.signal_quality.to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.motion_characteristic,
+        (results.motion as u8).to_le_bytes(),
+    )?;
+    set_value(&ble_api.results.r, results.r.to_le_bytes())?;
+    set_value(
+        &ble_api.results.led2_perfusion_index_characteristic,
+        results.red_pi.to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.led3_perfusion_index_characteristic,
+        results.ir_pi.to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.irregular_rhythm_characteristic,
+        (results.irregular_rhythm as u8).to_le_bytes(),
+    )?;
+    set_value(
+        &ble_api.results.irregular_rhythm_confidence_characteristic,
+        results.irregular_rhythm_confidence.to_le_bytes(),
+    )?;
+
+    let mut respiration_rate = [0; 5];
+    respiration_rate[0..4].copy_from_slice(&results.respiration_rate.to_le_bytes());
+    respiration_rate[4] = results.respiration_rate_reliable as u8;
+    set_value(
+        &ble_api.results.respiration_rate_characteristic,
+        respiration_rate,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, RwLock,
+};
+
+use esp_idf_hal::{
+    gpio::{Input, Pin, PinDriver},
+    i2c::I2cDriver,
+};
+
+use uom::si::{
+    electric_current::milliampere,
+    f32::{ElectricCurrent, Frequency},
+    frequency::megahertz,
+};
+
+use afe4404::{device::AFE4404, led_current::LedCurrentConfiguration, modes::ThreeLedsMode};
+
+use pulse_loop_core::{
+    calibration::{self, offset_measuring::OffsetCurrents, Calibrator},
+    error::FrontendError,
+    frontend::{self, with_frontend},
+    measurement::FrontendAction,
+};
+
+use crate::{
+    bluetooth::BluetoothAPI,
+    error::{FirmwareError, RecoveryPolicy},
+};
+
+pub mod char_control;
+pub mod data_reading;
+pub mod data_sending;
+
+lazy_static::lazy_static! {
+    pub static ref FRONTEND: Arc<Mutex<Option<AFE4404<I2cDriver<'static>, ThreeLedsMode>>>> = Arc::new(Mutex::new(None));
+    pub(crate) static ref CALIBRATOR_LED1: Arc<Mutex<Option<Calibrator>>> = Arc::new(Mutex::new(None));
+    pub(crate) static ref CALIBRATOR_LED2_LED3: Arc<Mutex<Option<Calibrator>>> = Arc::new(Mutex::new(None));
+}
+
+/// This is a flag that is set to true when the frontend has been given up after unrecoverable errors.
+/// While it is set, the LEDs are off and no readings are requested.
+pub(crate) static SAFE_STATE: AtomicBool = AtomicBool::new(false);
+
+/// This is a flag that is set to true when the frontend has been reinitialised, until the processing task has
+/// restarted the measurement.
+pub(crate) static FRONTEND_REINITIALISED: AtomicBool = AtomicBool::new(false);
+
+/// Initialises the `FRONTEND` with default values.
+/// The Bluetooth characteristics are attached and advertised even if the frontend cannot be set up, so that the
+/// error count can still be read.
+pub(crate) fn initialise<P: Pin>(
+    i2c: I2cDriver<'static>,
+    interrupt_pin: &mut PinDriver<P, Input>,
+    ble_api: Arc<RwLock<BluetoothAPI>>,
+    offset_currents: &mut OffsetCurrents,
+) -> Result<(), FirmwareError> {
+    // Interrupt pin.
+    interrupt_pin.set_interrupt_type(esp_idf_hal::gpio::InterruptType::PosEdge)?;
+
+    unsafe {
+        interrupt_pin.subscribe(data_reading::on_data_ready)?;
+    }
+
+    // Frontend.
+    *FRONTEND
+        .lock()
+        .map_err(|_| FirmwareError::Frontend(FrontendError::Poisoned))? = Some(
+        AFE4404::with_three_leds(i2c, 0x58u8, Frequency::new::<megahertz>(4.0)),
+    );
+
+    let mut attempts = 0;
+    let result = loop {
+        attempts += 1;
+        match set_up_frontend(offset_currents) {
+            Ok(()) => break Ok(()),
+            Err(e) if attempts < FirmwareError::MAX_RETRIES => e.report(),
+            Err(e) => break Err(e),
+        }
+    };
+
+    // Bluetooth.
+    let mut ble_api = ble_api
+        .write()
+        .map_err(|_| FirmwareError::Bluetooth("API poisoned."))?;
+    crate::optical::char_control::attach_optical_frontend_chars(&FRONTEND, &mut ble_api);
+    crate::optical::char_control::attach_optical_calibration_chars(
+        &CALIBRATOR_LED1,
+        &CALIBRATOR_LED2_LED3,
+        &mut ble_api,
+    );
+
+    ble_api.start();
+
+    result
+}
+
+/// Configures the `FRONTEND`, creates the calibrators and measures the accurate offset currents.
+fn set_up_frontend(offset_currents: &mut OffsetCurrents) -> Result<(), FirmwareError> {
+    with_frontend(&FRONTEND, frontend::configure)?;
+
+    // Calibration.
+    let (calibrator_led1, calibrator_led2_led3) = calibration::calibrators(&FRONTEND)?;
+    *CALIBRATOR_LED1
+        .lock()
+        .map_err(|_| FirmwareError::Frontend(FrontendError::Poisoned))? = Some(calibrator_led1);
+    *CALIBRATOR_LED2_LED3
+        .lock()
+        .map_err(|_| FirmwareError::Frontend(FrontendError::Poisoned))? =
+        Some(calibrator_led2_led3);
+
+    // Measure accurate offset currents.
+    with_frontend(&FRONTEND, |frontend| offset_currents.measure(frontend))?;
+    log::info!(
+        "{}{}",
+        pulse_loop_core::recording::OFFSET_CURRENTS_MARKER,
+        pulse_loop_core::recording::offset_currents_to_csv(offset_currents)
+    );
+
+    Ok(())
+}
+
+/// Resets and configures the `FRONTEND` again, leaving the LEDs off, and resets the calibrators to its currents.
+/// The measurement restarts from the calibration, or from the wrist check if the wrist is not detected with the
+/// LEDs off.
+pub(crate) fn reinitialise_frontend() -> Result<(), FirmwareError> {
+    log::warn!("Reinitialising the frontend.");
+    with_frontend(&FRONTEND, frontend::configure)?;
+
+    // The reset has cleared the offset currents cached by the calibrators.
+    with_calibrator(&CALIBRATOR_LED1, Calibrator::reset)?;
+    with_calibrator(&CALIBRATOR_LED2_LED3, Calibrator::reset)?;
+    FRONTEND_REINITIALISED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Turns off the LEDs, even if the `FRONTEND` mutex has been poisoned, and stops requesting readings.
+pub(crate) fn enter_safe_state() {
+    log::error!("Entering the safe state.");
+    SAFE_STATE.store(true, Ordering::Relaxed);
+
+    let mut frontend = FRONTEND
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(frontend) = frontend.as_mut() {
+        if let Err(e) = turn_off_leds(frontend) {
+            log::error!("Cannot turn off the LEDs: {:?}", e);
+        }
+    }
+}
+
+/// Reports the error and applies its recovery policy.
+pub(crate) fn recover(error: FirmwareError, consecutive_errors: u32) {
+    error.report();
+
+    match error.recovery_policy(consecutive_errors) {
+        RecoveryPolicy::Retry => {}
+        RecoveryPolicy::ReinitialiseFrontend => {
+            if let Err(e) = reinitialise_frontend() {
+                e.report();
+            }
+        }
+        RecoveryPolicy::SafeState => enter_safe_state(),
+    }
+}
+
+/// Locks the calibrator and calls the given operation on it.
+pub(crate) fn with_calibrator<T>(
+    calibrator: &Mutex<Option<Calibrator>>,
+    operation: impl FnOnce(&mut Calibrator) -> Result<T, FrontendError>,
+) -> Result<T, FrontendError> {
+    let mut calibrator = calibrator.lock().map_err(|_| FrontendError::Poisoned)?;
+    let calibrator = calibrator.as_mut().ok_or(FrontendError::NotInitialised)?;
+
+    operation(calibrator)
+}
+
+fn turn_off_leds<F: frontend::OpticalFrontend>(frontend: &mut F) -> Result<(), F::Error> {
+    frontend.set_leds_current(&LedCurrentConfiguration::<ThreeLedsMode>::new(
+        ElectricCurrent::new::<milliampere>(0.0),
+        ElectricCurrent::new::<milliampere>(0.0),
+        ElectricCurrent::new::<milliampere>(0.0),
+    ))?;
+
+    Ok(())
+}
+
+/// Performs the actions requested by the measurement state machine on the `FRONTEND`.
+pub(crate) fn perform_frontend_actions(actions: &[FrontendAction]) -> Result<(), FirmwareError> {
+    for action in actions {
+        match action {
+            FrontendAction::TurnOffLeds => {
+                with_frontend(&FRONTEND, turn_off_leds)?;
+            }
+            FrontendAction::TurnOnWristCheckLed => {
+                // Turn on the IR LED and set the offset current.
+                let (ir_max_current, ir_min_offset_current) =
+                    with_calibrator(&CALIBRATOR_LED2_LED3, |ir_calibrator| {
+                        ir_calibrator.offset_current = *ir_calibrator.offset_current_min();
+                        Ok((
+                            *ir_calibrator.led_current_max(),
+                            *ir_calibrator.offset_current_min(),
+                        ))
+                    })?;
+                with_frontend(&FRONTEND, |frontend| {
+                    frontend.set_led3_current(ir_max_current)?;
+                    frontend.set_offset_led3_current(ir_min_offset_current)
+                })?;
+            }
+            FrontendAction::ResetCalibration => {
+                with_calibrator(&CALIBRATOR_LED1, Calibrator::reset)?;
+                with_calibrator(&CALIBRATOR_LED2_LED3, Calibrator::reset)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,530 @@
+use std::{
+    convert::TryFrom,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+use embedded_svc::storage::RawStorage;
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+
+use uom::si::{
+    f32::Time,
+    time::{millisecond, second},
+};
+
+use pulse_loop_core::{
+    clock::Clock,
+    pipeline::{HeartRateMethod, VitalSignsPipeline},
+    signal_processing::{
+        averaging::Averaging,
+        hrv::SpectralHrvAnalyser,
+        spo2_calibration::{Spo2Calibration, WearSite},
+    },
+};
+
+use crate::{
+    bluetooth::BluetoothAPI,
+    error::{FirmwareError, StorageError},
+};
+
+/// The NVS namespace of the settings.
+const NAMESPACE: &str = "settings";
+const SPO2_CALIBRATION_PRESET_KEY: &str = "spo2_preset";
+const SPO2_CALIBRATION_CURVE_KEY: &str = "spo2_curve";
+const AVERAGING_KEY: &str = "averaging";
+const HEART_RATE_METHOD_KEY: &str = "hr_method";
+const SPECTRAL_HRV_KEY: &str = "spectral_hrv";
+/// The serialised preset of a custom curve.
+const CUSTOM_PRESET: [u8; 2] = [0xFF, 0xFF];
+
+/// The SpO2 calibration curve in use, with the preset it comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Spo2CalibrationSetting {
+    /// The hardware revision and the wear site of the preset, or `None` if the curve has been written directly.
+    pub(crate) preset: Option<(u8, WearSite)>,
+    pub(crate) curve: Spo2Calibration,
+}
+
+impl Spo2CalibrationSetting {
+    fn serialise_preset(&self) -> [u8; 2] {
+        self.preset.map_or(CUSTOM_PRESET, |(revision, site)| {
+            [revision, site.serialise()]
+        })
+    }
+}
+
+impl Default for Spo2CalibrationSetting {
+    fn default() -> Self {
+        Self {
+            preset: Some((Spo2Calibration::HARDWARE_REVISION, WearSite::default())),
+            curve: Spo2Calibration::default(),
+        }
+    }
+}
+
+/// The window and the period of the frequency-domain heart rate variability metrics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SpectralHrvSetting {
+    pub(crate) window: Time,
+    pub(crate) period: Time,
+}
+
+impl SpectralHrvSetting {
+    /// Serialises the window and the period in seconds, as little-endian `u16`.
+    fn serialise(&self) -> [u8; 4] {
+        let mut data = [0; 4];
+
+        data[0..2].copy_from_slice(&(self.window.get::<second>().round() as u16).to_le_bytes());
+        data[2..4].copy_from_slice(&(self.period.get::<second>().round() as u16).to_le_bytes());
+
+        data
+    }
+
+    /// Reads a setting serialised with [`SpectralHrvSetting::serialise`]. Returns `None` if the window is not
+    /// between 2 and 5 minutes or the period is 0.
+    fn deserialise(data: &[u8]) -> Option<Self> {
+        match data {
+            [window_low, window_high, period_low, period_high, ..] => {
+                let window = u16::from_le_bytes([*window_low, *window_high]) as u128 * 1000;
+                let period = u16::from_le_bytes([*period_low, *period_high]);
+                if !(SpectralHrvAnalyser::MIN_WINDOW..=SpectralHrvAnalyser::MAX_WINDOW)
+                    .contains(&window)
+                    || period == 0
+                {
+                    return None;
+                }
+
+                Some(Self {
+                    window: Time::new::<millisecond>(window as f32),
+                    period: Time::new::<second>(period as f32),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for SpectralHrvSetting {
+    fn default() -> Self {
+        Self {
+            window: Time::new::<second>(300.0),
+            period: Time::new::<second>(30.0),
+        }
+    }
+}
+
+/// A setting written by the application, sent to the processing task so that it is applied to the pipeline
+/// between two samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SettingChange {
+    Spo2Calibration(Spo2Calibration),
+    Averaging(Averaging),
+    HeartRateMethod(HeartRateMethod),
+    SpectralHrv(SpectralHrvSetting),
+}
+
+impl SettingChange {
+    /// Applies the setting to the pipeline.
+    pub(crate) fn apply<C: Clock + Clone>(self, pipeline: &mut VitalSignsPipeline<C>) {
+        match self {
+            Self::Spo2Calibration(curve) => pipeline.set_spo2_calibration(curve),
+            Self::Averaging(averaging) => pipeline.set_averaging(averaging),
+            Self::HeartRateMethod(heart_rate_method) => {
+                pipeline.set_heart_rate_method(heart_rate_method)
+            }
+            Self::SpectralHrv(setting) => {
+                pipeline.set_spectral_hrv_window(setting.window);
+                pipeline.set_spectral_hrv_period(setting.period);
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub(crate) static ref SPO2_CALIBRATION: Mutex<Spo2CalibrationSetting> = Mutex::new(Spo2CalibrationSetting::default());
+    pub(crate) static ref AVERAGING: Mutex<Averaging> = Mutex::new(Averaging::default());
+    pub(crate) static ref HEART_RATE_METHOD: Mutex<HeartRateMethod> = Mutex::new(HeartRateMethod::default());
+    pub(crate) static ref SPECTRAL_HRV: Mutex<SpectralHrvSetting> = Mutex::new(SpectralHrvSetting::default());
+    static ref NVS: Mutex<Option<EspDefaultNvs>> = Mutex::new(None);
+    static ref SETTING_CHANGES: (Mutex<Sender<SettingChange>>, Mutex<Receiver<SettingChange>>) = {
+        let (sender, receiver) = mpsc::channel();
+        (Mutex::new(sender), Mutex::new(receiver))
+    };
+}
+
+/// Opens the settings in the NVS and loads the stored ones. The settings that have not been stored, or that cannot
+/// be read, keep their default value.
+pub(crate) fn initialise() -> Result<(), FirmwareError> {
+    let partition = EspDefaultNvsPartition::take().map_err(StorageError::Nvs)?;
+    let nvs = EspDefaultNvs::new(partition, NAMESPACE, true).map_err(StorageError::Nvs)?;
+    // The NVS is kept before loading, so that the settings can still be stored if one of them cannot be read.
+    *NVS.lock().map_err(|_| StorageError::Poisoned("NVS"))? = Some(nvs);
+
+    let preset = load(SPO2_CALIBRATION_PRESET_KEY, |data| {
+        preset_from_bytes(data).ok()
+    });
+    let curve = load(SPO2_CALIBRATION_CURVE_KEY, Spo2Calibration::deserialise);
+    if let (Some(preset), Some(curve)) = (preset, curve) {
+        log::info!("Loaded the SpO2 calibration: {:?}, {:?}", preset, curve);
+        *SPO2_CALIBRATION
+            .lock()
+            .map_err(|_| StorageError::Poisoned("SpO2 calibration"))? =
+            Spo2CalibrationSetting { preset, curve };
+    }
+
+    if let Some(averaging) = load(AVERAGING_KEY, Averaging::deserialise) {
+        log::info!("Loaded the averaging: {:?}", averaging);
+        *AVERAGING
+            .lock()
+            .map_err(|_| StorageError::Poisoned("Averaging"))? = averaging;
+    }
+
+    if let Some(heart_rate_method) = load(HEART_RATE_METHOD_KEY, |data| {
+        HeartRateMethod::try_from(*data.first()?).ok()
+    }) {
+        log::info!("Loaded the heart rate method: {:?}", heart_rate_method);
+        *HEART_RATE_METHOD
+            .lock()
+            .map_err(|_| StorageError::Poisoned("Heart rate method"))? = heart_rate_method;
+    }
+
+    if let Some(spectral_hrv) = load(SPECTRAL_HRV_KEY, SpectralHrvSetting::deserialise) {
+        log::info!("Loaded the spectral HRV: {:?}", spectral_hrv);
+        *SPECTRAL_HRV
+            .lock()
+            .map_err(|_| StorageError::Poisoned("Spectral HRV"))? = spectral_hrv;
+    }
+
+    Ok(())
+}
+
+/// Loads a serialised setting from the NVS. Returns `None` if it has not been stored or cannot be parsed, and
+/// reports the error if it cannot be read, so that the other settings are still loaded.
+fn load<T>(key: &str, parse: impl FnOnce(&[u8]) -> Option<T>) -> Option<T> {
+    let read = || -> Result<Option<T>, FirmwareError> {
+        let nvs = NVS.lock().map_err(|_| StorageError::Poisoned("NVS"))?;
+        let mut buffer = [0; 12];
+        let data = nvs
+            .as_ref()
+            .ok_or(StorageError::NotInitialised)?
+            .get_raw(key, &mut buffer)
+            .map_err(StorageError::Nvs)?;
+
+        Ok(data.and_then(parse))
+    };
+
+    read().unwrap_or_else(|e| {
+        log::warn!(
+            "Could not load the setting {}, keeping its default value.",
+            key
+        );
+        e.report();
+        None
+    })
+}
+
+/// Gets the SpO2 calibration curve in use.
+pub(crate) fn spo2_calibration() -> Result<Spo2Calibration, FirmwareError> {
+    Ok(SPO2_CALIBRATION
+        .lock()
+        .map_err(|_| StorageError::Poisoned("SpO2 calibration"))?
+        .curve)
+}
+
+/// Gets the averaging of the SpO2 and of the heart rate.
+pub(crate) fn averaging() -> Result<Averaging, FirmwareError> {
+    Ok(*AVERAGING
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Averaging"))?)
+}
+
+/// Gets the method used to compute the heart rate.
+pub(crate) fn heart_rate_method() -> Result<HeartRateMethod, FirmwareError> {
+    Ok(*HEART_RATE_METHOD
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Heart rate method"))?)
+}
+
+/// Gets the window and the period of the frequency-domain heart rate variability metrics.
+pub(crate) fn spectral_hrv() -> Result<SpectralHrvSetting, FirmwareError> {
+    Ok(*SPECTRAL_HRV
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Spectral HRV"))?)
+}
+
+/// Applies all the settings in use to the pipeline, e.g. when it has just been created.
+pub(crate) fn configure<C: Clock + Clone>(
+    pipeline: &mut VitalSignsPipeline<C>,
+) -> Result<(), FirmwareError> {
+    SettingChange::Spo2Calibration(spo2_calibration()?).apply(pipeline);
+    SettingChange::Averaging(averaging()?).apply(pipeline);
+    SettingChange::HeartRateMethod(heart_rate_method()?).apply(pipeline);
+    SettingChange::SpectralHrv(spectral_hrv()?).apply(pipeline);
+
+    Ok(())
+}
+
+/// Gets the receiver of the settings written by the application. It is locked by the processing task for its
+/// whole life, and taken over when restarted.
+pub(crate) fn setting_changes() -> &'static Mutex<Receiver<SettingChange>> {
+    &SETTING_CHANGES.1
+}
+
+/// Sends a setting to the processing task.
+fn send(change: SettingChange) -> Result<(), FirmwareError> {
+    SETTING_CHANGES
+        .0
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Setting changes"))?
+        .send(change)
+        // The receiver is static, so it is never dropped.
+        .expect("The receiver of the setting changes has been dropped.");
+
+    Ok(())
+}
+
+/// Sets the SpO2 calibration curve in use, sends it to the processing task and stores it in the NVS.
+fn set_spo2_calibration(setting: Spo2CalibrationSetting) -> Result<(), FirmwareError> {
+    *SPO2_CALIBRATION
+        .lock()
+        .map_err(|_| StorageError::Poisoned("SpO2 calibration"))? = setting;
+    send(SettingChange::Spo2Calibration(setting.curve))?;
+
+    store(SPO2_CALIBRATION_PRESET_KEY, &setting.serialise_preset())?;
+    store(SPO2_CALIBRATION_CURVE_KEY, &setting.curve.serialise())
+}
+
+/// Sets the averaging of the SpO2 and of the heart rate, sends it to the processing task and stores it in the NVS.
+fn set_averaging(averaging: Averaging) -> Result<(), FirmwareError> {
+    *AVERAGING
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Averaging"))? = averaging;
+    send(SettingChange::Averaging(averaging))?;
+
+    store(AVERAGING_KEY, &averaging.serialise())
+}
+
+/// Sets the method used to compute the heart rate, sends it to the processing task and stores it in the NVS.
+fn set_heart_rate_method(heart_rate_method: HeartRateMethod) -> Result<(), FirmwareError> {
+    *HEART_RATE_METHOD
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Heart rate method"))? = heart_rate_method;
+    send(SettingChange::HeartRateMethod(heart_rate_method))?;
+
+    store(HEART_RATE_METHOD_KEY, &[heart_rate_method.serialise()])
+}
+
+/// Sets the window and the period of the frequency-domain heart rate variability metrics, sends them to the
+/// processing task and stores them in the NVS.
+fn set_spectral_hrv(setting: SpectralHrvSetting) -> Result<(), FirmwareError> {
+    *SPECTRAL_HRV
+        .lock()
+        .map_err(|_| StorageError::Poisoned("Spectral HRV"))? = setting;
+    send(SettingChange::SpectralHrv(setting))?;
+
+    store(SPECTRAL_HRV_KEY, &setting.serialise())
+}
+
+/// Stores a serialised setting in the NVS.
+fn store(key: &str, data: &[u8]) -> Result<(), FirmwareError> {
+    let mut nvs = NVS.lock().map_err(|_| StorageError::Poisoned("NVS"))?;
+    // The setting is used anyway if the NVS could not be opened, but it is lost at the next boot.
+    nvs.as_mut()
+        .ok_or(StorageError::NotInitialised)?
+        .set_raw(key, data)
+        .map_err(StorageError::Nvs)?;
+
+    Ok(())
+}
+
+/// Reads a preset serialised as hardware revision and wear site, `None` being a custom curve.
+fn preset_from_bytes(data: &[u8]) -> Result<Option<(u8, WearSite)>, ()> {
+    match data {
+        [0xFF, 0xFF, ..] => Ok(None),
+        [revision, site, ..] => Ok(Some((*revision, WearSite::try_from(*site)?))),
+        _ => Err(()),
+    }
+}
+
+/// Attaches the settings to their characteristics.
+pub(crate) fn attach_settings_chars(ble_api: &mut BluetoothAPI) {
+    log::info!("Attaching the SpO2 calibration preset.");
+    ble_api
+        .settings
+        .spo2_calibration_preset_characteristic
+        .write()
+        .unwrap()
+        .on_write(|value, _| {
+            let preset = match preset_from_bytes(&value) {
+                Ok(Some(preset)) => preset,
+                _ => {
+                    log::error!("Invalid value for the SpO2 calibration preset: {:?}", value);
+                    return;
+                }
+            };
+            let curve = match Spo2Calibration::preset(preset.0, preset.1) {
+                Some(curve) => curve,
+                None => {
+                    log::error!("There is no SpO2 calibration preset for {:?}.", preset);
+                    return;
+                }
+            };
+
+            log::info!("Setting the SpO2 calibration to {:?}: {:?}", preset, curve);
+            if let Err(e) = set_spo2_calibration(Spo2CalibrationSetting {
+                preset: Some(preset),
+                curve,
+            }) {
+                e.report();
+            }
+        });
+    ble_api
+        .settings
+        .spo2_calibration_preset_characteristic
+        .write()
+        .unwrap()
+        .on_read(|_| match SPO2_CALIBRATION.lock() {
+            Ok(setting) => setting.serialise_preset().to_vec(),
+            Err(_) => {
+                FirmwareError::Storage(StorageError::Poisoned("SpO2 calibration")).report();
+                vec![]
+            }
+        });
+
+    log::info!("Attaching the SpO2 calibration curve.");
+    ble_api
+        .settings
+        .spo2_calibration_curve_characteristic
+        .write()
+        .unwrap()
+        .on_write(|value, _| {
+            let curve = match Spo2Calibration::deserialise(&value) {
+                Some(curve) => curve,
+                None => {
+                    log::error!("Invalid value for the SpO2 calibration curve: {:?}", value);
+                    return;
+                }
+            };
+
+            log::info!("Setting the SpO2 calibration to {:?}", curve);
+            if let Err(e) = set_spo2_calibration(Spo2CalibrationSetting {
+                preset: None,
+                curve,
+            }) {
+                e.report();
+            }
+        });
+    ble_api
+        .settings
+        .spo2_calibration_curve_characteristic
+        .write()
+        .unwrap()
+        .on_read(|_| match spo2_calibration() {
+            Ok(curve) => curve.serialise().to_vec(),
+            Err(e) => {
+                e.report();
+                vec![]
+            }
+        });
+    log::info!("Attaching the averaging.");
+    ble_api
+        .settings
+        .averaging_characteristic
+        .write()
+        .unwrap()
+        .on_write(|value, _| {
+            let averaging = match Averaging::deserialise(&value) {
+                Some(averaging) => averaging,
+                None => {
+                    log::error!("Invalid value for the averaging: {:?}", value);
+                    return;
+                }
+            };
+
+            log::info!("Setting the averaging to {:?}", averaging);
+            if let Err(e) = set_averaging(averaging) {
+                e.report();
+            }
+        });
+    ble_api
+        .settings
+        .averaging_characteristic
+        .write()
+        .unwrap()
+        .on_read(|_| match averaging() {
+            Ok(averaging) => averaging.serialise().to_vec(),
+            Err(e) => {
+                e.report();
+                vec![]
+            }
+        });
+
+    log::info!("Attaching the heart rate method.");
+    ble_api
+        .settings
+        .heart_rate_method_characteristic
+        .write()
+        .unwrap()
+        .on_write(|value, _| {
+            let heart_rate_method =
+                match value.first().map(|&value| HeartRateMethod::try_from(value)) {
+                    Some(Ok(heart_rate_method)) => heart_rate_method,
+                    _ => {
+                        log::error!("Invalid value for the heart rate method: {:?}", value);
+                        return;
+                    }
+                };
+
+            log::info!("Setting the heart rate method to {:?}", heart_rate_method);
+            if let Err(e) = set_heart_rate_method(heart_rate_method) {
+                e.report();
+            }
+        });
+    ble_api
+        .settings
+        .heart_rate_method_characteristic
+        .write()
+        .unwrap()
+        .on_read(|_| match heart_rate_method() {
+            Ok(heart_rate_method) => vec![heart_rate_method.serialise()],
+            Err(e) => {
+                e.report();
+                vec![]
+            }
+        });
+
+    log::info!("Attaching the spectral HRV.");
+    ble_api
+        .settings
+        .spectral_hrv_characteristic
+        .write()
+        .unwrap()
+        .on_write(|value, _| {
+            let setting = match SpectralHrvSetting::deserialise(&value) {
+                Some(setting) => setting,
+                None => {
+                    log::error!("Invalid value for the spectral HRV: {:?}", value);
+                    return;
+                }
+            };
+
+            log::info!("Setting the spectral HRV to {:?}", setting);
+            if let Err(e) = set_spectral_hrv(setting) {
+                e.report();
+            }
+        });
+    ble_api
+        .settings
+        .spectral_hrv_characteristic
+        .write()
+        .unwrap()
+        .on_read(|_| match spectral_hrv() {
+            Ok(setting) => setting.serialise().to_vec(),
+            Err(e) => {
+                e.report();
+                vec![]
+            }
+        });
+}
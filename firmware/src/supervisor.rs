@@ -0,0 +1,328 @@
+use std::{
+    any::Any,
+    fmt::{self, Display, Formatter},
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use esp_idf_sys::{
+    esp, esp_reset_reason, esp_reset_reason_t_ESP_RST_INT_WDT, esp_reset_reason_t_ESP_RST_PANIC,
+    esp_reset_reason_t_ESP_RST_TASK_WDT, esp_task_wdt_add, esp_task_wdt_delete, esp_task_wdt_reset,
+};
+use pulse_loop_core::clock::{Clock, MonotonicClock};
+
+use crate::error::FirmwareError;
+
+lazy_static::lazy_static! {
+    /// The description of the last fault, exposed over BLE.
+    pub(crate) static ref LAST_FAULT: Mutex<String> = Mutex::new(String::new());
+}
+
+/// The value of `PersistedFault::magic` when a fault has been persisted.
+const PERSISTED_FAULT_MAGIC: u32 = 0x4641_554C;
+
+/// A fault description kept in the RTC memory, so that it survives a watchdog or panic reset.
+#[repr(C)]
+struct PersistedFault {
+    magic: u32,
+    length: u8,
+    description: [u8; 59],
+}
+
+// The RTC memory is not initialised at boot, so the initial value is only used after a power-on reset.
+#[link_section = ".rtc_noinit"]
+static mut PERSISTED_FAULT: PersistedFault = PersistedFault {
+    magic: 0,
+    length: 0,
+    description: [0; 59],
+};
+
+/// Why a task has been restarted or the device has been reset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum FaultCause {
+    /// The task panicked with the given message, which aborts and resets the device.
+    Panicked(String),
+    /// The task returned, while it should run forever.
+    Exited,
+    /// The task did not send a heartbeat in time.
+    Stalled,
+}
+
+/// A fault of a supervised task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Fault {
+    pub(crate) task: String,
+    pub(crate) cause: FaultCause,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.cause {
+            FaultCause::Panicked(message) => write!(f, "{} panicked: {}", self.task, message),
+            FaultCause::Exited => write!(f, "{} exited", self.task),
+            FaultCause::Stalled => write!(f, "{} stalled", self.task),
+        }
+    }
+}
+
+/// A handle used by a supervised task to signal that it is still running.
+#[derive(Debug, Clone)]
+pub(crate) struct Heartbeat {
+    last_beat: Arc<AtomicU64>,
+    clock: MonotonicClock,
+}
+
+impl Heartbeat {
+    fn new(clock: MonotonicClock) -> Self {
+        Self {
+            last_beat: Arc::new(AtomicU64::new(clock.now() as u64)),
+            clock,
+        }
+    }
+
+    /// Signals that the task is still running and feeds the task watchdog.
+    /// It must be called from the supervised task.
+    pub(crate) fn beat(&self) {
+        self.last_beat
+            .store(self.clock.now() as u64, Ordering::Relaxed);
+        unsafe {
+            esp_task_wdt_reset();
+        }
+    }
+
+    /// Gets the milliseconds elapsed since the last beat.
+    fn elapsed(&self) -> u128 {
+        self.clock
+            .now()
+            .saturating_sub(self.last_beat.load(Ordering::Relaxed) as u128)
+    }
+}
+
+/// Removes the current task from the task watchdog when dropped, i.e. when the task exits.
+struct WatchdogRegistration;
+
+impl WatchdogRegistration {
+    fn new() -> Result<Self, FirmwareError> {
+        esp!(unsafe { esp_task_wdt_add(ptr::null_mut()) })?;
+
+        Ok(Self)
+    }
+}
+
+impl Drop for WatchdogRegistration {
+    fn drop(&mut self) {
+        unsafe {
+            esp_task_wdt_delete(ptr::null_mut());
+        }
+    }
+}
+
+type TaskFactory = Arc<dyn Fn(Heartbeat) + Send + Sync>;
+
+struct SupervisedTask {
+    name: &'static str,
+    stack_size: usize,
+    factory: TaskFactory,
+    heartbeat: Heartbeat,
+    handle: Option<JoinHandle<()>>,
+    stalled: bool,
+}
+
+/// Starts the tasks, monitors their heartbeats and restarts them when they exit.
+///
+/// A task that exits is restarted with fresh state, since the factory is called again.
+/// The firmware is built with `panic = "abort"`, so a task that panics cannot be restarted: the panic hook persists
+/// the fault and the device is reset. A stalled task cannot be stopped either, so the fault is persisted and the task
+/// watchdog resets the device. The persisted fault is exposed at the next boot.
+pub(crate) struct Supervisor {
+    clock: MonotonicClock,
+    tasks: Vec<SupervisedTask>,
+}
+
+impl Supervisor {
+    /// The time without heartbeats after which a task is considered stalled, in milliseconds.
+    /// It must be shorter than the task watchdog timeout, so that the fault is persisted before the reset.
+    pub(crate) const STALL_TIMEOUT: u128 = 2000;
+    /// The period of the supervision.
+    pub(crate) const SUPERVISION_PERIOD: Duration = Duration::from_millis(500);
+
+    /// Creates a new `Supervisor`, loading the fault that caused the last reset, if any.
+    /// A panic hook is installed, so that the panics are recorded before they abort.
+    pub(crate) fn new() -> Self {
+        load_persisted_fault();
+
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            record(Fault {
+                task: thread::current().name().unwrap_or("main").to_string(),
+                cause: FaultCause::Panicked(panic_message(info.payload())),
+            });
+            default_hook(info);
+        }));
+
+        Self {
+            clock: MonotonicClock::new(),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Starts a supervised task in a new thread. The factory is called every time the task is (re)started,
+    /// and it must call [`Heartbeat::beat`] at least every [`Supervisor::STALL_TIMEOUT`].
+    pub(crate) fn spawn<T>(&mut self, name: &'static str, stack_size: usize, factory: T)
+    where
+        T: Fn(Heartbeat) + Send + Sync + 'static,
+    {
+        let mut task = SupervisedTask {
+            name,
+            stack_size,
+            factory: Arc::new(factory),
+            heartbeat: Heartbeat::new(self.clock),
+            handle: None,
+            stalled: false,
+        };
+        start(&mut task, self.clock);
+        self.tasks.push(task);
+    }
+
+    /// Checks every task once, restarting the exited ones.
+    pub(crate) fn supervise(&mut self) {
+        for task in self.tasks.iter_mut() {
+            let finished = match &task.handle {
+                Some(handle) => handle.is_finished(),
+                None => true,
+            };
+
+            if finished {
+                // A panic aborts, so a finished task has returned.
+                task.handle = None;
+                record(Fault {
+                    task: task.name.to_string(),
+                    cause: FaultCause::Exited,
+                });
+                start(task, self.clock);
+            } else if !task.stalled && task.heartbeat.elapsed() > Self::STALL_TIMEOUT {
+                task.stalled = true;
+                record(Fault {
+                    task: task.name.to_string(),
+                    cause: FaultCause::Stalled,
+                });
+            }
+        }
+    }
+}
+
+/// Starts the task with a fresh heartbeat.
+fn start(task: &mut SupervisedTask, clock: MonotonicClock) {
+    let heartbeat = Heartbeat::new(clock);
+    let factory = task.factory.clone();
+    let heartbeat_for_task = heartbeat.clone();
+    let name = task.name;
+
+    let result = thread::Builder::new()
+        .name(name.to_string())
+        .stack_size(task.stack_size)
+        .spawn(move || {
+            let _registration = match WatchdogRegistration::new() {
+                Ok(registration) => Some(registration),
+                Err(e) => {
+                    log::error!("Cannot register {} with the task watchdog.", name);
+                    e.report();
+                    None
+                }
+            };
+
+            factory(heartbeat_for_task);
+        });
+
+    match result {
+        Ok(handle) => {
+            log::info!("Started {}.", name);
+            task.handle = Some(handle);
+        }
+        Err(e) => {
+            // It will be started again at the next supervision.
+            log::error!("Cannot start {}: {}", name, e);
+            task.handle = None;
+        }
+    }
+    task.heartbeat = heartbeat;
+    task.stalled = false;
+}
+
+/// Logs the fault, exposes it over BLE and persists it in the RTC memory.
+fn record(fault: Fault) {
+    let description = fault.to_string();
+    log::error!("Task fault: {}", description);
+
+    if let Ok(mut last_fault) = LAST_FAULT.lock() {
+        *last_fault = description.clone();
+    }
+
+    // Truncate the description at a character boundary.
+    let mut length = description.len().min(59);
+    while !description.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    unsafe {
+        let persisted = &mut *ptr::addr_of_mut!(PERSISTED_FAULT);
+        persisted.description[..length].copy_from_slice(&description.as_bytes()[..length]);
+        persisted.length = length as u8;
+        persisted.magic = PERSISTED_FAULT_MAGIC;
+    }
+}
+
+/// Loads the persisted fault into `LAST_FAULT`, adding the reset reason if the device has been reset by a fault.
+/// The persisted fault is then cleared, so that it is not reported again at the following boots.
+fn load_persisted_fault() {
+    let reset = match unsafe { esp_reset_reason() } {
+        reason if reason == esp_reset_reason_t_ESP_RST_TASK_WDT => Some("task watchdog reset"),
+        reason if reason == esp_reset_reason_t_ESP_RST_INT_WDT => Some("interrupt watchdog reset"),
+        reason if reason == esp_reset_reason_t_ESP_RST_PANIC => Some("panic reset"),
+        _ => None,
+    };
+
+    let persisted = unsafe {
+        let persisted = &mut *ptr::addr_of_mut!(PERSISTED_FAULT);
+        let description =
+            if persisted.magic == PERSISTED_FAULT_MAGIC && persisted.length as usize <= 59 {
+                Some(
+                    String::from_utf8_lossy(&persisted.description[..persisted.length as usize])
+                        .into_owned(),
+                )
+            } else {
+                None
+            };
+        persisted.magic = 0;
+
+        description
+    };
+
+    let description = match (persisted, reset) {
+        (Some(fault), Some(reset)) => format!("{}, {}", fault, reset),
+        (Some(fault), None) => fault,
+        (None, Some(reset)) => reset.to_string(),
+        (None, None) => return,
+    };
+    log::warn!("Last fault: {}", description);
+
+    if let Ok(mut last_fault) = LAST_FAULT.lock() {
+        *last_fault = description;
+    }
+}
+
+/// Extracts the message of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown".to_string()
+    }
+}
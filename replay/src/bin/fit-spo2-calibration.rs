@@ -0,0 +1,86 @@
+//! Fits the SpO2 calibration curves to paired measurements.
+//!
+//! Usage: `fit-spo2-calibration <pairs>`
+//!
+//! The pairs are read from a CSV file with the R value measured by the pulse.loop and the SpO2 of a reference
+//! oximeter, in percent, on each line. The lines that do not start with a number, like the header, are skipped.
+//! The linear and quadratic curves are printed with their RMS error and the bytes to write to the SpO2 calibration
+//! curve characteristic.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    process,
+};
+
+use pulse_loop_core::signal_processing::spo2_calibration::Spo2Calibration;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: {} <pairs>", args[0]);
+        process::exit(1);
+    }
+
+    let pairs = match read_pairs(&args[1]) {
+        Ok(pairs) => pairs,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+    };
+    println!("Read {} pairs.", pairs.len());
+
+    for (name, calibration) in [
+        ("Linear", Spo2Calibration::fit_linear(&pairs)),
+        ("Quadratic", Spo2Calibration::fit_quadratic(&pairs)),
+    ] {
+        match calibration {
+            Some(calibration) => {
+                let bytes: Vec<String> = calibration
+                    .serialise()
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                println!(
+                    "{}: SpO2 = {} * R^2 + {} * R + {}, RMS error {} %, bytes {}",
+                    name,
+                    calibration.quadratic,
+                    calibration.linear,
+                    calibration.constant,
+                    calibration.rms_error(&pairs),
+                    bytes.join("")
+                );
+            }
+            None => println!("{}: not enough distinct R values.", name),
+        }
+    }
+}
+
+/// Reads the pairs of R and reference SpO2 from the CSV file.
+fn read_pairs(path: &str) -> Result<Vec<(f32, f32)>, Box<dyn std::error::Error>> {
+    let mut pairs = Vec::new();
+
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if !line.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != 2 {
+            return Err(
+                format!("line {}: expected 2 fields, found {}", i + 1, fields.len()).into(),
+            );
+        }
+        let r = fields[0]
+            .parse::<f32>()
+            .map_err(|e| format!("line {}: invalid R '{}': {}", i + 1, fields[0], e))?;
+        let spo2 = fields[1]
+            .parse::<f32>()
+            .map_err(|e| format!("line {}: invalid SpO2 '{}': {}", i + 1, fields[1], e))?;
+        pairs.push((r, spo2));
+    }
+
+    Ok(pairs)
+}
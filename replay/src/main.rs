@@ -0,0 +1,382 @@
+//! Replays a recorded session through the same pipeline used by the firmware.
+//!
+//! Usage: `pulse-loop-replay [--heart-rate-method <peak|autocorrelation|spectral|fusion>] [--hrv-window <seconds>] [--spectral-hrv-window <seconds>] [--spectral-hrv-period <seconds>] [--perfusion-index-method <rms|peak-to-peak>] [--wear-site <wrist|finger>] [--averaging-method <mean|median|exponential>] [--averaging-window <seconds>] <recording> <samples output> <beats output>`
+//!
+//! The recording is read as binary if its extension is `.bin`, as CSV or firmware log otherwise
+//! (see `pulse_loop_core::recording`). The samples output contains one line per processed sample, while the beats
+//! output contains one line per detected heart beat, with its fiducial points, and, with the autocorrelation and
+//! spectral methods, per heart rate estimate.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    process,
+};
+
+use pulse_loop_core::{
+    clock::ManualClock,
+    pipeline::{HeartRateMethod, PerfusionIndexMethod, PipelineOutput, VitalSignsPipeline},
+    recording::{RecordedSample, Recording},
+    signal_processing::{
+        averaging::{Averaging, AveragingMethod},
+        hrv::SpectralHrvAnalyser,
+        spo2_calibration::{Spo2Calibration, WearSite},
+    },
+};
+use uom::si::{f32::Time, time::second};
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+
+    let mut heart_rate_method = HeartRateMethod::PeakDetection;
+    if let Some(index) = args.iter().position(|arg| arg == "--heart-rate-method") {
+        heart_rate_method = match args.get(index + 1).map(String::as_str) {
+            Some("peak") => HeartRateMethod::PeakDetection,
+            Some("autocorrelation") => HeartRateMethod::Autocorrelation,
+            Some("spectral") => HeartRateMethod::Spectral,
+            Some("fusion") => HeartRateMethod::Fusion,
+            _ => {
+                eprintln!("The heart rate method must be `peak`, `autocorrelation`, `spectral` or `fusion`.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    let mut hrv_window = Time::new::<second>(60.0);
+    if let Some(index) = args.iter().position(|arg| arg == "--hrv-window") {
+        hrv_window = match args.get(index + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+            Some(seconds) if seconds > 0.0 => Time::new::<second>(seconds),
+            _ => {
+                eprintln!("The HRV window must be a positive number of seconds.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    let mut spectral_hrv_window = Time::new::<second>(300.0);
+    if let Some(index) = args.iter().position(|arg| arg == "--spectral-hrv-window") {
+        let (min, max) = (
+            SpectralHrvAnalyser::MIN_WINDOW as f32 / 1000.0,
+            SpectralHrvAnalyser::MAX_WINDOW as f32 / 1000.0,
+        );
+        spectral_hrv_window = match args.get(index + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+            Some(seconds) if seconds >= min && seconds <= max => Time::new::<second>(seconds),
+            _ => {
+                eprintln!(
+                    "The spectral HRV window must be between {} and {} s.",
+                    min, max
+                );
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    let mut spectral_hrv_period = Time::new::<second>(30.0);
+    if let Some(index) = args.iter().position(|arg| arg == "--spectral-hrv-period") {
+        spectral_hrv_period = match args.get(index + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+            Some(seconds) if seconds > 0.0 => Time::new::<second>(seconds),
+            _ => {
+                eprintln!("The spectral HRV period must be a positive number of seconds.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    let mut perfusion_index_method = PerfusionIndexMethod::Rms;
+    if let Some(index) = args
+        .iter()
+        .position(|arg| arg == "--perfusion-index-method")
+    {
+        perfusion_index_method = match args.get(index + 1).map(String::as_str) {
+            Some("rms") => PerfusionIndexMethod::Rms,
+            Some("peak-to-peak") => PerfusionIndexMethod::PeakToPeak,
+            _ => {
+                eprintln!("The perfusion index method must be `rms` or `peak-to-peak`.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    let mut wear_site = WearSite::Wrist;
+    if let Some(index) = args.iter().position(|arg| arg == "--wear-site") {
+        wear_site = match args.get(index + 1).map(String::as_str) {
+            Some("wrist") => WearSite::Wrist,
+            Some("finger") => WearSite::Finger,
+            _ => {
+                eprintln!("The wear site must be `wrist` or `finger`.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+    // The recordings do not contain the hardware revision, the current one is assumed.
+    let spo2_calibration =
+        match Spo2Calibration::preset(Spo2Calibration::HARDWARE_REVISION, wear_site) {
+            Some(calibration) => calibration,
+            None => {
+                eprintln!("There is no SpO2 calibration for the {:?}.", wear_site);
+                process::exit(1);
+            }
+        };
+
+    let mut averaging = Averaging::default();
+    if let Some(index) = args.iter().position(|arg| arg == "--averaging-method") {
+        averaging.method = match args.get(index + 1).map(String::as_str) {
+            Some("mean") => AveragingMethod::Mean,
+            Some("median") => AveragingMethod::Median,
+            Some("exponential") => AveragingMethod::Exponential,
+            _ => {
+                eprintln!("The averaging method must be `mean`, `median` or `exponential`.");
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--averaging-window") {
+        averaging.window = match args.get(index + 1).and_then(|arg| arg.parse::<f32>().ok()) {
+            Some(seconds) if seconds > 0.0 && seconds <= Averaging::MAX_WINDOW => {
+                Time::new::<second>(seconds)
+            }
+            _ => {
+                eprintln!(
+                    "The averaging window must be a positive number of seconds, up to {} s.",
+                    Averaging::MAX_WINDOW
+                );
+                process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+
+    if args.len() != 4 {
+        eprintln!(
+            "Usage: {} [--heart-rate-method <peak|autocorrelation|spectral|fusion>] [--hrv-window <seconds>] [--spectral-hrv-window <seconds>] [--spectral-hrv-period <seconds>] [--perfusion-index-method <rms|peak-to-peak>] [--wear-site <wrist|finger>] [--averaging-method <mean|median|exponential>] [--averaging-window <seconds>] <recording> <samples output> <beats output>",
+            args[0]
+        );
+        process::exit(1);
+    }
+
+    if let Err(error) = run(
+        Path::new(&args[1]),
+        Path::new(&args[2]),
+        Path::new(&args[3]),
+        &Settings {
+            heart_rate_method,
+            hrv_window,
+            spectral_hrv_window,
+            spectral_hrv_period,
+            perfusion_index_method,
+            spo2_calibration,
+            averaging,
+        },
+    ) {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
+}
+
+/// The settings of the pipeline given on the command line.
+struct Settings {
+    heart_rate_method: HeartRateMethod,
+    hrv_window: Time,
+    spectral_hrv_window: Time,
+    spectral_hrv_period: Time,
+    perfusion_index_method: PerfusionIndexMethod,
+    spo2_calibration: Spo2Calibration,
+    averaging: Averaging,
+}
+
+fn run(
+    recording_path: &Path,
+    samples_path: &Path,
+    beats_path: &Path,
+    settings: &Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = BufReader::new(File::open(recording_path)?);
+    let recording = if recording_path.extension().is_some_and(|e| e == "bin") {
+        Recording::from_binary(file)?
+    } else {
+        Recording::from_csv(file)?
+    };
+
+    let mut samples = BufWriter::new(File::create(samples_path)?);
+    let mut beats = BufWriter::new(File::create(beats_path)?);
+    writeln!(
+        samples,
+        "timestamp_ms,measurement_state,wrist_presence,led1_dc,led1_ac,led2_dc,led2_ac,led3_dc,led3_ac,beat_threshold,heart_rate_bpm,heart_rate_confidence,spo2,spo2_confidence,r,red_pi,ir_pi,signal_quality,motion,channel_disagreement,dc_jump,ac_burst,ambient_variation,led1_heart_rate_bpm,led2_heart_rate_bpm,led3_heart_rate_bpm,led1_weight,led2_weight,led3_weight"
+    )?;
+    writeln!(
+        beats,
+        "timestamp_ms,onset_ms,peak_ms,foot_ms,amplitude,interval_ms,searched_back,rr_interval_ms,edited_fraction,mean_nn_ms,sdnn_ms,rmssd_ms,pnn50,vlf_ms2,lf_ms2,hf_ms2,lf_hf_ratio,irregular_rhythm,irregular_rhythm_confidence,respiration_rate_bpm,respiration_rate_reliable,heart_rate_bpm,heart_rate_confidence,spo2,spo2_confidence,r,red_pi,ir_pi,led1_ac_p2p,led2_ac_p2p,led3_ac_p2p,led1_pi_p2p,led2_pi_p2p,led3_pi_p2p,raw_heart_rate_bpm,raw_spo2,sqi,window_sqi,template_correlation,skewness,zero_crossings,clipped,motion"
+    )?;
+
+    // The pipeline time follows the recording timestamps, so the results do not depend on the replay speed.
+    let clock = ManualClock::new();
+    let mut pipeline = VitalSignsPipeline::new(recording.offset_currents, clock.clone());
+    pipeline.set_heart_rate_method(settings.heart_rate_method);
+    pipeline.set_hrv_window(settings.hrv_window);
+    pipeline.set_spectral_hrv_window(settings.spectral_hrv_window);
+    pipeline.set_spectral_hrv_period(settings.spectral_hrv_period);
+    pipeline.set_perfusion_index_method(settings.perfusion_index_method);
+    pipeline.set_spo2_calibration(settings.spo2_calibration);
+    pipeline.set_averaging(settings.averaging);
+    let mut previous_sample: Option<RecordedSample> = None;
+    let mut beat_count = 0;
+
+    for sample in &recording.samples {
+        clock.set(sample.timestamp);
+
+        // The firmware notifies the pipeline every time the calibration changes the frontend settings.
+        if let Some(previous_sample) = previous_sample {
+            if previous_sample.led_currents != sample.led_currents
+                || previous_sample.offset_currents != sample.offset_currents
+            {
+                pipeline.frontend_changed();
+            }
+        }
+        previous_sample = Some(*sample);
+
+        // LED2 and LED3 share the same offset current, the IR one is used as in the calibration.
+        pipeline.set_offset_currents(sample.offset_currents[0], sample.offset_currents[2]);
+        let output = pipeline.process(sample.raw_data);
+
+        write_sample(&mut samples, &output)?;
+        if output.beat.is_some() || output.heart_rate.is_some() {
+            let beat = output.beat.map_or(",,,,,".to_string(), |beat| {
+                beat_count += 1;
+                format!(
+                    "{},{},{},{},{},{}",
+                    beat.onset,
+                    beat.peak,
+                    beat.foot,
+                    beat.amplitude,
+                    beat.interval
+                        .map_or(String::new(), |interval| interval.to_string()),
+                    beat.searched_back
+                )
+            });
+            let hrv = output.hrv.map_or(",,,".to_string(), |hrv| {
+                format!("{},{},{},{}", hrv.mean_nn, hrv.sdnn, hrv.rmssd, hrv.pnn50)
+            });
+            let spectral_hrv = output.spectral_hrv.map_or(",,,".to_string(), |hrv| {
+                format!("{},{},{},{}", hrv.vlf, hrv.lf, hrv.hf, hrv.lf_hf_ratio)
+            });
+            let rhythm = output.rhythm.map_or(",".to_string(), |rhythm| {
+                format!("{},{}", rhythm.irregular, rhythm.confidence)
+            });
+            let respiration = output.respiration.map_or(",".to_string(), |respiration| {
+                format!("{},{}", respiration.respiration_rate, respiration.reliable)
+            });
+            let pulse_amplitudes = output.pulse_amplitudes.map_or(",,,,,".to_string(), |a| {
+                format!(
+                    "{},{},{},{},{},{}",
+                    a[0].ac,
+                    a[1].ac,
+                    a[2].ac,
+                    a[0].perfusion_index(),
+                    a[1].perfusion_index(),
+                    a[2].perfusion_index()
+                )
+            });
+            let quality = output.signal_quality.map_or(",,,,,".to_string(), |q| {
+                format!(
+                    "{},{},{},{},{},{}",
+                    q.sqi, q.window_sqi, q.correlation, q.skewness, q.zero_crossings, q.clipped
+                )
+            });
+            writeln!(
+                beats,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                output.timestamp,
+                beat,
+                output
+                    .rr_interval
+                    .map_or(String::new(), |interval| interval.to_string()),
+                optional(output.edited_fraction),
+                hrv,
+                spectral_hrv,
+                rhythm,
+                respiration,
+                optional(output.heart_rate),
+                optional(output.heart_rate_confidence),
+                optional(output.results.spo2),
+                output.results.spo2_confidence,
+                output.results.r,
+                output.results.red_pi,
+                output.results.ir_pi,
+                pulse_amplitudes,
+                optional(output.raw_heart_rate),
+                optional(output.spo2.and_then(|estimate| estimate.spo2)),
+                quality,
+                output.results.motion
+            )?;
+        }
+    }
+
+    samples.flush()?;
+    beats.flush()?;
+
+    println!(
+        "Replayed {} samples, detected {} beats.",
+        recording.samples.len(),
+        beat_count
+    );
+
+    Ok(())
+}
+
+fn write_sample<W: Write>(writer: &mut W, output: &PipelineOutput) -> std::io::Result<()> {
+    let filtered = output.filtered_data.map_or(",,,,,".to_string(), |f| {
+        format!(
+            "{},{},{},{},{},{}",
+            f.led1.0, f.led1.1, f.led2.0, f.led2.1, f.led3.0, f.led3.1
+        )
+    });
+    let motion = output.motion.map_or(",,,,".to_string(), |m| {
+        format!(
+            "{},{},{},{},{}",
+            m.moving, m.channel_disagreement, m.dc_jump, m.ac_burst, m.ambient_variation
+        )
+    });
+    let fusion = output.fused_heart_rate.map_or(",,,,,".to_string(), |f| {
+        format!(
+            "{},{},{},{},{},{}",
+            optional(f.channel_heart_rates[0]),
+            optional(f.channel_heart_rates[1]),
+            optional(f.channel_heart_rates[2]),
+            f.weights[0],
+            f.weights[1],
+            f.weights[2]
+        )
+    });
+    writeln!(
+        writer,
+        "{},{:?},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        output.timestamp,
+        output.state,
+        output.results.wrist_presence,
+        filtered,
+        output.beat_threshold,
+        optional(output.heart_rate),
+        optional(output.heart_rate_confidence),
+        optional(output.results.spo2),
+        output.results.spo2_confidence,
+        output.results.r,
+        output.results.red_pi,
+        output.results.ir_pi,
+        output.results.signal_quality,
+        motion,
+        fusion
+    )
+}
+
+/// Formats an optional value, leaving the CSV field empty if there is none.
+fn optional(value: Option<f32>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}